@@ -0,0 +1,330 @@
+#[cfg(test)]
+mod tests;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    building::BuildingType,
+    construction::{get_build_menu, Construction, ConstructionType},
+    items::recipe_name,
+    player::PlayerId,
+    AsteroidColoniesGame, Conveyor, Pos,
+};
+
+/// The prefix of the shareable string, which also tells the version of the format.
+const SHARE_PREFIX: &str = "ACB2.";
+/// The prefix of the strings before the conveyor tiers were recorded
+const SHARE_PREFIX_V1: &str = "ACB1.";
+/// The largest width or height of a blueprint, which keeps the positions in it far from
+/// overflowing.
+const MAX_SIZE: usize = 256;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlueprintBuilding {
+    /// The top-left corner relative to the blueprint
+    pub pos: Pos,
+    pub type_: BuildingType,
+    /// The name of the recipe of an assembler
+    pub recipe: Option<String>,
+}
+
+/// A layout of buildings, conveyors and power grids in a rectangle. It can be copied from an
+/// area, rotated or mirrored, shared as a string and placed elsewhere as construction plans.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Blueprint {
+    pub size: [usize; 2],
+    pub buildings: Vec<BlueprintBuilding>,
+    /// The positions, shapes and tiers of conveyors
+    pub conveyors: Vec<(Pos, Conveyor, u8)>,
+    pub power_grids: Vec<Pos>,
+}
+
+/// The format of [`SHARE_PREFIX_V1`], whose conveyors are all of tier 0.
+#[derive(Deserialize)]
+struct BlueprintV1 {
+    size: [usize; 2],
+    buildings: Vec<BlueprintBuilding>,
+    conveyors: Vec<(Pos, Conveyor)>,
+    power_grids: Vec<Pos>,
+}
+
+impl From<BlueprintV1> for Blueprint {
+    fn from(value: BlueprintV1) -> Self {
+        Self {
+            size: value.size,
+            buildings: value.buildings,
+            conveyors: value
+                .conveyors
+                .into_iter()
+                .map(|(pos, conv)| (pos, conv, 0))
+                .collect(),
+            power_grids: value.power_grids,
+        }
+    }
+}
+
+impl Blueprint {
+    pub fn is_empty(&self) -> bool {
+        self.buildings.is_empty() && self.conveyors.is_empty() && self.power_grids.is_empty()
+    }
+
+    /// Rotate 90 degrees clockwise. Buildings have no orientation, so non-square buildings
+    /// cannot be rotated.
+    pub fn rotate(&self) -> Result<Self, String> {
+        let height = self.size[1] as i32;
+        let rotate_pos = |[x, y]: Pos| [height - 1 - y, x];
+        let buildings = self
+            .buildings
+            .iter()
+            .map(|b| {
+                let [width, b_height] = b.type_.size();
+                if width != b_height {
+                    return Err(format!(
+                        "{} cannot be rotated because it is not square",
+                        b.type_
+                    ));
+                }
+                Ok(BlueprintBuilding {
+                    pos: [height - b.pos[1] - b_height as i32, b.pos[0]],
+                    ..b.clone()
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            size: [self.size[1], self.size[0]],
+            buildings,
+            conveyors: self
+                .conveyors
+                .iter()
+                .map(|(pos, conv, tier)| {
+                    (rotate_pos(*pos), conv.map_dirs(|d| d.rotate_cw()), *tier)
+                })
+                .collect(),
+            power_grids: self
+                .power_grids
+                .iter()
+                .map(|pos| rotate_pos(*pos))
+                .collect(),
+        })
+    }
+
+    /// Mirror horizontally.
+    pub fn mirror(&self) -> Self {
+        let width = self.size[0] as i32;
+        let mirror_pos = |[x, y]: Pos| [width - 1 - x, y];
+        Self {
+            size: self.size,
+            buildings: self
+                .buildings
+                .iter()
+                .map(|b| BlueprintBuilding {
+                    pos: [width - b.pos[0] - b.type_.size()[0] as i32, b.pos[1]],
+                    ..b.clone()
+                })
+                .collect(),
+            conveyors: self
+                .conveyors
+                .iter()
+                .map(|(pos, conv, tier)| (mirror_pos(*pos), conv.map_dirs(|d| d.mirror_x()), *tier))
+                .collect(),
+            power_grids: self
+                .power_grids
+                .iter()
+                .map(|pos| mirror_pos(*pos))
+                .collect(),
+        }
+    }
+
+    /// Encode into a compact string that can be copied and pasted by players.
+    pub fn to_share_string(&self) -> Result<String, String> {
+        let bytes = bincode::serialize(self).map_err(|e| format!("{e}"))?;
+        Ok(format!("{SHARE_PREFIX}{}", URL_SAFE_NO_PAD.encode(bytes)))
+    }
+
+    /// Decode a string made by [`Self::to_share_string`], or by an older version.
+    pub fn from_share_string(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let decode = |encoded: &str| {
+            URL_SAFE_NO_PAD
+                .decode(encoded)
+                .map_err(|e| format!("Invalid blueprint: {e}"))
+        };
+        let invalid = |e: bincode::Error| format!("Invalid blueprint: {e}");
+        let blueprint: Self = if let Some(encoded) = s.strip_prefix(SHARE_PREFIX) {
+            bincode::deserialize(&decode(encoded)?).map_err(invalid)?
+        } else if let Some(encoded) = s.strip_prefix(SHARE_PREFIX_V1) {
+            bincode::deserialize::<BlueprintV1>(&decode(encoded)?)
+                .map_err(invalid)?
+                .into()
+        } else {
+            return Err(String::from("The string is not a blueprint"));
+        };
+        blueprint.validate()?;
+        Ok(blueprint)
+    }
+
+    /// Check that the blueprint is not too large, everything fits in the rectangle and buildings
+    /// do not overlap, since a shared string can be edited.
+    fn validate(&self) -> Result<(), String> {
+        if MAX_SIZE < self.size[0] || MAX_SIZE < self.size[1] {
+            return Err(format!(
+                "Invalid blueprint: the size is larger than {MAX_SIZE}"
+            ));
+        }
+        let inside = |pos: Pos, size: [usize; 2]| {
+            0 <= pos[0]
+                && 0 <= pos[1]
+                && pos[0] as usize + size[0] <= self.size[0]
+                && pos[1] as usize + size[1] <= self.size[1]
+        };
+        let all_inside = self.buildings.iter().all(|b| inside(b.pos, b.type_.size()))
+            && self
+                .conveyors
+                .iter()
+                .all(|(pos, conv, _)| conv.is_some() && inside(*pos, [1; 2]))
+            && self.power_grids.iter().all(|pos| inside(*pos, [1; 2]));
+        if !all_inside {
+            return Err(String::from(
+                "Invalid blueprint: an element is out of bounds",
+            ));
+        }
+        for (i, b) in self.buildings.iter().enumerate() {
+            let [width, height] = b.type_.size();
+            let overlaps = self.buildings[..i].iter().any(|other| {
+                let [o_width, o_height] = other.type_.size();
+                other.pos[0] < b.pos[0] + width as i32
+                    && b.pos[0] < other.pos[0] + o_width as i32
+                    && other.pos[1] < b.pos[1] + height as i32
+                    && b.pos[1] < other.pos[1] + o_height as i32
+            });
+            if overlaps {
+                return Err(String::from("Invalid blueprint: buildings overlap"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AsteroidColoniesGame {
+    /// Capture buildings, conveyors and power grids in a rectangle into a blueprint. Buildings
+    /// sticking out of the rectangle are left out.
+    pub fn copy_blueprint(&self, pos: Pos, size: [usize; 2]) -> Result<Blueprint, String> {
+        if MAX_SIZE < size[0] || MAX_SIZE < size[1] {
+            return Err(format!("The area is larger than {MAX_SIZE} tiles"));
+        }
+        let contains = |b_pos: Pos, b_size: [usize; 2]| {
+            pos[0] <= b_pos[0]
+                && pos[1] <= b_pos[1]
+                && b_pos[0] + b_size[0] as i32 <= pos[0] + size[0] as i32
+                && b_pos[1] + b_size[1] as i32 <= pos[1] + size[1] as i32
+        };
+        let relative = |p: Pos| [p[0] - pos[0], p[1] - pos[1]];
+        let buildings = self
+            .buildings
+            .iter()
+            .filter(|b| contains(b.pos, b.type_.size()))
+            .map(|b| BlueprintBuilding {
+                pos: relative(b.pos),
+                type_: b.type_,
+                // Fixed recipes come with the building type
+                recipe: if b.type_.recipe().is_none() {
+                    b.recipe.as_ref().and_then(recipe_name)
+                } else {
+                    None
+                },
+            })
+            .collect();
+        let mut conveyors = vec![];
+        let mut power_grids = vec![];
+        for iy in pos[1]..pos[1] + size[1] as i32 {
+            for ix in pos[0]..pos[0] + size[0] as i32 {
+                let tile = &self.tiles[[ix, iy]];
+                if tile.conveyor.is_some() {
+                    conveyors.push((relative([ix, iy]), tile.conveyor, tile.conveyor_tier));
+                }
+                if tile.power_grid {
+                    power_grids.push(relative([ix, iy]));
+                }
+            }
+        }
+        let blueprint = Blueprint {
+            size,
+            buildings,
+            conveyors,
+            power_grids,
+        };
+        if blueprint.is_empty() {
+            return Err(String::from("There is nothing to copy in the area"));
+        }
+        Ok(blueprint)
+    }
+
+    /// Make construction plans to place a blueprint with its top-left corner at `pos`, after
+    /// checking that all of them can be built there. Conveyors and power grids that already
+    /// exist as in the blueprint are skipped, while conveyors of another tier are replaced.
+    pub fn blueprint_plan(
+        &self,
+        blueprint: &Blueprint,
+        pos: Pos,
+    ) -> Result<Vec<Construction>, String> {
+        let absolute = |p: Pos| [pos[0] + p[0], pos[1] + p[1]];
+        let planned = |tile_pos: Pos, f: &dyn Fn(ConstructionType) -> bool| {
+            self.constructions
+                .iter()
+                .find(|c| c.pos == tile_pos && f(c.get_type()))
+                .map(|c| (c.get_type(), c.conveyor_tier))
+        };
+
+        let mut plan = vec![];
+        for b in &blueprint.buildings {
+            let b_pos = absolute(b.pos);
+            self.check_building_site(b_pos, b.type_)?;
+            let item = get_build_menu()
+                .iter()
+                .find(|item| item.type_ == ConstructionType::Building(b.type_))
+                .ok_or_else(|| format!("{} cannot be built", b.type_))?;
+            plan.push(Construction::new(item, b_pos).with_building_recipe(b.recipe.clone()));
+        }
+        for (c_pos, conv, tier) in &blueprint.conveyors {
+            let c_pos = absolute(*c_pos);
+            self.check_tile_site(c_pos, "conveyor")?;
+            let tile = &self.tiles[c_pos];
+            let existing_plan = planned(c_pos, &|ty| matches!(ty, ConstructionType::Conveyor(_)));
+            if (tile.conveyor == *conv && tile.conveyor_tier == *tier)
+                || existing_plan == Some((ConstructionType::Conveyor(*conv), *tier))
+            {
+                continue;
+            }
+            if (tile.conveyor.is_some() && tile.conveyor != *conv) || existing_plan.is_some() {
+                return Err(format!(
+                    "Another conveyor is already installed at {c_pos:?}"
+                ));
+            }
+            plan.push(Construction::new_conveyor(c_pos, *conv, *tier, false));
+        }
+        for p_pos in &blueprint.power_grids {
+            let p_pos = absolute(*p_pos);
+            self.check_tile_site(p_pos, "power grid")?;
+            if self.tiles[p_pos].power_grid
+                || planned(p_pos, &|ty| matches!(ty, ConstructionType::PowerGrid)).is_some()
+            {
+                continue;
+            }
+            plan.push(Construction::new_power_grid(p_pos, false));
+        }
+        Ok(plan)
+    }
+
+    /// Place a blueprint as construction plans and return them.
+    pub fn place_blueprint(
+        &mut self,
+        blueprint: &Blueprint,
+        pos: Pos,
+        owner: Option<PlayerId>,
+    ) -> Result<Vec<Construction>, String> {
+        let plan = self.blueprint_plan(blueprint, pos)?;
+        self.build_plan(&plan, owner);
+        Ok(plan)
+    }
+}
@@ -0,0 +1,183 @@
+use super::*;
+use crate::direction::Direction;
+
+fn sample() -> Blueprint {
+    Blueprint {
+        size: [3, 2],
+        buildings: vec![BlueprintBuilding {
+            pos: [0, 0],
+            type_: BuildingType::Assembler,
+            recipe: Some("Gear".to_string()),
+        }],
+        conveyors: vec![
+            ([2, 0], Conveyor::One(Direction::Up, Direction::Down), 1),
+            ([2, 1], Conveyor::Splitter(Direction::Up), 0),
+        ],
+        power_grids: vec![[2, 1]],
+    }
+}
+
+fn assembler_pos(game: &AsteroidColoniesGame) -> Pos {
+    game.iter_building()
+        .find(|b| b.type_ == BuildingType::Assembler)
+        .unwrap()
+        .pos
+}
+
+#[test]
+fn test_transforms() {
+    let blueprint = sample();
+    let rotated = blueprint.rotate().unwrap();
+    assert_eq!(rotated.size, [2, 3]);
+    assert_eq!(rotated.buildings[0].pos, [0, 0]);
+    assert_eq!(
+        rotated.conveyors,
+        vec![
+            ([1, 2], Conveyor::One(Direction::Right, Direction::Left), 1),
+            ([0, 2], Conveyor::Splitter(Direction::Right), 0),
+        ]
+    );
+    assert_eq!(rotated.power_grids, vec![[0, 2]]);
+
+    let mut full_turn = blueprint.clone();
+    for _ in 0..4 {
+        full_turn = full_turn.rotate().unwrap();
+    }
+    assert_eq!(full_turn, blueprint);
+
+    let mirrored = blueprint.mirror();
+    assert_eq!(mirrored.buildings[0].pos, [1, 0]);
+    assert_eq!(
+        mirrored.conveyors[1],
+        ([0, 1], Conveyor::Splitter(Direction::Up), 0)
+    );
+    assert_eq!(mirrored.mirror(), blueprint);
+}
+
+#[test]
+fn test_share_string() {
+    let blueprint = sample();
+    let s = blueprint.to_share_string().unwrap();
+    assert!(s.starts_with(SHARE_PREFIX));
+    assert_eq!(Blueprint::from_share_string(&s).unwrap(), blueprint);
+
+    assert_eq!(
+        Blueprint::from_share_string("hello").unwrap_err(),
+        "The string is not a blueprint"
+    );
+    let mut out_of_bounds = blueprint.clone();
+    out_of_bounds.power_grids.push([3, 0]);
+    assert_eq!(
+        Blueprint::from_share_string(&out_of_bounds.to_share_string().unwrap()).unwrap_err(),
+        "Invalid blueprint: an element is out of bounds"
+    );
+    let mut overlapping = blueprint.clone();
+    overlapping.size = [4, 3];
+    overlapping.buildings.push(BlueprintBuilding {
+        pos: [1, 1],
+        type_: BuildingType::Storage,
+        recipe: None,
+    });
+    assert_eq!(
+        Blueprint::from_share_string(&overlapping.to_share_string().unwrap()).unwrap_err(),
+        "Invalid blueprint: buildings overlap"
+    );
+    let mut huge = blueprint;
+    huge.size = [usize::MAX, 2];
+    assert_eq!(
+        Blueprint::from_share_string(&huge.to_share_string().unwrap()).unwrap_err(),
+        "Invalid blueprint: the size is larger than 256"
+    );
+}
+
+#[test]
+fn test_share_string_v1() {
+    let blueprint = sample();
+    let v1 = (
+        blueprint.size,
+        blueprint.buildings.clone(),
+        blueprint
+            .conveyors
+            .iter()
+            .map(|(pos, conv, _)| (*pos, *conv))
+            .collect::<Vec<_>>(),
+        blueprint.power_grids.clone(),
+    );
+    let s = format!(
+        "{SHARE_PREFIX_V1}{}",
+        URL_SAFE_NO_PAD.encode(bincode::serialize(&v1).unwrap())
+    );
+    let decoded = Blueprint::from_share_string(&s).unwrap();
+    assert_eq!(decoded.buildings, blueprint.buildings);
+    assert!(decoded.conveyors.iter().all(|(_, _, tier)| *tier == 0));
+}
+
+#[test]
+fn test_copy_and_place() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let [x, y] = assembler_pos(&game);
+    game.set_recipe(x, y, Some("Gear")).unwrap();
+    let blueprint = game.copy_blueprint([x, y], [2, 2]).unwrap();
+    assert_eq!(
+        blueprint.buildings,
+        vec![BlueprintBuilding {
+            pos: [0, 0],
+            type_: BuildingType::Assembler,
+            recipe: Some("Gear".to_string()),
+        }]
+    );
+    assert!(game.copy_blueprint([0, 0], [2, 2]).is_err());
+
+    assert_eq!(
+        game.place_blueprint(&blueprint, [x, y], None).unwrap_err(),
+        "Assembler is not researched yet"
+    );
+    game.research.complete("Automation");
+    assert!(game
+        .place_blueprint(&blueprint, [x, y], None)
+        .unwrap_err()
+        .starts_with("The destination is already occupied by a building"));
+
+    // Build the copy where the original was
+    let id = game
+        .buildings
+        .items()
+        .find(|(_, b)| b.type_ == BuildingType::Assembler)
+        .unwrap()
+        .0;
+    game.buildings.remove(id);
+    let plan = game.place_blueprint(&blueprint, [x, y], None).unwrap();
+    // Conveyors and power grids under the assembler are left as they are
+    assert_eq!(plan.len(), 1);
+    for construction in game.constructions.iter_mut() {
+        construction.progress = construction.recipe.time;
+    }
+    game.tick().unwrap();
+    let building = game.iter_building().find(|b| b.pos == [x, y]).unwrap();
+    assert_eq!(building.type_, BuildingType::Assembler);
+    assert_eq!(
+        building.recipe.as_ref().and_then(recipe_name).as_deref(),
+        Some("Gear")
+    );
+}
+
+#[test]
+fn test_conveyor_tier() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let conv = Conveyor::One(Direction::Left, Direction::Right);
+    game.tiles[[26, 50]].conveyor = conv;
+    game.tiles[[26, 50]].conveyor_tier = 1;
+    let blueprint = game.copy_blueprint([26, 50], [1, 1]).unwrap();
+    assert_eq!(blueprint.conveyors, vec![([0, 0], conv, 1)]);
+
+    // The same conveyor of another tier is replaced
+    game.tiles[[26, 50]].conveyor_tier = 0;
+    let plan = game.blueprint_plan(&blueprint, [26, 50]).unwrap();
+    assert_eq!(plan.len(), 1);
+    assert_eq!(plan[0].conveyor_tier, 1);
+    game.tiles[[26, 50]].conveyor_tier = 1;
+    assert!(game
+        .blueprint_plan(&blueprint, [26, 50])
+        .unwrap()
+        .is_empty());
+}
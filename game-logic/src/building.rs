@@ -1,7 +1,9 @@
 mod crew_cabin;
+#[cfg(test)]
+mod tests;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashSet},
     fmt::Display,
 };
 
@@ -11,21 +13,27 @@ use self::crew_cabin::Envs;
 
 use crate::{
     construction::Construction,
-    crew::expected_crew_pickup_any,
     entity::{EntityId, EntitySet},
+    filter::ItemSet,
     inventory::Inventory,
     items::ItemType,
-    measure_time,
+    life_support::{CrewNeeds, SUPPLY_STOCK},
+    logistics::Logistics,
+    player::PlayerId,
+    power::PowerNetworks,
     push_pull::{pull_inputs, pull_ores, push_outputs},
+    research::{research_project, Research},
+    ruleset::ruleset,
+    skills::{CabinSkills, Job},
     task::{BuildingTask, GlobalTask, RAW_ORE_SMELT_TIME},
     tile::Tiles,
     transport::TransportId,
-    AsteroidColoniesGame, Crew, Direction, Pos, TileState, Transport, Xor128,
+    AsteroidColoniesGame, Crew, Direction, Pos, Transport, Xor128,
 };
 
 pub type BuildingId = EntityId<Building>;
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
 #[non_exhaustive]
 pub enum BuildingType {
     Power,
@@ -36,55 +44,59 @@ pub enum BuildingType {
     CrewCabin,
     Assembler,
     Furnace,
+    Laboratory,
+    OxygenGenerator,
+    Greenhouse,
+    LargeCrewCabin,
 }
 
 impl BuildingType {
+    pub(crate) fn all() -> [Self; 12] {
+        [
+            Self::Power,
+            Self::Battery,
+            Self::Excavator,
+            Self::Storage,
+            Self::MediumStorage,
+            Self::CrewCabin,
+            Self::Assembler,
+            Self::Furnace,
+            Self::Laboratory,
+            Self::OxygenGenerator,
+            Self::Greenhouse,
+            Self::LargeCrewCabin,
+        ]
+    }
+
     pub fn capacity(&self) -> usize {
-        match self {
-            Self::Power => 5,
-            Self::Battery => 0,
-            Self::Excavator => 10,
-            Self::Storage => 20,
-            Self::MediumStorage => 100,
-            Self::CrewCabin => 20,
-            Self::Assembler => 40,
-            Self::Furnace => 30,
-        }
+        ruleset().building(*self).capacity
     }
 
     pub fn size(&self) -> [usize; 2] {
-        match self {
-            Self::MediumStorage | Self::CrewCabin | Self::Assembler | Self::Furnace => [2, 2],
-            _ => [1, 1],
-        }
+        ruleset().building(*self).size
     }
 
+    /// The number of crews that can live in the building
     pub fn max_crews(&self) -> usize {
-        match self {
-            Self::CrewCabin => 4,
-            _ => 0,
-        }
+        ruleset().building(*self).max_crews
+    }
+
+    pub fn is_cabin(&self) -> bool {
+        0 < self.max_crews()
     }
 
     /// Return the amount of base generating/consuming power
     pub fn power_gen(&self) -> isize {
-        match self {
-            Self::Power => 250,
-            Self::Battery => 0,
-            Self::CrewCabin => -100,
-            Self::Excavator => -10,
-            Self::Storage => 0,
-            Self::MediumStorage => 0,
-            Self::Assembler => -20,
-            Self::Furnace => -10,
-        }
+        ruleset().building(*self).power_gen
     }
 
     pub fn energy_capacity(&self) -> Option<usize> {
-        match self {
-            Self::Battery => Some(10000),
-            _ => None,
-        }
+        ruleset().building(*self).energy_capacity
+    }
+
+    /// The fixed recipe of the building, if it has one
+    pub fn recipe(&self) -> Option<&'static Recipe> {
+        ruleset().building(*self).recipe.as_ref()
     }
 
     pub fn is_storage(&self) -> bool {
@@ -108,14 +120,18 @@ impl Display for BuildingType {
             Self::CrewCabin => write!(f, "CrewCabin"),
             Self::Assembler => write!(f, "Assembler"),
             Self::Furnace => write!(f, "Furnace"),
+            Self::Laboratory => write!(f, "Laboratory"),
+            Self::OxygenGenerator => write!(f, "OxygenGenerator"),
+            Self::Greenhouse => write!(f, "Greenhouse"),
+            Self::LargeCrewCabin => write!(f, "LargeCrewCabin"),
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Recipe {
-    pub inputs: HashMap<ItemType, usize>,
-    pub outputs: HashMap<ItemType, usize>,
+    pub inputs: BTreeMap<ItemType, usize>,
+    pub outputs: BTreeMap<ItemType, usize>,
     pub time: f64,
 }
 
@@ -127,6 +143,8 @@ pub struct Building {
     pub inventory: Inventory,
     /// The number of crews attending this building.
     pub crews: usize,
+    /// The number of crews living in this building, including the ones out of it.
+    pub population: usize,
     // TODO: We want to avoid copies of recipes, but deserializing a recipe with static is
     // extremely hard with serde.
     pub recipe: Option<Recipe>,
@@ -135,6 +153,16 @@ pub struct Building {
     /// Some buildings can store energy, like capacitors and batteries.
     pub energy: Option<usize>,
     pub ore_accum: OreAccum,
+    /// The player who built this building. Anyone can operate a building without an owner.
+    pub owner: Option<PlayerId>,
+    /// Life support of the crews living in this building
+    pub needs: Option<CrewNeeds>,
+    /// Job priorities and experience of the crews living in this building
+    pub skills: Option<CabinSkills>,
+    /// The items to push out, if restricted. Items not in the filter stay in the inventory.
+    pub output_filter: Option<ItemSet>,
+    /// Whether this storage provides or requests items, and the target counts of them.
+    pub logistics: Option<Logistics>,
     #[serde(skip)]
     /// A cache of expected transports
     pub expected_transports: HashSet<TransportId>,
@@ -147,11 +175,17 @@ impl Building {
             type_,
             task: BuildingTask::None,
             inventory: Inventory::new(),
-            crews: type_.max_crews(),
-            recipe: None,
+            crews: 0,
+            population: 0,
+            recipe: type_.recipe().cloned(),
             direction: None,
             energy: type_.energy_capacity(),
             ore_accum: OreAccum::default(),
+            owner: None,
+            needs: type_.is_cabin().then(CrewNeeds::new),
+            skills: type_.is_cabin().then(CabinSkills::default),
+            output_filter: None,
+            logistics: None,
             expected_transports: HashSet::new(),
         }
     }
@@ -162,21 +196,36 @@ impl Building {
             type_,
             task: BuildingTask::None,
             inventory,
-            crews: type_.max_crews(),
-            recipe: None,
+            crews: 0,
+            population: 0,
+            recipe: type_.recipe().cloned(),
             direction: None,
             energy: type_.energy_capacity(),
             ore_accum: OreAccum::default(),
+            owner: None,
+            needs: type_.is_cabin().then(CrewNeeds::new),
+            skills: type_.is_cabin().then(CabinSkills::default),
+            output_filter: None,
+            logistics: None,
             expected_transports: HashSet::new(),
         }
     }
 
+    /// Move in crews to a cabin.
+    pub fn with_crews(mut self, crews: usize) -> Self {
+        self.crews = crews;
+        self.population = crews;
+        self
+    }
+
     /// Generating power. Prioritized to be used.
     pub fn power_gen(&self) -> isize {
         let base = self.type_.power_gen();
         let task_power = match self.task {
             BuildingTask::Excavate(_, _) => 200,
-            BuildingTask::Assemble { .. } => 300,
+            // Buildings with a fixed recipe consume the base power only
+            BuildingTask::Assemble { .. } if self.type_.recipe().is_none() => 300,
+            BuildingTask::Research { .. } => 100,
             _ => 0,
         };
         base - task_power
@@ -240,6 +289,7 @@ impl Building {
         constructions: &mut EntitySet<Construction>,
         crews: &mut EntitySet<Crew>,
         gtasks: &EntitySet<GlobalTask>,
+        research: &Research,
         _rng: &mut Xor128,
     ) -> Result<(), String> {
         // Try pushing out products
@@ -267,23 +317,8 @@ impl Building {
                     &mut this.inventory,
                     bldgs,
                 );
-                for (ty, recipe_count) in &recipe.inputs {
-                    let actual_count = this.inventory.get(&ty);
-                    if actual_count < *recipe_count {
-                        // crate::console_log!(
-                        //     "An ingredient {:?} is missing for recipe {:?}",
-                        //     ty,
-                        //     recipe.outputs
-                        // );
-                        return Ok(());
-                    }
-                }
-                for (ty, recipe_count) in &recipe.inputs {
-                    if let Some(entry) = this.inventory.get_mut(&ty) {
-                        if *recipe_count <= *entry {
-                            *entry = entry.saturating_sub(*recipe_count);
-                        }
-                    }
+                if !take_inputs(&mut this.inventory, &recipe.inputs) {
+                    return Ok(());
                 }
                 this.task = BuildingTask::Assemble {
                     t: recipe.time,
@@ -303,77 +338,71 @@ impl Building {
                     true,
                 );
             }
-            BuildingType::CrewCabin => {
-                if this.crews == 0 {
-                    return Ok(());
-                }
-                for (gt_id, gtask) in gtasks.items() {
-                    let goal_pos = match &*gtask {
-                        GlobalTask::Excavate(t, goal_pos) => {
-                            if *t <= 0. {
-                                continue;
-                            }
-                            if bldgs.iter().any(|b| matches!(b.task, BuildingTask::Excavate(_, other_id) if other_id == gt_id)) {
-                                continue;
-                            }
-                            goal_pos
-                        }
-                        GlobalTask::Cleanup(pos) => {
-                            let pickups = expected_crew_pickup_any(crews, *pos);
-                            if pickups != 0 {
-                                continue;
-                            }
-                            pos
-                        }
-                    };
-                    if crews
-                        .iter()
-                        .any(|crew| crew.gt_id() == Some(gt_id) || crew.target() == Some(*goal_pos))
-                    {
-                        continue;
-                    }
-                    if let Some(crew) = Crew::new_task(id, this, gt_id, &*gtask, tiles) {
-                        crews.insert(crew);
-                        this.crews -= 1;
-                        return Ok(());
+            BuildingType::CrewCabin | BuildingType::LargeCrewCabin => {
+                let mut stock: BTreeMap<_, _> = SUPPLY_STOCK.into_iter().collect();
+                // Immigrants come if there are vacant rooms and the items for them
+                let immigration = ruleset().immigration.as_ref().filter(|_| {
+                    matches!(this.task, BuildingTask::None)
+                        && this.population < this.type_.max_crews()
+                });
+                if let Some(immigration) = immigration {
+                    for (item, count) in &immigration.ingredients {
+                        *stock.entry(*item).or_default() += *count;
                     }
                 }
-                fn print_time<R>(name: &str, f: impl FnOnce() -> R) -> R {
-                    let (r, t) = measure_time(f);
-                    if 0.001 < t {
-                        println!("{name} time: {}", t);
+                pull_inputs(
+                    &stock,
+                    tiles,
+                    transports,
+                    &mut this.expected_transports,
+                    this.pos,
+                    this.type_.size(),
+                    &mut this.inventory,
+                    bldgs,
+                );
+                if let Some(immigration) = immigration {
+                    if take_inputs(&mut this.inventory, &immigration.ingredients) {
+                        this.task = BuildingTask::Immigrate {
+                            t: immigration.time,
+                            max_t: immigration.time,
+                        };
                     }
-                    r
                 }
-                for construction in constructions.iter() {
-                    let pos = construction.pos;
-                    if !matches!(tiles[pos].state, TileState::Empty) {
-                        // Don't bother trying to find a path in an unreachable area.
+                if this.crews == 0 {
+                    return Ok(());
+                }
+                let skills = this.skills.clone().unwrap_or_default();
+                let mut members: Vec<_> = this
+                    .members_at_home(id, crews)
+                    .into_iter()
+                    .map(Some)
+                    .collect();
+                if members.is_empty() {
+                    members.push(None);
+                }
+                // Crews with the same priorities would find the same job, so try them once
+                let mut tried = vec![];
+                for member in members {
+                    let priorities = Job::all().map(|job| skills.priority(member, job));
+                    if tried.contains(&priorities) {
                         continue;
                     }
-                    let envs = Envs {
-                        buildings: bldgs,
-                        transports,
-                        crews,
-                        tiles,
-                    };
-                    let crew = print_time("try_find_deliver", || {
-                        this.try_find_deliver(id, &*construction, &envs)
-                    })
-                    .or_else(|| {
-                        print_time("try_find_pickup_and_deliver", || {
-                            this.try_find_pickup_and_deliver(id, &*construction, &envs)
-                        })
-                    })
-                    .or_else(|| {
-                        print_time("try_send_to_build", || {
-                            this.try_send_to_build(id, &*construction, &envs)
-                        })
-                    });
-                    if let Some(crew) = crew {
-                        crews.insert(crew);
-                        this.crews -= 1;
-                        return Ok(());
+                    tried.push(priorities);
+                    for level in skills.priority_levels(member) {
+                        let enabled = |job| skills.priority(member, job) == level;
+                        let envs = Envs {
+                            buildings: bldgs,
+                            transports,
+                            crews,
+                            tiles,
+                            constructions,
+                            global_tasks: gtasks,
+                        };
+                        if let Some(crew) = this.try_dispatch(id, &envs, enabled) {
+                            crews.insert(crew.with_member(member));
+                            this.crews -= 1;
+                            return Ok(());
+                        }
                     }
                 }
             }
@@ -411,31 +440,67 @@ impl Building {
                     };
                 }
             }
+            BuildingType::Laboratory => {
+                if !matches!(this.task, BuildingTask::None) {
+                    return Ok(());
+                }
+                let Some(project) = research.current_project() else {
+                    return Ok(());
+                };
+                // Only one laboratory works on a project, since the others would be wasted
+                if bldgs.iter().any(|b| {
+                    matches!(&b.task, BuildingTask::Research { project: other, .. } if *other == project.name)
+                }) {
+                    return Ok(());
+                }
+                pull_inputs(
+                    &project.inputs,
+                    tiles,
+                    transports,
+                    &mut this.expected_transports,
+                    this.pos,
+                    this.type_.size(),
+                    &mut this.inventory,
+                    bldgs,
+                );
+                if !take_inputs(&mut this.inventory, &project.inputs) {
+                    return Ok(());
+                }
+                this.task = BuildingTask::Research {
+                    t: project.time,
+                    max_t: project.time,
+                    project: project.name.clone(),
+                };
+            }
             _ => {}
         }
         Ok(())
     }
 }
 
+/// Take the inputs from the inventory if all of them are available.
+fn take_inputs(inventory: &mut Inventory, inputs: &BTreeMap<ItemType, usize>) -> bool {
+    for (ty, count) in inputs {
+        if inventory.get(ty) < *count {
+            // crate::console_log!("An ingredient {:?} is missing", ty);
+            return false;
+        }
+    }
+    for (ty, count) in inputs {
+        if let Some(entry) = inventory.get_mut(ty) {
+            *entry -= *count;
+        }
+    }
+    true
+}
+
 impl AsteroidColoniesGame {
     pub(super) fn process_buildings(&mut self) {
-        let (chargeable, dischargeable, power_gen, power_demand) = self
-            .buildings
-            .iter()
-            .map(|b| (b.power_charge(), b.power_discharge(), b.power_gen()))
-            .fold((0, 0, 0, 0), |acc, (charge, discharge, gen)| {
-                (
-                    acc.0 + charge,
-                    acc.1 + discharge,
-                    acc.2 + gen.max(0).abs(),
-                    acc.3 + gen.min(0).abs(),
-                )
-            });
-        // let power_load = (power_demand as f64 / power_gen as f64).min(1.);
-        let power_ratio = ((dischargeable as f64 + power_gen as f64) / power_demand as f64).min(1.);
+        let power_networks = PowerNetworks::new(&self.tiles, &self.buildings);
         // A buffer to avoid borrow checker
         let mut moving_items = vec![];
         for (id, mut b) in self.buildings.items_borrow_mut() {
+            let idle = matches!(b.task, BuildingTask::None);
             let res = b.tick(
                 id,
                 &self.buildings,
@@ -444,58 +509,73 @@ impl AsteroidColoniesGame {
                 &mut self.constructions,
                 &mut self.crews,
                 &self.global_tasks,
+                &self.research,
                 &mut self.rng,
             );
             if let Err(e) = res {
                 crate::console_log!("Building::tick error: {}", e);
             };
+            // The inputs are taken from the inventory when the recipe or the research starts
+            let inputs = match (&b.task, &b.recipe) {
+                _ if !idle => None,
+                (BuildingTask::Assemble { .. }, Some(recipe)) => Some(&recipe.inputs),
+                (BuildingTask::Research { project, .. }, _) => {
+                    research_project(project).map(|project| &project.inputs)
+                }
+                (BuildingTask::Immigrate { .. }, _) => ruleset()
+                    .immigration
+                    .as_ref()
+                    .map(|immigration| &immigration.ingredients),
+                _ => None,
+            };
+            for (item, count) in inputs.into_iter().flatten() {
+                self.production_stats.consume(Some(b.type_), *item, *count);
+            }
         }
-        for mut building in self.buildings.iter_borrow_mut() {
+        for (id, mut building) in self.buildings.items_borrow_mut() {
+            let before = matches!(
+                building.task,
+                BuildingTask::Assemble { .. } | BuildingTask::Smelt { .. }
+            )
+            .then(|| building.inventory.countable().clone());
+            // Upgrades make buildings work faster with the same power
+            let speed = self.research.speed(building.type_);
             if let Some((item, dest)) = Self::process_task(
                 &mut self.tiles,
                 &mut *building,
                 &self.buildings,
                 &mut self.global_tasks,
-                power_ratio,
+                &mut self.research,
+                power_networks.power_ratio(id) * speed,
                 &mut self.rng,
                 self.calculate_back_image.as_mut(),
             ) {
                 moving_items.push((item, dest));
             }
-        }
-
-        let charging_total = power_gen - power_demand;
-
-        if charging_total < 0 {
-            if 0 < dischargeable {
-                let drain_total = -charging_total;
-                // Drain energy from capacitors proportional to the capacity
-                for building in self.buildings.iter_mut() {
-                    let cap = building.power_discharge();
-                    let Some(ref mut energy) = building.energy else {
-                        continue;
-                    };
-                    let drain = drain_total * cap / dischargeable;
-                    *energy = (*energy as isize - drain).max(0) as usize;
+            if let Some(before) = before {
+                for (item, count) in building.inventory.countable() {
+                    let prev = before.get(item).copied().unwrap_or(0);
+                    if prev < *count {
+                        self.production_stats
+                            .produce(building.type_, *item, *count - prev);
+                    }
                 }
             }
-        } else if 0 < chargeable {
-            for building in self.buildings.iter_mut() {
-                let max_charge = building.power_charge();
-                let Some(max_energy) = building.type_.energy_capacity() else {
-                    continue;
-                };
-                let Some(ref mut energy) = building.energy else {
-                    continue;
-                };
-                let charge = charging_total * max_charge / chargeable;
-                *energy = (*energy as isize + charge).clamp(0, max_energy as isize) as usize;
-            }
         }
 
-        self.power_ratio = power_ratio;
-        self.used_power = power_ratio * power_demand as f64;
-        // println!("charge: {chargeable}, discharge: {dischargeable}, power_gen: {power_gen}, power_demand: {power_demand}, ratio = {power_ratio}, used: {}", self.used_power);
+        power_networks.update_batteries(&mut self.buildings);
+        let (generated, used) =
+            power_networks
+                .iter()
+                .fold((0., 0.), |(generated, used), (_, network)| {
+                    (
+                        generated + network.power_gen as f64,
+                        used + network.used_power,
+                    )
+                });
+        let energy = self.buildings.iter().filter_map(|b| b.energy).sum();
+        self.production_stats.record_power(generated, used, energy);
+        self.power_networks = power_networks;
 
         for (item, item_pos) in moving_items {
             let found = self.buildings.iter_mut().find(|b| b.pos == item_pos);
@@ -1,18 +1,121 @@
+use std::collections::HashSet;
+
 use crate::{
-    construction::Construction, entity::EntitySet, push_pull::HasInventory,
-    transport::find_multipath, Crew, TileState, Tiles, Transport,
+    construction::{scheduled_constructions, Construction},
+    crew::expected_crew_pickup_any,
+    entity::EntitySet,
+    measure_time,
+    push_pull::{rect_iter, HasInventory},
+    skills::Job,
+    task::GlobalTask,
+    transport::find_multipath,
+    Crew, TileState, Tiles, Transport,
 };
 
-use super::{Building, BuildingId};
+use super::{Building, BuildingId, BuildingTask};
 
 pub(super) struct Envs<'a> {
     pub buildings: &'a EntitySet<Building>,
     pub transports: &'a EntitySet<Transport>,
     pub crews: &'a EntitySet<Crew>,
     pub tiles: &'a Tiles,
+    pub constructions: &'a EntitySet<Construction>,
+    pub global_tasks: &'a EntitySet<GlobalTask>,
 }
 
 impl Building {
+    /// Find a job for a crew among the enabled ones, first global tasks and then constructions.
+    pub(super) fn try_dispatch(
+        &mut self,
+        id: BuildingId,
+        envs: &Envs,
+        enabled: impl Fn(Job) -> bool,
+    ) -> Option<Crew> {
+        for (gt_id, gtask) in envs.global_tasks.items() {
+            let goal_pos = match &*gtask {
+                GlobalTask::Excavate(t, goal_pos, _) => {
+                    if !enabled(Job::Excavate) || *t <= 0. {
+                        continue;
+                    }
+                    if envs.buildings.iter().any(|b| matches!(b.task, BuildingTask::Excavate(_, other_id) if other_id == gt_id)) {
+                        continue;
+                    }
+                    goal_pos
+                }
+                GlobalTask::Cleanup(pos, _) => {
+                    if !enabled(Job::Cleanup) {
+                        continue;
+                    }
+                    let pickups = expected_crew_pickup_any(envs.crews, *pos);
+                    if pickups != 0 {
+                        continue;
+                    }
+                    pos
+                }
+            };
+            if envs
+                .crews
+                .iter()
+                .any(|crew| crew.gt_id() == Some(gt_id) || crew.target() == Some(*goal_pos))
+            {
+                continue;
+            }
+            if let Some(crew) = Crew::new_task(id, self, gt_id, &gtask, envs.tiles) {
+                return Some(crew);
+            }
+        }
+        fn print_time<R>(name: &str, f: impl FnOnce() -> R) -> R {
+            let (r, t) = measure_time(f);
+            if 0.001 < t {
+                println!("{name} time: {}", t);
+            }
+            r
+        }
+        let haul = enabled(Job::Haul);
+        let build = enabled(Job::Build);
+        if !haul && !build {
+            return None;
+        }
+        for construction in scheduled_constructions(envs.constructions)
+            .into_iter()
+            .filter_map(|id| envs.constructions.get(id))
+        {
+            let pos = construction.pos;
+            if !matches!(envs.tiles[pos].state, TileState::Empty) {
+                // Don't bother trying to find a path in an unreachable area.
+                continue;
+            }
+            let crew = haul
+                .then(|| {
+                    print_time("try_find_deliver", || {
+                        self.try_find_deliver(id, &construction, envs)
+                    })
+                    .or_else(|| {
+                        print_time("try_find_pickup_and_deliver", || {
+                            self.try_find_pickup_and_deliver(id, &construction, envs)
+                        })
+                    })
+                })
+                .flatten()
+                .or_else(|| {
+                    build
+                        .then(|| {
+                            print_time("try_send_to_build", || {
+                                self.try_send_to_build(id, &construction, envs)
+                            })
+                        })
+                        .flatten()
+                });
+            if crew.is_some() {
+                return crew;
+            }
+        }
+        if haul {
+            return print_time("try_find_logistics", || self.try_find_logistics(id, envs));
+        }
+        None
+    }
+
     pub(super) fn try_find_deliver(
         &mut self,
         from_id: BuildingId,
@@ -91,6 +194,43 @@ impl Building {
         })
     }
 
+    /// Find an item that a requester storage needs and a provider storage to pick it up from.
+    pub(super) fn try_find_logistics(&mut self, from_id: BuildingId, envs: &Envs) -> Option<Crew> {
+        for requester in envs.buildings.iter() {
+            let Some(logistics) = &requester.logistics else {
+                continue;
+            };
+            for item in logistics.targets.keys() {
+                if requester.logistics_demand(*item, envs.transports, envs.crews) == 0 {
+                    continue;
+                }
+                let targets: HashSet<_> = envs
+                    .buildings
+                    .iter()
+                    .filter(|o| 0 < o.logistics_supply(*item, envs.crews))
+                    .flat_map(|o| rect_iter(o.pos, o.type_.size()))
+                    .collect();
+                if targets.is_empty() {
+                    continue;
+                }
+                let path_to_source = find_multipath(
+                    [self.pos].into_iter(),
+                    |pos| targets.contains(&pos),
+                    |_, pos| matches!(envs.tiles[pos].state, TileState::Empty),
+                );
+                let crew = path_to_source
+                    .and_then(|src| src.first().copied())
+                    .and_then(|src| {
+                        Crew::new_pickup(from_id, self.pos, src, requester.pos, *item, envs.tiles)
+                    });
+                if crew.is_some() {
+                    return crew;
+                }
+            }
+        }
+        None
+    }
+
     pub(super) fn try_send_to_build(
         &mut self,
         from_id: BuildingId,
@@ -0,0 +1,64 @@
+use super::*;
+use crate::btree_map;
+
+#[test]
+fn test_immigration() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    // Remove the existing cabin, so that it does not share the supplies
+    let (id, _) = game
+        .buildings
+        .items()
+        .find(|(_, b)| b.type_ == BuildingType::CrewCabin)
+        .unwrap();
+    game.buildings.remove(id);
+    // Turn the assembler into an empty cabin, since it is powered
+    let cabin = game
+        .buildings
+        .iter_mut()
+        .find(|b| b.type_ == BuildingType::Assembler)
+        .unwrap();
+    let pos = cabin.pos;
+    *cabin = Building::new_inventory(
+        pos,
+        BuildingType::CrewCabin,
+        btree_map!(ItemType::Oxygen => 4, ItemType::Food => 3).into(),
+    );
+    assert_eq!(cabin.population, 0);
+
+    let cabin = |game: &AsteroidColoniesGame| {
+        game.iter_building()
+            .find(|b| b.pos == pos)
+            .unwrap()
+            .population
+    };
+    for _ in 0..2000 {
+        game.tick().unwrap();
+        if cabin(&game) == 1 {
+            break;
+        }
+    }
+    assert_eq!(cabin(&game), 1);
+    let food = game
+        .iter_building()
+        .find(|b| b.pos == pos)
+        .unwrap()
+        .inventory
+        .get(&ItemType::Food);
+    assert_eq!(food, 0);
+
+    // No more immigrants without the items
+    for _ in 0..2000 {
+        game.tick().unwrap();
+    }
+    assert_eq!(cabin(&game), 1);
+}
+
+#[test]
+fn test_cabin_sizes() {
+    assert_eq!(BuildingType::CrewCabin.max_crews(), 4);
+    assert_eq!(BuildingType::LargeCrewCabin.max_crews(), 8);
+    assert!(BuildingType::LargeCrewCabin.is_cabin());
+    assert!(!BuildingType::Storage.is_cabin());
+    let cabin = Building::new([0, 0], BuildingType::LargeCrewCabin).with_crews(8);
+    assert_eq!((cabin.crews, cabin.population), (8, 8));
+}
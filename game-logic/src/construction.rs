@@ -1,25 +1,33 @@
-use std::{
-    collections::{HashMap, HashSet},
-    sync::OnceLock,
-};
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, HashSet};
 
 use crate::{
     building::{Building, BuildingType},
     crew::{expected_crew_deliveries, Crew},
-    direction::Direction,
-    entity::EntitySet,
+    entity::{EntityId, EntitySet},
     inventory::{CountableInventory, Inventory},
-    items::ItemType,
-    push_pull::{pull_inputs, push_outputs, HasInventory},
-    task::{BUILD_CONVEYOR_TIME, BUILD_POWER_GRID_TIME},
-    transport::{expected_deliveries, Transport, TransportId},
-    Conveyor, Pos,
+    item_pile::drop_items,
+    items::{find_recipe, ItemType},
+    player::PlayerId,
+    push_pull::{pull_inputs, push_outputs, rect_iter, HasInventory},
+    ruleset::ruleset,
+    transport::{expected_deliveries, find_multipath, Transport, TransportId},
+    Conveyor, Pos, TileState,
 };
 
-use super::{hash_map, AsteroidColoniesGame};
+use super::AsteroidColoniesGame;
 
 use serde::{Deserialize, Serialize};
 
+pub type ConstructionId = EntityId<Construction>;
+
+/// The priority of a newly planned construction.
+pub const DEFAULT_CONSTRUCTION_PRIORITY: u8 = 2;
+/// The highest priority a construction can have.
+pub const MAX_CONSTRUCTION_PRIORITY: u8 = 4;
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum ConstructionType {
     PowerGrid,
@@ -36,18 +44,23 @@ pub struct Construction {
     pub recipe: BuildMenuItem,
     canceling: bool,
     pub progress: f64,
+    /// The player who planned this construction. The building inherits the owner.
+    pub owner: Option<PlayerId>,
+    /// Constructions with higher priority get ingredients and crews first.
+    pub priority: u8,
+    /// A paused construction gets neither ingredients nor crews until resumed.
+    pub paused: bool,
+    /// The name of the recipe set to the building when it is completed, e.g. from a blueprint.
+    pub building_recipe: Option<String>,
+    /// The tier of the conveyor to build
+    pub conveyor_tier: u8,
     #[serde(skip)]
     /// A cache of expected transports
     expected_transports: HashSet<TransportId>,
 }
 
 impl Construction {
-    fn new_ex(
-        type_: ConstructionType,
-        item: &'static BuildMenuItem,
-        pos: Pos,
-        canceling: bool,
-    ) -> Self {
+    fn new_ex(type_: ConstructionType, item: &BuildMenuItem, pos: Pos, canceling: bool) -> Self {
         Self {
             type_,
             pos,
@@ -59,58 +72,42 @@ impl Construction {
             recipe: (*item).clone(),
             canceling,
             progress: if canceling { item.time } else { 0. },
+            owner: None,
+            priority: DEFAULT_CONSTRUCTION_PRIORITY,
+            paused: false,
+            building_recipe: None,
+            conveyor_tier: 0,
             expected_transports: HashSet::new(),
         }
     }
 
-    pub fn new(item: &'static BuildMenuItem, pos: Pos) -> Self {
+    pub fn new(item: &BuildMenuItem, pos: Pos) -> Self {
         Self::new_ex(item.type_, item, pos, false)
     }
 
     pub fn new_power_grid(pos: Pos, canceling: bool) -> Self {
-        static BUILD: OnceLock<BuildMenuItem> = OnceLock::new();
-        let recipe = &*BUILD.get_or_init(|| BuildMenuItem {
-            type_: ConstructionType::PowerGrid,
-            ingredients: hash_map!(ItemType::PowerGridComponent => 1),
-            time: BUILD_POWER_GRID_TIME,
-        });
-        Self::new_ex(ConstructionType::PowerGrid, recipe, pos, canceling)
+        let type_ = ConstructionType::PowerGrid;
+        let recipe = ruleset().power_grid.to_build_menu_item(type_);
+        Self::new_ex(type_, &recipe, pos, canceling)
     }
 
-    fn build_recipe() -> &'static BuildMenuItem {
-        static BUILD: OnceLock<BuildMenuItem> = OnceLock::new();
-        &*BUILD.get_or_init(|| BuildMenuItem {
-            type_: ConstructionType::Conveyor(Conveyor::One(Direction::Left, Direction::Right)),
-            ingredients: hash_map!(ItemType::ConveyorComponent => 1),
-            time: BUILD_CONVEYOR_TIME,
-        })
-    }
-
-    fn splitter_recipe() -> &'static BuildMenuItem {
-        static BUILD_SPLITTER: OnceLock<BuildMenuItem> = OnceLock::new();
-        &*BUILD_SPLITTER.get_or_init(|| BuildMenuItem {
-            type_: ConstructionType::Conveyor(Conveyor::One(Direction::Left, Direction::Right)),
-            ingredients: hash_map!(ItemType::ConveyorComponent => 1, ItemType::Circuit => 1, ItemType::Gear => 1),
-            time: BUILD_CONVEYOR_TIME,
-        })
-    }
-
-    pub fn new_conveyor(pos: Pos, conv: Conveyor, canceling: bool) -> Self {
-        if matches!(conv, Conveyor::Splitter(_) | Conveyor::Merger(_)) {
-            Self::new_ex(
-                ConstructionType::Conveyor(conv),
-                Self::splitter_recipe(),
-                pos,
-                canceling,
-            )
-        } else {
-            Self::new_ex(
-                ConstructionType::Conveyor(conv),
-                Self::build_recipe(),
-                pos,
-                canceling,
-            )
-        }
+    /// A conveyor of the given tier. Only straight and crossing conveyors can be upgraded, so
+    /// the tier of the others is always 0.
+    pub fn new_conveyor(pos: Pos, conv: Conveyor, tier: u8, canceling: bool) -> Self {
+        let ruleset = ruleset();
+        let (cost, tier) = match conv {
+            Conveyor::Splitter(_) | Conveyor::Merger(_) => (&ruleset.splitter, 0),
+            Conveyor::UndergroundIn(_) | Conveyor::UndergroundOut(_) => (&ruleset.underground, 0),
+            _ => tier
+                .checked_sub(1)
+                .and_then(|i| ruleset.conveyor_tiers.get(i as usize))
+                .map(|stats| (&stats.cost, tier))
+                .unwrap_or((&ruleset.conveyor, 0)),
+        };
+        let type_ = ConstructionType::Conveyor(conv);
+        let mut ret = Self::new_ex(type_, &cost.to_build_menu_item(type_), pos, canceling);
+        ret.conveyor_tier = tier;
+        ret
     }
 
     pub fn new_deconstruct(
@@ -132,10 +129,25 @@ impl Construction {
             recipe: recipe.clone(),
             canceling: true,
             progress: recipe.time,
+            owner: None,
+            priority: DEFAULT_CONSTRUCTION_PRIORITY,
+            paused: false,
+            building_recipe: None,
+            conveyor_tier: 0,
             expected_transports: HashSet::new(),
         })
     }
 
+    pub fn with_owner(mut self, owner: Option<PlayerId>) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    pub fn with_building_recipe(mut self, recipe: Option<String>) -> Self {
+        self.building_recipe = recipe;
+        self
+    }
+
     pub fn get_type(&self) -> ConstructionType {
         self.type_
     }
@@ -255,54 +267,84 @@ impl HasInventory for Construction {
     }
 }
 
+/// Ids of the constructions that are not paused, from the highest priority.
+/// Constructions with the same priority keep the order in the set.
+pub(crate) fn scheduled_constructions(
+    constructions: &EntitySet<Construction>,
+) -> Vec<ConstructionId> {
+    let mut ids: Vec<_> = constructions
+        .items()
+        .filter(|(_, c)| !c.paused)
+        .map(|(id, c)| (id, c.priority))
+        .collect();
+    ids.sort_by_key(|(_, priority)| std::cmp::Reverse(*priority));
+    ids.into_iter().map(|(id, _)| id).collect()
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct BuildMenuItem {
     pub type_: ConstructionType,
-    pub ingredients: HashMap<ItemType, usize>,
+    pub ingredients: BTreeMap<ItemType, usize>,
     pub time: f64,
 }
 
 pub fn get_build_menu() -> &'static [BuildMenuItem] {
-    static BUILD_MENU: OnceLock<Vec<BuildMenuItem>> = OnceLock::new();
-    &*BUILD_MENU.get_or_init(|| {
-        vec![
-            BuildMenuItem {
-                type_: ConstructionType::Building(BuildingType::Battery),
-                ingredients: hash_map!(ItemType::Battery => 2, ItemType::IronIngot => 1),
-                time: 120.,
-            },
-            BuildMenuItem {
-                type_: ConstructionType::Building(BuildingType::Storage),
-                ingredients: hash_map!(ItemType::IronIngot => 1, ItemType::Cilicate => 5),
-                time: 100.,
-            },
-            BuildMenuItem {
-                type_: ConstructionType::Building(BuildingType::Excavator),
-                ingredients: hash_map!(ItemType::IronIngot => 3, ItemType::Gear => 2, ItemType::Circuit => 2),
-                time: 200.,
-            },
-            BuildMenuItem {
-                type_: ConstructionType::Building(BuildingType::MediumStorage),
-                ingredients: hash_map!(ItemType::IronIngot => 2,  ItemType::Gear => 2, ItemType::Cilicate => 10),
-                time: 200.,
-            },
-            BuildMenuItem {
-                type_: ConstructionType::Building(BuildingType::Furnace),
-                ingredients: hash_map!(ItemType::IronIngot => 2, ItemType::Wire => 1, ItemType::Cilicate => 6),
-                time: 300.,
-            },
-            BuildMenuItem {
-                type_: ConstructionType::Building(BuildingType::Assembler),
-                ingredients: hash_map!(ItemType::AssemblerComponent => 4),
-                time: 300.,
-            },
-        ]
-    })
+    &ruleset().build_menu
 }
 
 impl AsteroidColoniesGame {
+    pub fn set_construction_priority(&mut self, pos: Pos, priority: u8) -> Result<(), String> {
+        if MAX_CONSTRUCTION_PRIORITY < priority {
+            return Err(format!(
+                "Priority must be at most {MAX_CONSTRUCTION_PRIORITY}"
+            ));
+        }
+        let construction = self
+            .constructions
+            .iter_mut()
+            .find(|c| c.intersects_rect(pos, [1, 1]))
+            .ok_or_else(|| String::from("Construction not found at given position"))?;
+        construction.priority = priority;
+        Ok(())
+    }
+
+    pub fn pause_construction(&mut self, pos: Pos, paused: bool) -> Result<(), String> {
+        let construction = self
+            .constructions
+            .iter_mut()
+            .find(|c| c.intersects_rect(pos, [1, 1]))
+            .ok_or_else(|| String::from("Construction not found at given position"))?;
+        construction.paused = paused;
+        Ok(())
+    }
+
     pub(super) fn process_constructions(&mut self) {
+        // Pull ingredients in priority order, so that the scarce items go to the most
+        // important constructions first.
+        for id in scheduled_constructions(&self.constructions) {
+            let Some(construction) = self.constructions.get_mut(id) else {
+                continue;
+            };
+            if construction.canceling {
+                continue;
+            }
+            let size = construction.size();
+            pull_inputs(
+                &construction.recipe.ingredients,
+                &self.tiles,
+                &mut self.transports,
+                &mut construction.expected_transports,
+                construction.pos,
+                size,
+                &mut construction.ingredients,
+                &self.buildings,
+            );
+        }
+
         self.constructions.retain(|construction| {
+            if construction.paused {
+                return true;
+            }
             if construction.canceling {
                 if construction.ingredients.is_empty() {
                     return false;
@@ -316,28 +358,56 @@ impl AsteroidColoniesGame {
                         false,
                     );
                     crate::console_log!("Pushed out after: {:?}", construction.ingredients);
+                    // Neither conveyors nor crews can take the items to a storage with space
+                    let vacant: HashSet<Pos> = self
+                        .buildings
+                        .iter()
+                        .filter(|b| b.type_.is_storage() && b.inventory_size() < b.type_.capacity())
+                        .flat_map(|b| rect_iter(b.pos, b.type_.size()))
+                        .collect();
+                    let reachable = !vacant.is_empty()
+                        && find_multipath(
+                            [construction.pos].into_iter(),
+                            |pos| vacant.contains(&pos),
+                            |_, pos| {
+                                matches!(self.tiles[pos].state, TileState::Empty)
+                                    || vacant.contains(&pos)
+                            },
+                        )
+                        .is_some();
+                    if !reachable {
+                        drop_items(
+                            &mut self.item_piles,
+                            construction.pos,
+                            &construction.ingredients,
+                        );
+                        return false;
+                    }
                 }
             } else {
-                let size = construction.size();
-                pull_inputs(
-                    &construction.recipe.ingredients,
-                    &self.tiles,
-                    &mut self.transports,
-                    &mut construction.expected_transports,
-                    construction.pos,
-                    size,
-                    &mut construction.ingredients,
-                    &self.buildings,
-                );
                 // TODO: should we always use the same amount of time to deconstruct as construction?
                 // Some buildings should be easier to deconstruct than construct.
                 if construction.progress < construction.recipe.time {
                     return true;
                 }
+                for (item, count) in construction.ingredients.iter() {
+                    self.production_stats.consume(None, *item, *count);
+                }
                 let pos = construction.pos;
                 match construction.type_ {
                     ConstructionType::Building(ty) => {
-                        self.buildings.insert(Building::new(pos, ty));
+                        let mut building = Building::new(pos, ty);
+                        building.owner = construction.owner;
+                        if let Some(recipe) = construction
+                            .building_recipe
+                            .as_deref()
+                            .and_then(find_recipe)
+                            .filter(|recipe| self.research.is_recipe_unlocked(recipe))
+                        {
+                            // Only assemblers accept recipes, so ignore the error for others.
+                            let _ = building.set_recipe(Some(recipe));
+                        }
+                        self.buildings.insert(building);
                     }
                     ConstructionType::PowerGrid => {
                         if let Some(tile) = self.tiles.try_get_mut(pos) {
@@ -347,7 +417,10 @@ impl AsteroidColoniesGame {
                     ConstructionType::Conveyor(conv) => {
                         if let Some(tile) = self.tiles.try_get_mut(pos) {
                             tile.conveyor = conv;
+                            tile.conveyor_tier = construction.conveyor_tier;
+                            tile.filter = tile.filter.filter(|filter| filter.fits(&conv));
                         }
+                        self.changed_tiles.insert(pos);
                     }
                 }
                 return false;
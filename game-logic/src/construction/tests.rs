@@ -0,0 +1,131 @@
+use super::*;
+use crate::{building::BuildingType, direction::Direction, tile::TileState};
+
+const CONVEYOR: Conveyor = Conveyor::One(Direction::Left, Direction::Right);
+
+fn construction_at(game: &AsteroidColoniesGame, pos: Pos) -> (u8, bool, f64, bool) {
+    let c = game.iter_construction().find(|c| c.pos == pos).unwrap();
+    (c.priority, c.paused, c.progress(), c.ingredients.is_empty())
+}
+
+#[test]
+fn test_scheduled_order() {
+    let mut constructions = EntitySet::new();
+    let low = constructions.insert(Construction::new_power_grid([0, 0], false));
+    let default = constructions.insert(Construction::new_power_grid([1, 0], false));
+    let mut paused = Construction::new_power_grid([2, 0], false);
+    paused.priority = MAX_CONSTRUCTION_PRIORITY;
+    paused.paused = true;
+    constructions.insert(paused);
+    let mut high = Construction::new_power_grid([3, 0], false);
+    high.priority = MAX_CONSTRUCTION_PRIORITY;
+    let high = constructions.insert(high);
+    let default2 = constructions.insert(Construction::new_power_grid([4, 0], false));
+    constructions.get_mut(low).unwrap().priority = 0;
+
+    assert_eq!(
+        scheduled_constructions(&constructions),
+        vec![high, default, default2, low]
+    );
+}
+
+#[test]
+fn test_set_priority() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.build_plan(
+        &[Construction::new_conveyor([26, 50], CONVEYOR, 0, false)],
+        None,
+    );
+    assert_eq!(
+        construction_at(&game, [26, 50]).0,
+        DEFAULT_CONSTRUCTION_PRIORITY
+    );
+    assert_eq!(
+        game.set_construction_priority([26, 50], MAX_CONSTRUCTION_PRIORITY + 1)
+            .unwrap_err(),
+        "Priority must be at most 4"
+    );
+    assert_eq!(
+        game.set_construction_priority([0, 0], 1).unwrap_err(),
+        "Construction not found at given position"
+    );
+    game.set_construction_priority([26, 50], MAX_CONSTRUCTION_PRIORITY)
+        .unwrap();
+    assert_eq!(
+        construction_at(&game, [26, 50]).0,
+        MAX_CONSTRUCTION_PRIORITY
+    );
+}
+
+#[test]
+fn test_pause() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.build_plan(
+        &[Construction::new_conveyor([26, 50], CONVEYOR, 0, false)],
+        None,
+    );
+    game.pause_construction([26, 50], true).unwrap();
+    for _ in 0..200 {
+        game.tick().unwrap();
+    }
+    assert_eq!(
+        construction_at(&game, [26, 50]),
+        (DEFAULT_CONSTRUCTION_PRIORITY, true, 0., true)
+    );
+    assert_eq!(game.iter_crew().count(), 0);
+
+    game.pause_construction([26, 50], false).unwrap();
+    for _ in 0..2000 {
+        game.tick().unwrap();
+        if game.iter_construction().all(|c| c.pos != [26, 50]) {
+            break;
+        }
+    }
+    assert!(game.iter_construction().all(|c| c.pos != [26, 50]));
+    assert_eq!(game.tiles()[[26, 50]].conveyor, CONVEYOR);
+}
+
+#[test]
+fn test_build_plan_rebuilt() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.tiles[[27, 50]].state = TileState::Space;
+    let mut forged = Construction::new_conveyor([26, 50], CONVEYOR, 0, false);
+    forged.progress = forged.recipe.time;
+    forged.ingredients.insert(ItemType::Gear, 100);
+    forged.owner = Some(PlayerId(1));
+    game.build_plan(
+        &[
+            forged,
+            Construction::new_conveyor([26, 50], CONVEYOR, 0, false),
+            Construction::new_conveyor([27, 50], CONVEYOR, 0, false),
+        ],
+        None,
+    );
+    assert_eq!(game.iter_construction().count(), 1);
+    assert_eq!(
+        construction_at(&game, [26, 50]),
+        (DEFAULT_CONSTRUCTION_PRIORITY, false, 0., true)
+    );
+    assert_eq!(game.iter_construction().next().unwrap().owner, None);
+}
+
+#[test]
+fn test_priority_of_building_plan() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let item = get_build_menu()
+        .iter()
+        .find(|item| item.type_ == ConstructionType::Building(BuildingType::Storage))
+        .unwrap();
+    let pos = [26, 50];
+    game.constructions.insert(Construction::new(item, pos));
+    // Any tile in the footprint points to the plan, but not the ones next to it
+    let size = BuildingType::Storage.size();
+    let corner = [pos[0] + size[0] as i32 - 1, pos[1] + size[1] as i32 - 1];
+    game.set_construction_priority(corner, 0).unwrap();
+    game.pause_construction(corner, true).unwrap();
+    assert_eq!(construction_at(&game, pos).0, 0);
+    assert!(construction_at(&game, pos).1);
+    let outside = [pos[0] + size[0] as i32, pos[1]];
+    assert!(game.set_construction_priority(outside, 1).is_err());
+    assert!(game.pause_construction(outside, false).is_err());
+}
@@ -1,8 +1,11 @@
+#[cfg(test)]
+mod tests;
+
 use std::{cmp::Ordering, hash::Hash};
 
 use crate::{
     console_log, construction::Construction, direction::Direction, push_pull::TileSampler,
-    AsteroidColoniesGame, Tile,
+    ruleset::ruleset, AsteroidColoniesGame, Pos, Tile,
 };
 use serde::{Deserialize, Serialize};
 
@@ -16,8 +19,16 @@ pub enum Conveyor {
     Splitter(Direction),
     /// Assume a splitter merges from the other 3 directions
     Merger(Direction),
+    /// The entrance of an underground conveyor, which takes items moving in the direction
+    /// and carries them to the nearest exit facing the same direction.
+    UndergroundIn(Direction),
+    /// The exit of an underground conveyor, which outputs items in the direction.
+    UndergroundOut(Direction),
 }
 
+/// The maximum number of tiles that an underground conveyor can pass under.
+pub const MAX_UNDERGROUND_SPAN: i32 = 6;
+
 impl Hash for Conveyor {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         // It's annoying to define hash logic for all cases, but we need to do it
@@ -45,6 +56,14 @@ impl Hash for Conveyor {
                 4u8.hash(state);
                 a.hash(state);
             }
+            Self::UndergroundIn(a) => {
+                5u8.hash(state);
+                a.hash(state);
+            }
+            Self::UndergroundOut(a) => {
+                6u8.hash(state);
+                a.hash(state);
+            }
         }
     }
 }
@@ -66,6 +85,22 @@ impl Conveyor {
             Self::Two((from, _), _) => Some(*from),
             Self::Splitter(from) => Some(*from),
             Self::Merger(to) => Some(to.reverse()),
+            Self::UndergroundIn(to) | Self::UndergroundOut(to) => Some(to.reverse()),
+        }
+    }
+
+    /// Apply a transformation to all the directions of the conveyor.
+    pub(crate) fn map_dirs(&self, f: impl Fn(Direction) -> Direction) -> Self {
+        match *self {
+            Self::None => Self::None,
+            Self::One(from, to) => Self::One(f(from), f(to)),
+            Self::Two((from0, to0), (from1, to1)) => {
+                Self::Two((f(from0), f(to0)), (f(from1), f(to1)))
+            }
+            Self::Splitter(from) => Self::Splitter(f(from)),
+            Self::Merger(to) => Self::Merger(f(to)),
+            Self::UndergroundIn(to) => Self::UndergroundIn(f(to)),
+            Self::UndergroundOut(to) => Self::UndergroundOut(f(to)),
         }
     }
 
@@ -76,6 +111,7 @@ impl Conveyor {
             Self::Two((_, to), _) => Some(*to),
             Self::Splitter(from) => Some(from.reverse()),
             Self::Merger(to) => Some(*to),
+            Self::UndergroundIn(to) | Self::UndergroundOut(to) => Some(*to),
         }
     }
 
@@ -86,6 +122,9 @@ impl Conveyor {
             Self::Two((from1, _), (from2, _)) => from1 == dir || from2 == dir,
             Self::Splitter(from) => from == dir,
             Self::Merger(to) => to != dir,
+            Self::UndergroundIn(to) => to.reverse() == dir,
+            // Items only come from the tunnel
+            Self::UndergroundOut(_) => false,
         }
     }
 
@@ -96,6 +135,9 @@ impl Conveyor {
             Self::Two((_, to1), (_, to2)) => to1 == dir || to2 == dir,
             Self::Splitter(from) => from != dir,
             Self::Merger(to) => to == dir,
+            // Items only go into the tunnel
+            Self::UndergroundIn(_) => false,
+            Self::UndergroundOut(to) => to == dir,
         }
     }
 
@@ -109,6 +151,8 @@ impl Conveyor {
             }
             Self::Splitter(from) => from == dir,
             Self::Merger(to) => to == dir,
+            Self::UndergroundIn(to) => to.reverse() == dir,
+            Self::UndergroundOut(to) => to == dir,
         }
     }
 
@@ -116,10 +160,31 @@ impl Conveyor {
     pub fn has_two(&self) -> bool {
         matches!(self, Self::Two(_, _))
     }
+
+    pub fn is_underground(&self) -> bool {
+        matches!(self, Self::UndergroundIn(_) | Self::UndergroundOut(_))
+    }
+}
+
+/// Find the exit of the underground conveyor whose entrance is at `pos`, and return the
+/// distance to it. Tiles in between can be anything, even solid rock.
+pub(crate) fn find_underground_exit(tiles: &impl TileSampler, pos: Pos) -> Option<i32> {
+    let Conveyor::UndergroundIn(dir) = tiles.at(pos)?.conveyor else {
+        return None;
+    };
+    let dir_vec = dir.to_vec();
+    (1..=MAX_UNDERGROUND_SPAN + 1).find(|dist| {
+        let exit_pos = [pos[0] + dir_vec[0] * dist, pos[1] + dir_vec[1] * dist];
+        tiles
+            .at(exit_pos)
+            .is_some_and(|tile| tile.conveyor == Conveyor::UndergroundOut(dir))
+    })
 }
 
 impl AsteroidColoniesGame {
-    /// Preview or stage conveyor build plan.
+    /// Preview or stage conveyor build plan. If `underground` is true, an underground
+    /// conveyor is planned with the entrance at the start and the exit at the end, which have to
+    /// be on a straight line.
     pub fn preview_build_conveyor(
         &mut self,
         ix0: i32,
@@ -127,6 +192,7 @@ impl AsteroidColoniesGame {
         ix1: i32,
         iy1: i32,
         preview: bool,
+        underground: bool,
     ) -> Result<(), String> {
         use {Conveyor::*, Direction::*};
         let x_rev = ix1.cmp(&ix0);
@@ -134,6 +200,25 @@ impl AsteroidColoniesGame {
 
         self.conveyor_preview.clear();
 
+        if underground {
+            let dir = Direction::from_vec([ix1 - ix0, iy1 - iy0])
+                .filter(|_| ix0 == ix1 || iy0 == iy1)
+                .ok_or_else(|| String::from("Underground conveyor must be in a straight line"))?;
+            let dist = (ix1 - ix0).abs() + (iy1 - iy0).abs();
+            if MAX_UNDERGROUND_SPAN + 1 < dist {
+                return Err(format!(
+                    "Underground conveyor can pass under at most {MAX_UNDERGROUND_SPAN} tiles"
+                ));
+            }
+            self.conveyor_preview.insert([ix0, iy0], UndergroundIn(dir));
+            self.conveyor_preview
+                .insert([ix1, iy1], UndergroundOut(dir));
+            if !preview {
+                self.conveyor_staged.extend(self.conveyor_preview.drain());
+            }
+            return Ok(());
+        }
+
         let mut prev_from = Option::None;
 
         let pos = [ix0, iy0];
@@ -257,6 +342,15 @@ impl AsteroidColoniesGame {
         self.conveyor_staged.insert([ix0, iy0], filtered);
     }
 
+    /// Set the tier of the conveyors to build, which applies to all the staged conveyors.
+    pub fn set_conveyor_tier(&mut self, tier: u8) -> Result<(), String> {
+        if ruleset().conveyor_tiers.len() < tier as usize {
+            return Err(format!("Conveyor tier {tier} does not exist"));
+        }
+        self.conveyor_tier = tier;
+        Ok(())
+    }
+
     pub fn cancel_build_conveyor(&mut self, preview: bool) {
         if !preview {
             self.conveyor_staged.clear();
@@ -265,14 +359,19 @@ impl AsteroidColoniesGame {
     }
 
     pub fn commit_build_conveyor(&mut self) -> Vec<Construction> {
-        for (pos, conv) in self.conveyor_staged.iter() {
-            self.constructions
-                .insert(Construction::new_conveyor(*pos, *conv, false));
+        let plan = self.take_build_conveyor();
+        for construction in &plan {
+            self.constructions.insert(construction.clone());
         }
+        plan
+    }
+
+    /// Clear the staged conveyors and return them as a build plan without applying it.
+    pub fn take_build_conveyor(&mut self) -> Vec<Construction> {
         self.conveyor_preview.clear();
         std::mem::take(&mut self.conveyor_staged)
             .into_iter()
-            .map(|(pos, conv)| Construction::new_conveyor(pos, conv, false))
+            .map(|(pos, conv)| Construction::new_conveyor(pos, conv, self.conveyor_tier, false))
             .collect()
     }
 }
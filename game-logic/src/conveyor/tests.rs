@@ -0,0 +1,67 @@
+use super::*;
+use crate::{construction::ConstructionType, ItemType};
+
+#[test]
+fn test_preview_underground() {
+    use Direction::*;
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    assert_eq!(
+        game.preview_build_conveyor(10, 10, 12, 12, true, true)
+            .unwrap_err(),
+        "Underground conveyor must be in a straight line"
+    );
+    assert_eq!(
+        game.preview_build_conveyor(10, 10, 10, 10, true, true)
+            .unwrap_err(),
+        "Underground conveyor must be in a straight line"
+    );
+    assert_eq!(
+        game.preview_build_conveyor(10, 10, 10, 18, true, true)
+            .unwrap_err(),
+        "Underground conveyor can pass under at most 6 tiles"
+    );
+
+    game.preview_build_conveyor(10, 17, 10, 10, false, true)
+        .unwrap();
+    let mut plan: Vec<_> = game.iter_conveyor_plan().map(|(p, c)| (*p, *c)).collect();
+    plan.sort_by_key(|(pos, _)| pos[1]);
+    assert_eq!(
+        plan,
+        vec![
+            ([10, 10], Conveyor::UndergroundOut(Up)),
+            ([10, 17], Conveyor::UndergroundIn(Up))
+        ]
+    );
+
+    let plan = game.take_build_conveyor();
+    assert_eq!(plan.len(), 2);
+    for construction in &plan {
+        assert!(matches!(
+            construction.get_type(),
+            ConstructionType::Conveyor(conv) if conv.is_underground()
+        ));
+        assert_eq!(
+            construction
+                .recipe
+                .ingredients
+                .get(&ItemType::ConveyorComponent),
+            Some(&2)
+        );
+    }
+}
+
+#[test]
+fn test_find_underground_exit() {
+    use Direction::*;
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.tiles[[10, 10]].conveyor = Conveyor::UndergroundIn(Right);
+    game.tiles[[10 + MAX_UNDERGROUND_SPAN + 2, 10]].conveyor = Conveyor::UndergroundOut(Right);
+    assert_eq!(find_underground_exit(&game.tiles, [10, 10]), None);
+
+    // The nearest exit facing the same direction is paired
+    game.tiles[[14, 10]].conveyor = Conveyor::UndergroundOut(Left);
+    game.tiles[[15, 10]].conveyor = Conveyor::UndergroundOut(Right);
+    game.tiles[[16, 10]].conveyor = Conveyor::UndergroundOut(Right);
+    assert_eq!(find_underground_exit(&game.tiles, [10, 10]), Some(5));
+    assert_eq!(find_underground_exit(&game.tiles, [14, 10]), None);
+}
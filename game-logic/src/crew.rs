@@ -2,12 +2,14 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 use crate::{
-    building::{Building, BuildingId},
+    building::{Building, BuildingId, OreAccum},
     console_log,
-    construction::Construction,
+    construction::{scheduled_constructions, Construction},
     entity::EntitySet,
     inventory::Inventory,
+    item_pile::ItemPile,
     items::ItemType,
+    skills::{CabinSkills, Job},
     task::{GlobalTask, GlobalTaskId, EXCAVATE_ORE_AMOUNT, LABOR_EXCAVATE_TIME},
     transport::{find_path, Transport, TransportPayload},
     AsteroidColoniesGame, Pos, Tile, TileState, Tiles,
@@ -38,6 +40,8 @@ pub struct Crew {
     pub pos: Pos,
     pub path: Option<Vec<Pos>>,
     pub from: BuildingId,
+    /// The index of this crew among the ones living in the cabin, which keeps its skills
+    pub member: Option<usize>,
     task: CrewTask,
     inventory: Inventory,
 }
@@ -51,8 +55,8 @@ impl Crew {
         tiles: &Tiles,
     ) -> Option<Self> {
         let (target, task) = match gtask {
-            GlobalTask::Excavate(_, pos) => (*pos, CrewTask::Excavate(gt_id)),
-            GlobalTask::Cleanup(spos) => (
+            GlobalTask::Excavate(_, pos, _) => (*pos, CrewTask::Excavate(gt_id)),
+            GlobalTask::Cleanup(spos, _) => (
                 *spos,
                 CrewTask::Pickup {
                     src: *spos,
@@ -68,6 +72,7 @@ impl Crew {
             pos: from_building.pos,
             path: Some(path),
             from: from_id,
+            member: None,
             task,
             inventory: Inventory::new(),
         })
@@ -81,6 +86,7 @@ impl Crew {
             pos: from_pos,
             path: Some(path),
             from: from_id,
+            member: None,
             task: CrewTask::Build(dest),
             inventory: Inventory::new(),
         })
@@ -109,6 +115,7 @@ impl Crew {
             pos: from_pos,
             path: Some(path),
             from: from_id,
+            member: None,
             task: CrewTask::Pickup {
                 src,
                 dest,
@@ -132,12 +139,18 @@ impl Crew {
             pos: from_pos,
             path: Some(path),
             from: from_id,
+            member: None,
             task: CrewTask::Deliver { dst: dest, item },
             inventory: Inventory::from([(item, 1)]),
         })
     }
 
     /// Returns the id of the global task
+    pub(crate) fn with_member(mut self, member: Option<usize>) -> Self {
+        self.member = member;
+        self
+    }
+
     pub fn gt_id(&self) -> Option<GlobalTaskId> {
         match self.task {
             CrewTask::Excavate(id) => Some(id),
@@ -145,6 +158,15 @@ impl Crew {
         }
     }
 
+    /// The job that takes working time at the target
+    fn job(&self) -> Option<Job> {
+        match self.task {
+            CrewTask::Excavate(_) => Some(Job::Excavate),
+            CrewTask::Build(_) => Some(Job::Build),
+            _ => None,
+        }
+    }
+
     pub fn target(&self) -> Option<Pos> {
         match self.task {
             CrewTask::Build(pos) => Some(pos),
@@ -157,30 +179,41 @@ impl Crew {
         global_tasks: &mut EntitySet<GlobalTask>,
         gt_id: GlobalTaskId,
         tiles: &Tiles,
+        efficiency: f64,
     ) {
-        if let Some(GlobalTask::Excavate(t, pos)) = global_tasks.get_mut(gt_id) {
+        if let Some(GlobalTask::Excavate(t, pos, _)) = global_tasks.get_mut(gt_id) {
             let tile = &tiles[*pos];
-            if proceed_excavate(t, 1., &mut self.inventory, tile) && self.inventory.is_empty() {
+            if proceed_excavate(t, efficiency, &mut self.inventory, tile)
+                && self.inventory.is_empty()
+            {
                 return;
             }
         }
         self.task = CrewTask::None;
     }
 
-    fn process_build_task(&mut self, constructions: &mut EntitySet<Construction>, ct_pos: Pos) {
+    fn process_build_task(
+        &mut self,
+        constructions: &mut EntitySet<Construction>,
+        ct_pos: Pos,
+        efficiency: f64,
+    ) {
         for con in constructions.iter_mut() {
+            if con.paused {
+                continue;
+            }
             let canceling = con.canceling();
             let t = &mut con.progress;
             if canceling {
                 if ct_pos == con.pos && 0. < *t {
-                    *t = (*t - 1.).max(0.);
+                    *t = (*t - efficiency).max(0.);
                     if *t <= 0. {
                         self.task = CrewTask::None;
                     }
                     return;
                 }
             } else if ct_pos == con.pos && *t < con.recipe.time {
-                *t += 1.;
+                *t = (*t + efficiency).min(con.recipe.time);
                 if con.recipe.time <= *t {
                     self.task = CrewTask::None;
                 }
@@ -199,6 +232,7 @@ impl Crew {
         buildings: &mut EntitySet<Building>,
         constructions: &mut EntitySet<Construction>,
         transports: &mut EntitySet<Transport>,
+        item_piles: &mut EntitySet<ItemPile>,
     ) {
         let mut process_inventory = |inventory: &mut Inventory| {
             let Some(item) = item.or_else(|| inventory.keys().copied().next()) else {
@@ -259,6 +293,42 @@ impl Crew {
                     self.path = Some(path);
                     self.task = CrewTask::Deliver { dst: dest, item };
                     Some(())
+                })
+                .or_else(|| {
+                    let (idx, pile) = item_piles.items_mut().find(|(_, p)| p.pos == src)?;
+                    // Ores are not countable, so they are carried all at once along with an item
+                    self.inventory.add_ores(pile.inventory.ores());
+                    *pile.inventory.ores_mut() = OreAccum::default();
+                    let item = item
+                        .or_else(|| pile.inventory.keys().next().copied())
+                        .filter(|item| 0 < pile.inventory.get(item));
+                    if let Some(item) = item {
+                        let entry = pile.inventory.entry(item).or_default();
+                        *entry -= 1;
+                        if *entry == 0 {
+                            pile.inventory.remove(&item);
+                        }
+                        *self.inventory.entry(item).or_default() += 1;
+                    }
+                    if pile.is_empty() {
+                        item_piles.remove(idx);
+                    }
+                    let Some(item) = item else {
+                        // Only ores were in the pile, which the crew takes back to the cabin
+                        let cabin = buildings.get(self.from)?;
+                        let path = find_path(self.pos, cabin.pos, |pos| {
+                            matches!(tiles[pos].state, TileState::Empty) || cabin.intersects(pos)
+                        })?;
+                        self.path = Some(path);
+                        self.task = CrewTask::Return;
+                        return Some(());
+                    };
+                    let path = find_path(self.pos, dest, |pos| {
+                        matches!(tiles[pos].state, TileState::Empty) || pos == dest
+                    })?;
+                    self.path = Some(path);
+                    self.task = CrewTask::Deliver { dst: dest, item };
+                    Some(())
                 });
         if res.is_none() {
             self.task = CrewTask::None;
@@ -300,15 +370,24 @@ impl Crew {
         constructions: &mut EntitySet<Construction>,
         buildings: &mut EntitySet<Building>,
     ) -> bool {
-        let construction = constructions.iter().find(|construction| {
-            if crews
-                .iter()
-                .any(|crew| crew.target() == Some(construction.pos))
-            {
-                return false;
-            }
-            construction.ingredients_satisfied()
-        });
+        let can_build = buildings.get(self.from).and_then(|b| {
+            b.skills
+                .as_ref()
+                .map(|skills| skills.priority(self.member, Job::Build))
+        }) != Some(0);
+        let construction = scheduled_constructions(constructions)
+            .into_iter()
+            .filter(|_| can_build)
+            .filter_map(|id| constructions.get(id))
+            .find(|construction| {
+                if crews
+                    .iter()
+                    .any(|crew| crew.target() == Some(construction.pos))
+                {
+                    return false;
+                }
+                construction.ingredients_satisfied()
+            });
 
         if let Some((construction, path)) = construction.and_then(|construction| {
             let dest = construction.pos;
@@ -346,9 +425,7 @@ impl Crew {
     fn try_return(&mut self, buildings: &mut EntitySet<Building>) -> bool {
         if let Some(building) = buildings.get_mut(self.from) {
             building.crews += 1;
-            for (item, amount) in &self.inventory {
-                *building.inventory.entry(*item).or_default() += *amount;
-            }
+            building.inventory.merge(&self.inventory);
             false
         } else {
             true
@@ -371,12 +448,29 @@ impl AsteroidColoniesGame {
                 }
                 return true;
             }
+            // Crews whose needs are not met work slower, and experienced ones work faster
+            let mut efficiency = 1.;
+            if let Some(cabin) = self.buildings.get_mut(crew.from) {
+                if let Some(needs) = &cabin.needs {
+                    efficiency *= needs.efficiency();
+                }
+                if let Some(job) = crew.job().filter(|_| cabin.type_.is_cabin()) {
+                    let skills = cabin.skills.get_or_insert_with(CabinSkills::default);
+                    efficiency *= skills.speed(crew.member, job);
+                    skills.gain(crew.member, job);
+                }
+            }
             match crew.task {
                 CrewTask::Excavate(gt_id) => {
-                    crew.process_excavate_task(&mut self.global_tasks, gt_id, &self.tiles);
+                    crew.process_excavate_task(
+                        &mut self.global_tasks,
+                        gt_id,
+                        &self.tiles,
+                        efficiency,
+                    );
                 }
                 CrewTask::Build(ct_pos) => {
-                    crew.process_build_task(&mut self.constructions, ct_pos);
+                    crew.process_build_task(&mut self.constructions, ct_pos, efficiency);
                 }
                 CrewTask::Pickup { src, dest, item } => {
                     crew.process_pickup_task(
@@ -387,6 +481,7 @@ impl AsteroidColoniesGame {
                         &mut self.buildings,
                         &mut self.constructions,
                         &mut self.transports,
+                        &mut self.item_piles,
                     );
                 }
                 CrewTask::Deliver { dst, item } => {
@@ -424,7 +519,7 @@ impl AsteroidColoniesGame {
     }
 }
 
-pub(crate) fn _expected_crew_pickups(crews: &[Crew], src: Pos) -> HashMap<ItemType, usize> {
+pub(crate) fn expected_crew_pickups(crews: &EntitySet<Crew>, src: Pos) -> HashMap<ItemType, usize> {
     crews
         .iter()
         .filter_map(|t| match t.task {
@@ -46,4 +46,23 @@ impl Direction {
             Self::Down => Self::Up,
         }
     }
+
+    /// The direction after rotating 90 degrees clockwise on the screen, where y points down.
+    pub(crate) fn rotate_cw(&self) -> Self {
+        match self {
+            Self::Left => Self::Up,
+            Self::Up => Self::Right,
+            Self::Right => Self::Down,
+            Self::Down => Self::Left,
+        }
+    }
+
+    /// The direction after mirroring horizontally.
+    pub(crate) fn mirror_x(&self) -> Self {
+        match self {
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+            _ => *self,
+        }
+    }
 }
@@ -1,5 +1,8 @@
+mod diff;
 mod entry_payload;
 mod ref_option;
+#[cfg(test)]
+mod tests;
 
 use std::{
     cell::{Cell, RefCell},
@@ -7,6 +10,7 @@ use std::{
     marker::PhantomData,
 };
 
+pub use self::diff::{EntityDigests, EntitySetDiff};
 pub(crate) use self::entry_payload::EntryPayload;
 pub use self::ref_option::{RefMutOption, RefOption};
 use serde::{Deserialize, Serialize};
@@ -56,7 +60,7 @@ impl<T> EntitySet<T> {
 
     /// Return an iterator over Ref<T>.
     /// It borrows the T immutably.
-    pub fn iter(&self) -> impl Iterator<Item = RefOption<T>> {
+    pub fn iter(&self) -> impl Iterator<Item = RefOption<'_, T>> {
         self.v.iter().filter_map(|v| RefOption::new(&v.payload))
     }
 
@@ -70,14 +74,14 @@ impl<T> EntitySet<T> {
 
     /// Return an iterator over RefMut<T>, skipping already borrowed items.
     /// It borrows the T mutablly.
-    pub fn iter_borrow_mut(&self) -> impl Iterator<Item = RefMutOption<T>> {
+    pub fn iter_borrow_mut(&self) -> impl Iterator<Item = RefMutOption<'_, T>> {
         self.v.iter().filter_map(|v| RefMutOption::new(&v.payload))
     }
 
     /// Return an iterator over (id, Ref<T>)
     /// It is convenient when you want the EntityId of the iterated items.
     /// It borrows the T immutably.
-    pub fn items(&self) -> impl Iterator<Item = (EntityId<T>, RefOption<T>)> {
+    pub fn items(&self) -> impl Iterator<Item = (EntityId<T>, RefOption<'_, T>)> {
         self.v.iter().enumerate().filter_map(|(i, v)| {
             Some((EntityId::new(i as u32, v.gen), RefOption::new(&v.payload)?))
         })
@@ -98,7 +102,7 @@ impl<T> EntitySet<T> {
     /// Return an iterator over (id, RefMut<T>), skipping already borrowed items.
     /// It is convenient when you want the EntityId of the iterated items.
     /// It borrows the T mutablly.
-    pub fn items_borrow_mut(&self) -> impl Iterator<Item = (EntityId<T>, RefMutOption<T>)> {
+    pub fn items_borrow_mut(&self) -> impl Iterator<Item = (EntityId<T>, RefMutOption<'_, T>)> {
         self.v.iter().enumerate().filter_map(|(i, v)| {
             Some((
                 EntityId::new(i as u32, v.gen),
@@ -188,7 +192,7 @@ impl<T> EntitySet<T> {
         }
     }
 
-    pub fn get(&self, id: EntityId<T>) -> Option<RefOption<T>> {
+    pub fn get(&self, id: EntityId<T>) -> Option<RefOption<'_, T>> {
         self.v.get(id.id as usize).and_then(|entry| {
             if id.gen == entry.gen {
                 RefOption::new(&entry.payload)
@@ -215,7 +219,7 @@ impl<T> EntitySet<T> {
             .and_then(|entry| entry.payload.get_mut().as_mut())
     }
 
-    pub fn borrow_mut_at(&self, idx: usize) -> Option<RefMutOption<T>> {
+    pub fn borrow_mut_at(&self, idx: usize) -> Option<RefMutOption<'_, T>> {
         self.v
             .get(idx)
             .and_then(|entry| RefMutOption::new(&entry.payload))
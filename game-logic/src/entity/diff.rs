@@ -0,0 +1,110 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{EntityEntry, EntityId, EntitySet, EntryPayload};
+use crate::tile::new_hasher;
+
+/// Digests of the entities in an `EntitySet`, keyed by their ids.
+/// Comparing them tells which entities have changed without keeping a copy of the contents.
+pub type EntityDigests<T> = HashMap<EntityId<T>, u64>;
+
+/// Changes of an `EntitySet` from a base state.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EntitySetDiff<T> {
+    pub inserted: Vec<(EntityId<T>, T)>,
+    pub changed: Vec<(EntityId<T>, T)>,
+    pub removed: Vec<EntityId<T>>,
+}
+
+impl<T> EntitySetDiff<T> {
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl<T: Serialize> EntitySet<T> {
+    /// Hash the serialized form of each entity, so that anything that is synchronized
+    /// to clients affects the digest.
+    pub fn digests(&self) -> EntityDigests<T> {
+        let mut buf = vec![];
+        self.items()
+            .map(|(id, item)| {
+                buf.clear();
+                // Serializing into a Vec does not fail unless the Serialize impl is broken
+                bincode::serialize_into(&mut buf, &*item).expect("Entity should be serializable");
+                let mut hasher = new_hasher();
+                buf.hash(&mut hasher);
+                (id, hasher.finish())
+            })
+            .collect()
+    }
+}
+
+impl<T: Clone> EntitySet<T> {
+    /// Collect the entities that differ between `base` and `current`, which should be the
+    /// digests of this set. If `base` is `None`, every entity is regarded as inserted.
+    pub fn diff(
+        &self,
+        base: Option<&EntityDigests<T>>,
+        current: &EntityDigests<T>,
+    ) -> EntitySetDiff<T> {
+        let mut inserted = vec![];
+        let mut changed = vec![];
+        for (id, digest) in current {
+            let base_digest = base.and_then(|base| base.get(id));
+            if base_digest == Some(digest) {
+                continue;
+            }
+            let Some(item) = self.get(*id) else {
+                continue;
+            };
+            if base_digest.is_some() {
+                changed.push((*id, item.clone()));
+            } else {
+                inserted.push((*id, item.clone()));
+            }
+        }
+        let removed = base.map_or_else(Vec::new, |base| {
+            base.keys()
+                .filter(|id| !current.contains_key(id))
+                .copied()
+                .collect()
+        });
+        EntitySetDiff {
+            inserted,
+            changed,
+            removed,
+        }
+    }
+}
+
+impl<T> EntitySet<T> {
+    /// Apply changes made on another instance of the set, keeping the entity ids the same.
+    pub fn apply_diff(&mut self, diff: EntitySetDiff<T>) {
+        for id in diff.removed {
+            self.remove(id);
+        }
+        for (id, item) in diff.inserted.into_iter().chain(diff.changed) {
+            self.set(id, item);
+        }
+    }
+
+    /// Put an entity at the given id, overwriting any entity in the slot.
+    fn set(&mut self, id: EntityId<T>, item: T) {
+        let idx = id.id as usize;
+        while self.v.len() <= idx {
+            self.v.push(EntityEntry {
+                gen: 0,
+                payload: RefCell::new(EntryPayload::Free(None)),
+            });
+        }
+        let entry = &mut self.v[idx];
+        entry.gen = id.gen;
+        entry.payload = RefCell::new(EntryPayload::Occupied(item));
+    }
+}
@@ -0,0 +1,52 @@
+use super::*;
+
+fn apply_diff(server: &EntitySet<i32>, client: &mut EntitySet<i32>, base: &EntityDigests<i32>) {
+    let diff = server.diff(Some(base), &server.digests());
+    client.apply_diff(diff);
+}
+
+#[test]
+fn test_diff() {
+    let mut set: EntitySet<i32> = [1, 2, 3].into_iter().collect();
+    let ids: Vec<_> = set.items().map(|(id, _)| id).collect();
+    let base = set.digests();
+
+    *set.get_mut(ids[0]).unwrap() = 10;
+    set.remove(ids[1]);
+    let inserted = set.insert(4);
+    let diff = set.diff(Some(&base), &set.digests());
+
+    assert_eq!(diff.changed, vec![(ids[0], 10)]);
+    assert_eq!(diff.removed, vec![ids[1]]);
+    // The slot of the removed entity is reused with a new generation
+    assert_eq!(diff.inserted, vec![(inserted, 4)]);
+    assert_ne!(inserted, ids[1]);
+
+    let full = set.diff(None, &set.digests());
+    assert_eq!(full.inserted.len(), 3);
+    assert!(full.changed.is_empty() && full.removed.is_empty());
+}
+
+#[test]
+fn test_apply_diff() {
+    let mut server: EntitySet<i32> = [1, 2, 3].into_iter().collect();
+    let mut client = EntitySet::new();
+    client.apply_diff(server.diff(None, &server.digests()));
+    assert_eq!(client, server);
+
+    let base = server.digests();
+    let ids: Vec<_> = server.items().map(|(id, _)| id).collect();
+    server.remove(ids[0]);
+    server.remove(ids[2]);
+    server.insert(5);
+    *server.get_mut(ids[1]).unwrap() = 20;
+    apply_diff(&server, &mut client, &base);
+
+    let items = |set: &EntitySet<i32>| {
+        set.items()
+            .map(|(id, item)| (id, *item))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(items(&client), items(&server));
+    assert!(client.get(ids[0]).is_none());
+}
@@ -0,0 +1,122 @@
+//! Item filters of splitters and building outputs, which sort items on conveyors.
+
+#[cfg(test)]
+mod tests;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{conveyor::Conveyor, direction::Direction, items::ItemType, AsteroidColoniesGame, Pos};
+
+/// A set of item types. It is a bit set, so that it can be copied around with tiles, but it is
+/// serialized as a list of items.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ItemSet(u32);
+
+impl ItemSet {
+    pub fn new() -> Self {
+        Self(0)
+    }
+
+    pub fn contains(&self, item: ItemType) -> bool {
+        self.0 & (1 << item as u32) != 0
+    }
+
+    pub fn insert(&mut self, item: ItemType) {
+        self.0 |= 1 << item as u32;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ItemType> + '_ {
+        ItemType::all()
+            .into_iter()
+            .filter(|item| self.contains(*item))
+    }
+}
+
+impl FromIterator<ItemType> for ItemSet {
+    fn from_iter<T: IntoIterator<Item = ItemType>>(iter: T) -> Self {
+        let mut set = Self::new();
+        for item in iter {
+            set.insert(item);
+        }
+        set
+    }
+}
+
+impl std::fmt::Debug for ItemSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl Serialize for ItemSet {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for ItemSet {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<ItemType>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+/// A filter of a splitter, which sends the items in the set out to `dir` and all the other
+/// items, including ores, to the other outputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SplitterFilter {
+    pub dir: Direction,
+    pub items: ItemSet,
+}
+
+impl SplitterFilter {
+    /// Whether the filter can be set to the conveyor, which has to be a splitter with an output
+    /// to the direction.
+    pub(crate) fn fits(&self, conveyor: &Conveyor) -> bool {
+        matches!(conveyor, Conveyor::Splitter(from) if *from != self.dir)
+    }
+
+    /// Whether the item can go out of the splitter to the direction. `None` is ores.
+    pub(crate) fn allows(&self, item: Option<ItemType>, to: Direction) -> bool {
+        item.is_some_and(|item| self.items.contains(item)) == (to == self.dir)
+    }
+}
+
+impl AsteroidColoniesGame {
+    /// Set or clear the filter of the splitter at the position.
+    pub fn set_splitter_filter(
+        &mut self,
+        pos: Pos,
+        filter: Option<SplitterFilter>,
+    ) -> Result<(), String> {
+        let tile = self
+            .tiles
+            .try_get_mut(pos)
+            .filter(|tile| matches!(tile.conveyor, Conveyor::Splitter(_)))
+            .ok_or_else(|| String::from("There is no splitter at the target"))?;
+        if let Some(filter) = filter {
+            if !filter.fits(&tile.conveyor) {
+                return Err(String::from(
+                    "The filter direction is not an output of the splitter",
+                ));
+            }
+        }
+        tile.filter = filter;
+        Ok(())
+    }
+
+    /// Set the items that the building at the position pushes out, or clear the filter to push
+    /// out all of its products.
+    pub fn set_output_filter(&mut self, pos: Pos, items: Option<ItemSet>) -> Result<(), String> {
+        let Some(building) = self.buildings.iter_mut().find(|b| b.intersects(pos)) else {
+            return Err("The building does not exist at the target".to_string());
+        };
+        building.output_filter = items;
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use super::*;
+use crate::{
+    building::{Building, BuildingType},
+    entity::EntitySet,
+    inventory::Inventory,
+    push_pull::{pull_inputs, push_outputs},
+    tile::{Tile, Tiles},
+    transport::TransportPayload,
+};
+use std::collections::HashSet;
+
+#[test]
+fn test_item_set_serde() {
+    let set: ItemSet = [ItemType::Wire, ItemType::Gear].into_iter().collect();
+    assert!(set.contains(ItemType::Gear));
+    assert!(!set.contains(ItemType::RawOre));
+    let json = serde_json::to_string(&set).unwrap();
+    assert_eq!(json, r#"["Gear","Wire"]"#);
+    assert_eq!(serde_json::from_str::<ItemSet>(&json).unwrap(), set);
+    assert!(serde_json::from_str::<ItemSet>("[]").unwrap().is_empty());
+}
+
+#[test]
+fn test_allows() {
+    use Direction::*;
+    let filter = SplitterFilter {
+        dir: Right,
+        items: [ItemType::Gear].into_iter().collect(),
+    };
+    assert!(filter.allows(Some(ItemType::Gear), Right));
+    assert!(!filter.allows(Some(ItemType::Gear), Left));
+    assert!(!filter.allows(Some(ItemType::Wire), Right));
+    assert!(filter.allows(Some(ItemType::Wire), Left));
+    assert!(!filter.allows(None, Right));
+    assert!(filter.allows(None, Down));
+}
+
+#[test]
+fn test_set_splitter_filter() {
+    use Direction::*;
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let filter = SplitterFilter {
+        dir: Right,
+        items: [ItemType::Gear].into_iter().collect(),
+    };
+    game.tiles[[10, 10]].conveyor = Conveyor::One(Left, Right);
+    assert_eq!(
+        game.set_splitter_filter([10, 10], Some(filter)),
+        Err("There is no splitter at the target".to_string())
+    );
+    game.tiles[[10, 10]].conveyor = Conveyor::Splitter(Right);
+    assert_eq!(
+        game.set_splitter_filter([10, 10], Some(filter)),
+        Err("The filter direction is not an output of the splitter".to_string())
+    );
+    game.tiles[[10, 10]].conveyor = Conveyor::Splitter(Left);
+    game.set_splitter_filter([10, 10], Some(filter)).unwrap();
+    assert_eq!(game.tiles[[10, 10]].filter, Some(filter));
+    game.set_splitter_filter([10, 10], None).unwrap();
+    assert_eq!(game.tiles[[10, 10]].filter, None);
+}
+
+/// A splitter at [0, 0] fed from above, branching to [1, 2] on the right and [-1, 2] on the left.
+/// Gears are filtered to the right.
+fn splitter_tiles() -> Tiles {
+    use Direction::*;
+    let mut tiles = Tiles::new();
+    let mut splitter = Tile::new_with_conveyor(Conveyor::Splitter(Up));
+    splitter.filter = Some(SplitterFilter {
+        dir: Right,
+        items: [ItemType::Gear].into_iter().collect(),
+    });
+    tiles[[0, 0]] = splitter;
+    tiles[[1, 0]] = Tile::new_with_conveyor(Conveyor::One(Left, Down));
+    tiles[[1, 1]] = Tile::new_with_conveyor(Conveyor::One(Up, Down));
+    tiles[[-1, 0]] = Tile::new_with_conveyor(Conveyor::One(Right, Down));
+    tiles[[-1, 1]] = Tile::new_with_conveyor(Conveyor::One(Up, Down));
+    tiles
+}
+
+#[test]
+fn test_filtered_splitter() {
+    let tiles = splitter_tiles();
+    let inventory: Inventory = [(ItemType::Gear, 1), (ItemType::Wire, 1)]
+        .into_iter()
+        .collect();
+    let storage: EntitySet<_> = [Building::new_inventory(
+        [0, -1],
+        BuildingType::Storage,
+        inventory,
+    )]
+    .into_iter()
+    .collect();
+
+    let pull = |ty: ItemType, dest: Pos| {
+        let mut transports = EntitySet::new();
+        pull_inputs(
+            &[(ty, 1)]
+                .into_iter()
+                .collect::<std::collections::BTreeMap<_, _>>(),
+            &tiles,
+            &mut transports,
+            &mut HashSet::new(),
+            dest,
+            [1, 1],
+            &mut Inventory::new(),
+            &storage,
+        );
+        let path = transports.iter().map(|t| t.path.clone()).next();
+        path
+    };
+
+    assert_eq!(
+        pull(ItemType::Gear, [1, 2]),
+        Some(vec![[1, 2], [1, 1], [1, 0], [0, 0], [0, -1]])
+    );
+    assert_eq!(pull(ItemType::Gear, [-1, 2]), None);
+    assert_eq!(pull(ItemType::Wire, [1, 2]), None);
+    assert_eq!(
+        pull(ItemType::Wire, [-1, 2]),
+        Some(vec![[-1, 2], [-1, 1], [-1, 0], [0, 0], [0, -1]])
+    );
+}
+
+#[test]
+fn test_output_filter() {
+    let tiles = splitter_tiles();
+    let storages: EntitySet<_> = [
+        Building::new([-1, 2], BuildingType::Storage),
+        Building::new([1, 2], BuildingType::Storage),
+    ]
+    .into_iter()
+    .collect();
+
+    let inventory: Inventory = [(ItemType::Wire, 1)].into_iter().collect();
+    let mut source = Building::new_inventory([0, -1], BuildingType::Assembler, inventory);
+    source.output_filter = Some([ItemType::Gear].into_iter().collect());
+
+    let mut transports = EntitySet::new();
+    push_outputs(
+        &tiles,
+        &mut transports,
+        &mut source,
+        &storages,
+        &|_| true,
+        false,
+    );
+    assert_eq!(transports.len(), 0);
+
+    source.inventory.insert(ItemType::Gear, 1);
+    push_outputs(
+        &tiles,
+        &mut transports,
+        &mut source,
+        &storages,
+        &|_| true,
+        false,
+    );
+    assert_eq!(transports.len(), 1);
+    let transport = transports.iter().next().unwrap();
+    assert_eq!(transport.dest, [1, 2]);
+    assert!(matches!(
+        transport.payload,
+        TransportPayload::Item(ItemType::Gear, 1)
+    ));
+    assert_eq!(source.inventory.get(&ItemType::Wire), 1);
+}
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, io::Read};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::Read,
+};
 
 use crate::{
     btree_map,
@@ -10,13 +13,23 @@ use crate::{
     crew::Crew,
     direction::Direction,
     entity::{EntitySet, RefOption},
-    items::{recipes, ItemType},
+    history::CommandHistory,
+    item_pile::ItemPile,
+    items::{find_recipe, recipes, ItemType},
+    logistics::LOGISTICS_INTERVAL,
+    migration::{migrate_save, SAVE_VERSION},
     perlin_noise::gen_terms,
+    player::PlayerId,
+    power::{PowerNetwork, PowerNetworkId, PowerNetworks},
     push_pull::send_item,
+    research::Research,
+    ruleset::{load_ruleset, Ruleset},
+    stats::ProductionStats,
+    sync::EntitySnapshot,
     task::{BuildingTask, GlobalTask, MOVE_TIME},
     tile::CHUNK_SIZE,
     transport::{find_path, Transport},
-    Pos, Position, Tile, TileState, Tiles, Xor128, HEIGHT, WIDTH,
+    Pos, Tile, TileState, Tiles, Xor128, HEIGHT, WIDTH,
 };
 
 pub(crate) const PERLIN_BITS: u32 = 4;
@@ -28,21 +41,43 @@ pub struct AsteroidColoniesGame {
     pub(crate) buildings: EntitySet<Building>,
     pub(crate) crews: EntitySet<Crew>,
     pub(crate) global_tasks: EntitySet<GlobalTask>,
-    pub(crate) power_ratio: f64,
-    /// Used power for the last tick, in kW
-    pub(crate) used_power: f64,
+    /// Power networks evaluated in the last tick
+    pub(crate) power_networks: PowerNetworks,
     pub(crate) global_time: usize,
     pub(crate) transports: EntitySet<Transport>,
     pub(crate) constructions: EntitySet<Construction>,
+    pub(crate) item_piles: EntitySet<ItemPile>,
+    /// Tiles whose conveyors have changed since the last tick, to reroute the transports over them
+    pub(crate) changed_tiles: HashSet<Pos>,
     /// Ghost conveyors staged for commit. After committing, they will be queued to construction plans
     pub(crate) conveyor_staged: HashMap<Pos, Conveyor>,
     /// Preview of ghost conveyors, just for visualization.
     pub(crate) conveyor_preview: HashMap<Pos, Conveyor>,
+    /// The tier of the staged conveyors
+    pub(crate) conveyor_tier: u8,
     pub(crate) calculate_back_image: Option<CalculateBackImage>,
     pub(crate) rng: Xor128,
+    /// Wall-clock time of the last save in seconds since the UNIX epoch, set by whoever saves
+    /// the game, since the game logic cannot read the clock on Wasm.
+    pub(crate) saved_at: Option<u64>,
+    /// Entities of the recent updates received from the server, which it can send diffs against.
+    pub(crate) sync_frames: VecDeque<(u64, EntitySnapshot)>,
+    pub(crate) production_stats: ProductionStats,
+    pub(crate) research: Research,
+    pub(crate) history: CommandHistory,
 }
 
 impl AsteroidColoniesGame {
+    /// Create a game with the given ruleset instead of the default one. The ruleset is shared
+    /// in the process, so it must be the same as the one of any other game created before.
+    pub fn new_with_ruleset(
+        calculate_back_image: Option<CalculateBackImage>,
+        ruleset: Ruleset,
+    ) -> Result<Self, String> {
+        load_ruleset(ruleset)?;
+        Self::new(calculate_back_image)
+    }
+
     pub fn new(calculate_back_image: Option<CalculateBackImage>) -> Result<Self, String> {
         let mut tiles = Tiles::new();
         let r2_thresh = (WIDTH as f64 * 3. / 8.).powi(2);
@@ -70,7 +105,12 @@ impl AsteroidColoniesGame {
             ]
         };
         let buildings: EntitySet<_> = [
-            Building::new(start_ofs([1, 7]), BuildingType::CrewCabin),
+            Building::new_inventory(
+                start_ofs([1, 7]),
+                BuildingType::CrewCabin,
+                btree_map!(ItemType::Oxygen => 10, ItemType::Food => 10).into(),
+            )
+            .with_crews(BuildingType::CrewCabin.max_crews()),
             Building::new(start_ofs([3, 4]), BuildingType::Power),
             Building::new(start_ofs([2, 3]), BuildingType::Battery),
             Building::new(start_ofs([3, 3]), BuildingType::Battery),
@@ -148,15 +188,22 @@ impl AsteroidColoniesGame {
             buildings,
             crews: EntitySet::new(),
             global_tasks: EntitySet::new(),
-            power_ratio: 1.,
-            used_power: 0.,
+            power_networks: PowerNetworks::default(),
             global_time: 0,
             transports: EntitySet::new(),
             constructions: EntitySet::new(),
+            item_piles: EntitySet::new(),
+            changed_tiles: HashSet::new(),
             conveyor_staged: HashMap::new(),
             conveyor_preview: HashMap::new(),
+            conveyor_tier: 0,
             calculate_back_image,
             rng: Xor128::new(412135),
+            saved_at: None,
+            sync_frames: VecDeque::new(),
+            production_stats: ProductionStats::default(),
+            research: Research::default(),
+            history: CommandHistory::default(),
         })
     }
 
@@ -164,11 +211,53 @@ impl AsteroidColoniesGame {
         self.global_time
     }
 
-    /// Get the last power ratio. Used for interpolation of buildings animation.
-    ///
-    /// TODO: it shouldn't be global, should be per building of power grid.
+    pub fn get_saved_at(&self) -> Option<u64> {
+        self.saved_at
+    }
+
+    /// Record the wall-clock time in seconds since the UNIX epoch, which is saved with the game.
+    pub fn set_saved_at(&mut self, time: u64) {
+        self.saved_at = Some(time);
+    }
+
+    /// Get the last power ratio aggregated over all power networks. Used for interpolation of
+    /// buildings animation. Use [`Self::get_building_power_ratio`] for a specific building.
     pub fn get_power_ratio(&self) -> f64 {
-        self.power_ratio
+        self.power_networks.total_power_ratio()
+    }
+
+    /// Get the last power ratio of the network that the building at `pos` is connected to.
+    /// Returns 0 if the building is not connected to any power grid.
+    pub fn get_building_power_ratio(&self, pos: Pos) -> f64 {
+        self.buildings
+            .items()
+            .find(|(_, b)| b.intersects(pos))
+            .map(|(id, _)| self.power_networks.power_ratio(id))
+            .unwrap_or(0.)
+    }
+
+    /// Used power for the last tick summed over all power networks, in kW
+    pub fn get_used_power(&self) -> f64 {
+        self.power_networks.total_used_power()
+    }
+
+    pub fn power_networks(&self) -> &PowerNetworks {
+        &self.power_networks
+    }
+
+    pub fn production_stats(&self) -> &ProductionStats {
+        &self.production_stats
+    }
+
+    /// Find the power network that a building or a power grid tile at `pos` belongs to.
+    pub fn power_network_at(&self, pos: Pos) -> Option<(PowerNetworkId, &PowerNetwork)> {
+        let id = self
+            .buildings
+            .items()
+            .find(|(_, b)| b.intersects(pos))
+            .and_then(|(id, _)| self.power_networks.of_building(id))
+            .or_else(|| self.power_networks.at_tile(pos))?;
+        Some((id, self.power_networks.get(id)?))
     }
 
     // pub fn iter_tile(&self) -> impl Iterator<Item = &Tile> {
@@ -187,19 +276,19 @@ impl AsteroidColoniesGame {
         &self.tiles[pos]
     }
 
-    pub fn iter_building(&self) -> impl Iterator<Item = RefOption<Building>> {
+    pub fn iter_building(&self) -> impl Iterator<Item = RefOption<'_, Building>> {
         self.buildings.iter()
     }
 
-    pub fn iter_construction(&self) -> impl Iterator<Item = RefOption<Construction>> {
+    pub fn iter_construction(&self) -> impl Iterator<Item = RefOption<'_, Construction>> {
         self.constructions.iter()
     }
 
-    pub fn iter_crew(&self) -> impl Iterator<Item = RefOption<Crew>> {
+    pub fn iter_crew(&self) -> impl Iterator<Item = RefOption<'_, Crew>> {
         self.crews.iter()
     }
 
-    pub fn iter_global_task(&self) -> impl Iterator<Item = RefOption<GlobalTask>> {
+    pub fn iter_global_task(&self) -> impl Iterator<Item = RefOption<'_, GlobalTask>> {
         self.global_tasks.iter()
     }
 
@@ -207,7 +296,11 @@ impl AsteroidColoniesGame {
         self.transports.len()
     }
 
-    pub fn iter_transport(&self) -> impl Iterator<Item = RefOption<Transport>> {
+    pub fn num_item_piles(&self) -> usize {
+        self.item_piles.len()
+    }
+
+    pub fn iter_transport(&self) -> impl Iterator<Item = RefOption<'_, Transport>> {
         self.transports.iter()
     }
 
@@ -278,12 +371,14 @@ impl AsteroidColoniesGame {
                 .keys()
                 .next()
                 .ok_or_else(|| "Moving item source does not have an item")?;
-            let crew = if matches!(src.type_, BuildingType::CrewCabin) && 0 < src.crews {
-                Crew::new_deliver(src_id, src.pos, to, item, &self.tiles).map(|crew| (crew, src))
+            let member = |b: &Building, id| b.members_at_home(id, &self.crews).first().copied();
+            let crew = if src.type_.is_cabin() && 0 < src.crews {
+                Crew::new_deliver(src_id, src.pos, to, item, &self.tiles)
+                    .map(|crew| (crew.with_member(member(&src, src_id)), src))
             } else {
                 self.buildings.items_borrow_mut().find_map(|(from_id, b)| {
                     Crew::new_pickup(from_id, b.pos, from, to, item, &self.tiles)
-                        .map(|crew| (crew, b))
+                        .map(|crew| (crew.with_member(member(&b, from_id)), b))
                 })
             };
             if let Some((crew, mut cabin)) = crew {
@@ -298,7 +393,32 @@ impl AsteroidColoniesGame {
         })
     }
 
-    pub fn build(&mut self, ix: i32, iy: i32, type_: BuildingType) -> Result<(), String> {
+    pub fn build(
+        &mut self,
+        ix: i32,
+        iy: i32,
+        type_: BuildingType,
+        owner: Option<PlayerId>,
+    ) -> Result<(), String> {
+        self.check_building_site([ix, iy], type_)?;
+
+        if let Some(build) = get_build_menu()
+            .iter()
+            .find(|it| it.type_ == ConstructionType::Building(type_))
+        {
+            self.constructions
+                .insert(Construction::new(build, [ix, iy]).with_owner(owner));
+            // self.build_building(ix, iy, type_)?;
+        }
+        Ok(())
+    }
+
+    /// Check if a building of `type_` can be planned at `pos`.
+    pub(crate) fn check_building_site(&self, pos: Pos, type_: BuildingType) -> Result<(), String> {
+        let [ix, iy] = pos;
+        if !self.research.is_building_unlocked(type_) {
+            return Err(format!("{type_} is not researched yet"));
+        }
         let size = type_.size();
         for jy in iy..iy + size[1] as i32 {
             for jx in ix..ix + size[0] as i32 {
@@ -331,21 +451,65 @@ impl AsteroidColoniesGame {
                 "The destination is already occupied by a construction plan",
             ));
         }
+        Ok(())
+    }
 
-        if let Some(build) = get_build_menu()
-            .iter()
-            .find(|it| it.type_ == ConstructionType::Building(type_))
-        {
-            self.constructions
-                .insert(Construction::new(build, [ix, iy]));
-            // self.build_building(ix, iy, type_)?;
+    /// Check if an infrastructure tile like a conveyor or a power grid can be planned at `pos`.
+    pub(crate) fn check_tile_site(&self, pos: Pos, what: &str) -> Result<(), String> {
+        match self.tiles[pos].state {
+            TileState::Solid => Err(format!("Needs excavation before building {what}")),
+            TileState::Space => Err(format!("You cannot build {what} in space!")),
+            TileState::Empty => Ok(()),
         }
-        Ok(())
     }
 
-    pub fn build_plan(&mut self, constructions: &[Construction]) {
+    /// Insert construction plans made on a client. Only the types, positions, conveyor tiers
+    /// and building recipes are taken from the plans, which are made again here, so that a
+    /// client cannot send a plan with progress or ingredients. Building plans are owned by
+    /// `owner`, while conveyors and power grids are shared infrastructure and have no owner.
+    /// Plans that cannot be built at their positions are ignored.
+    pub fn build_plan(&mut self, constructions: &[Construction], owner: Option<PlayerId>) {
         for c in constructions {
-            self.constructions.insert(c.clone());
+            let pos = c.pos;
+            let planned = |f: &dyn Fn(ConstructionType) -> bool| {
+                self.constructions
+                    .iter()
+                    .any(|other| other.pos == pos && f(other.get_type()))
+            };
+            let plan = match c.get_type() {
+                ConstructionType::Building(ty) => {
+                    if self.check_building_site(pos, ty).is_err() {
+                        continue;
+                    }
+                    let Some(item) = get_build_menu()
+                        .iter()
+                        .find(|item| item.type_ == ConstructionType::Building(ty))
+                    else {
+                        continue;
+                    };
+                    Construction::new(item, pos)
+                        .with_owner(owner)
+                        .with_building_recipe(c.building_recipe.clone())
+                }
+                ConstructionType::Conveyor(conv) => {
+                    if self.check_tile_site(pos, "conveyor").is_err()
+                        || planned(&|ty| matches!(ty, ConstructionType::Conveyor(_)))
+                    {
+                        continue;
+                    }
+                    Construction::new_conveyor(pos, conv, c.conveyor_tier, false)
+                }
+                ConstructionType::PowerGrid => {
+                    if self.check_tile_site(pos, "power grid").is_err()
+                        || self.tiles[pos].power_grid
+                        || planned(&|ty| matches!(ty, ConstructionType::PowerGrid))
+                    {
+                        continue;
+                    }
+                    Construction::new_power_grid(pos, false)
+                }
+            };
+            self.constructions.insert(plan);
         }
     }
 
@@ -362,7 +526,8 @@ impl AsteroidColoniesGame {
             .find(|(_, b)| b.pos == [ix, iy])
             .ok_or_else(|| String::from("Building not found at given position"))?;
         let decon = Construction::new_deconstruct(b.type_, [ix, iy], &b.inventory)
-            .ok_or_else(|| String::from("No build recipe was found to deconstruct"))?;
+            .ok_or_else(|| String::from("No build recipe was found to deconstruct"))?
+            .with_owner(b.owner);
         self.constructions.insert(decon);
 
         self.buildings.remove(id);
@@ -378,9 +543,11 @@ impl AsteroidColoniesGame {
         if matches!(tile.conveyor, Conveyor::None) {
             return Err("Conveyor does not exist");
         }
-        let decon = Construction::new_conveyor([ix, iy], tile.conveyor, true);
+        let decon = Construction::new_conveyor([ix, iy], tile.conveyor, tile.conveyor_tier, true);
         tile.conveyor = Conveyor::None;
+        tile.filter = None;
         self.constructions.insert(decon);
+        self.changed_tiles.insert([ix, iy]);
         Ok(())
     }
 
@@ -398,6 +565,22 @@ impl AsteroidColoniesGame {
         Ok(())
     }
 
+    /// The owner of the building covering the position, if any.
+    pub fn building_owner(&self, pos: Pos) -> Option<PlayerId> {
+        self.buildings
+            .iter()
+            .find(|b| b.intersects(pos))
+            .and_then(|b| b.owner)
+    }
+
+    /// The owner of the construction plan at the position, if any.
+    pub fn construction_owner(&self, pos: Pos) -> Option<PlayerId> {
+        self.constructions
+            .iter()
+            .find(|c| c.pos == pos)
+            .and_then(|c| c.owner)
+    }
+
     pub fn get_recipes(&self, ix: i32, iy: i32) -> Result<Vec<&'static Recipe>, String> {
         if ix < 0 || WIDTH as i32 <= ix || iy < 0 || HEIGHT as i32 <= iy {
             return Err(String::from("Point outside tile"));
@@ -409,7 +592,10 @@ impl AsteroidColoniesGame {
         if !matches!(assembler.type_, BuildingType::Assembler) {
             return Err(String::from("The building is not an assembler"));
         }
-        Ok(recipes().iter().collect::<Vec<_>>())
+        Ok(recipes()
+            .iter()
+            .filter(|recipe| self.research.is_recipe_unlocked(recipe))
+            .collect::<Vec<_>>())
     }
 
     pub fn set_recipe(&mut self, ix: i32, iy: i32, name: Option<&str>) -> Result<(), String> {
@@ -423,20 +609,17 @@ impl AsteroidColoniesGame {
             assembler.set_recipe(None)?;
             return Ok(());
         };
-        for recipe in recipes() {
-            let Some((key, _)) = recipe.outputs.iter().next() else {
-                continue;
-            };
-            if format!("{:?}", key) == name {
-                assembler.set_recipe(Some(recipe))?;
-                break;
+        if let Some(recipe) = find_recipe(name) {
+            if !self.research.is_recipe_unlocked(recipe) {
+                return Err(String::from("The recipe is not researched yet"));
             }
+            assembler.set_recipe(Some(recipe))?;
         }
         Ok(())
     }
 
-    pub fn cleanup_item(&mut self, pos: Pos) -> Result<(), String> {
-        self.global_tasks.insert(GlobalTask::Cleanup(pos));
+    pub fn cleanup_item(&mut self, pos: Pos, owner: Option<PlayerId>) -> Result<(), String> {
+        self.global_tasks.insert(GlobalTask::Cleanup(pos, owner));
         Ok(())
     }
 
@@ -444,9 +627,14 @@ impl AsteroidColoniesGame {
         self.process_global_tasks();
         self.process_transports();
         self.process_constructions();
+        if self.global_time.is_multiple_of(LOGISTICS_INTERVAL) {
+            self.process_logistics();
+        }
         self.process_buildings();
+        self.process_life_support();
         self.process_crews();
 
+        self.production_stats.end_tick(self.global_time);
         self.global_time += 1;
 
         Ok(())
@@ -470,13 +658,29 @@ impl AsteroidColoniesGame {
         bincode::serialize(&ser_game).map_err(|e| format!("{e}"))
     }
 
-    pub fn deserialize(&mut self, rdr: impl Read) -> serde_json::Result<()> {
-        let ser_data: SerializeGame = serde_json::from_reader(rdr)?;
+    /// Deserialize a JSON save, migrating it from an older save format version if necessary.
+    pub fn deserialize(&mut self, rdr: impl Read) -> Result<(), String> {
+        let value: serde_json::Value = serde_json::from_reader(rdr).map_err(|e| format!("{e}"))?;
+        self.deserialize_value(value)
+    }
+
+    pub fn deserialize_value(&mut self, value: serde_json::Value) -> Result<(), String> {
+        let value = migrate_save(value)?;
+        let ser_data: SerializeGame = serde_json::from_value(value).map_err(|e| format!("{e}"))?;
         self.from_serialized(ser_data);
         Ok(())
     }
 
+    /// Deserialize binary data from the server. Unlike JSON, it is not migrated, because the
+    /// server and the client should always run the same version.
     pub fn deserialize_bin(&mut self, rdr: &[u8]) -> Result<(), String> {
+        // The version is the first field, so we can check it before parsing the rest.
+        let version: u32 = bincode::deserialize(rdr).map_err(|e| format!("{e}"))?;
+        if version != SAVE_VERSION {
+            return Err(format!(
+                "Save version mismatch: expected {SAVE_VERSION}, got {version}"
+            ));
+        }
         let ser_data: SerializeGame = bincode::deserialize(rdr).map_err(|e| format!("{e}"))?;
         self.from_serialized(ser_data);
         Ok(())
@@ -492,8 +696,20 @@ impl AsteroidColoniesGame {
         self.global_time = ser_data.global_time;
         self.transports = ser_data.transports;
         self.constructions = ser_data.constructions;
+        self.item_piles = ser_data.item_piles;
         self.rng = ser_data.rng;
+        self.saved_at = ser_data.saved_at;
+        self.production_stats = ser_data.production_stats;
+        self.research = ser_data.research;
+        self.history = ser_data.history;
+        // A full state replaces whatever we received from the server incrementally
+        self.sync_frames.clear();
 
+        self.rebuild_caches();
+    }
+
+    /// Rebuild the state derived from the entities after they are replaced.
+    pub(crate) fn rebuild_caches(&mut self) {
         // Clear transports expectation cache
         for building in self.buildings.iter_mut() {
             building.expected_transports.clear();
@@ -518,42 +734,18 @@ impl AsteroidColoniesGame {
             }
         }
 
+        self.power_networks = PowerNetworks::new(&self.tiles, &self.buildings);
+
         if let Some(ref f) = self.calculate_back_image {
             f(&mut self.tiles);
         }
     }
-
-    pub fn serialize_chunks_digest(&self) -> bincode::Result<Vec<u8>> {
-        let digests = self
-            .tiles
-            .chunks()
-            .iter()
-            .map(|(pos, chunk)| (pos, chunk.get_hash()))
-            .collect::<HashMap<_, _>>();
-        bincode::serialize(&digests)
-    }
-
-    pub fn serialize_with_diffs(
-        &self,
-        chunks_digest: &HashMap<Position, u64>,
-    ) -> Result<Vec<u8>, String> {
-        let tiles = self.tiles.filter_with_diffs(chunks_digest)?;
-        let ser_game = SerializeGame {
-            tiles,
-            buildings: self.buildings.clone(),
-            crews: self.crews.clone(),
-            global_tasks: self.global_tasks.clone(),
-            global_time: self.global_time,
-            transports: self.transports.clone(),
-            constructions: self.constructions.clone(),
-            rng: self.rng.clone(),
-        };
-        bincode::serialize(&ser_game).map_err(|e| format!("{e}"))
-    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SerializeGame {
+    /// The save format version. It must be the first field for `deserialize_bin` to check it.
+    version: u32,
     tiles: Tiles,
     buildings: EntitySet<Building>,
     crews: EntitySet<Crew>,
@@ -561,12 +753,18 @@ pub struct SerializeGame {
     global_time: usize,
     transports: EntitySet<Transport>,
     constructions: EntitySet<Construction>,
+    item_piles: EntitySet<ItemPile>,
     rng: Xor128,
+    saved_at: Option<u64>,
+    production_stats: ProductionStats,
+    research: Research,
+    history: CommandHistory,
 }
 
 impl From<&AsteroidColoniesGame> for SerializeGame {
     fn from(value: &AsteroidColoniesGame) -> Self {
         Self {
+            version: SAVE_VERSION,
             tiles: value.tiles.clone(),
             buildings: value.buildings.clone(),
             crews: value.crews.clone(),
@@ -574,7 +772,12 @@ impl From<&AsteroidColoniesGame> for SerializeGame {
             global_time: value.global_time,
             transports: value.transports.clone(),
             constructions: value.constructions.clone(),
+            item_piles: value.item_piles.clone(),
             rng: value.rng.clone(),
+            saved_at: value.saved_at,
+            production_stats: value.production_stats.clone(),
+            research: value.research.clone(),
+            history: value.history.clone(),
         }
     }
 }
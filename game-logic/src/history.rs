@@ -0,0 +1,236 @@
+//! Per-player history of commands, which lets players undo and redo recent changes.
+
+#[cfg(test)]
+mod tests;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    building::{Building, BuildingId},
+    construction::{Construction, ConstructionType},
+    items::recipe_name,
+    player::PlayerId,
+    AsteroidColoniesGame, Command, Conveyor, Pos,
+};
+
+/// The number of commands each player can undo.
+pub const MAX_HISTORY: usize = 32;
+
+/// The data to revert a command.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum Revert {
+    /// Remove the construction plans made by the command.
+    RemovePlans(Vec<(Pos, ConstructionType)>),
+    /// Toggle canceling of the construction plan back.
+    ToggleCancel(Pos),
+    /// Bring back a building with its inventory before it is deconstructed. The crews living in
+    /// it are moved back in from the id it had.
+    RestoreBuilding(BuildingId, Box<Building>),
+    RestoreConveyor(Pos, Conveyor),
+    RestorePowerGrid(Pos),
+    SetRecipe(Pos, Option<String>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct HistoryEntry {
+    command: Command,
+    revert: Revert,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct PlayerHistory {
+    undo: Vec<HistoryEntry>,
+    redo: Vec<Command>,
+}
+
+/// Undo and redo stacks of the players. `None` is the player in a single player game.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct CommandHistory(Vec<(Option<PlayerId>, PlayerHistory)>);
+
+impl CommandHistory {
+    fn get_mut(&mut self, owner: Option<PlayerId>) -> &mut PlayerHistory {
+        let idx = match self.0.iter().position(|(player, _)| *player == owner) {
+            Some(idx) => idx,
+            None => {
+                self.0.push((owner, PlayerHistory::default()));
+                self.0.len() - 1
+            }
+        };
+        &mut self.0[idx].1
+    }
+
+    pub fn can_undo(&self, owner: Option<PlayerId>) -> bool {
+        self.0
+            .iter()
+            .any(|(player, history)| *player == owner && !history.undo.is_empty())
+    }
+
+    pub fn can_redo(&self, owner: Option<PlayerId>) -> bool {
+        self.0
+            .iter()
+            .any(|(player, history)| *player == owner && !history.redo.is_empty())
+    }
+
+    fn push(&mut self, owner: Option<PlayerId>, entry: HistoryEntry, clear_redo: bool) {
+        let history = self.get_mut(owner);
+        history.undo.push(entry);
+        if MAX_HISTORY < history.undo.len() {
+            history.undo.remove(0);
+        }
+        if clear_redo {
+            history.redo.clear();
+        }
+    }
+}
+
+impl AsteroidColoniesGame {
+    pub fn history(&self) -> &CommandHistory {
+        &self.history
+    }
+
+    /// Apply a command and record it in the history of the owner if it can be reverted.
+    pub(crate) fn apply_recorded(
+        &mut self,
+        command: &Command,
+        owner: Option<PlayerId>,
+        clear_redo: bool,
+    ) -> Result<(), String> {
+        let revert = self.prepare_revert(command);
+        self.execute_command(command, owner)?;
+        if let Some(revert) = revert {
+            let entry = HistoryEntry {
+                command: command.clone(),
+                revert,
+            };
+            self.history.push(owner, entry, clear_redo);
+        }
+        Ok(())
+    }
+
+    /// Revert the last command of the player. A deconstruction can only be reverted before it
+    /// makes progress, and a construction plan before building starts.
+    pub fn undo(&mut self, owner: Option<PlayerId>) -> Result<(), String> {
+        let revert = self
+            .history
+            .get_mut(owner)
+            .undo
+            .last()
+            .map(|entry| entry.revert.clone())
+            .ok_or_else(|| String::from("Nothing to undo"))?;
+        // Keep the entry if it cannot be reverted yet, e.g. while the crews carry ingredients back
+        self.revert(&revert)?;
+        let history = self.history.get_mut(owner);
+        if let Some(entry) = history.undo.pop() {
+            history.redo.push(entry.command);
+        }
+        Ok(())
+    }
+
+    /// Apply the last undone command of the player again.
+    pub fn redo(&mut self, owner: Option<PlayerId>) -> Result<(), String> {
+        let command = self
+            .history
+            .get_mut(owner)
+            .redo
+            .pop()
+            .ok_or_else(|| String::from("Nothing to redo"))?;
+        self.apply_recorded(&command, owner, false)
+            .inspect_err(|_| self.history.get_mut(owner).redo.push(command))
+    }
+
+    /// Capture the state that the command is going to change.
+    fn prepare_revert(&self, command: &Command) -> Option<Revert> {
+        Some(match command {
+            Command::Build { pos, ty } => Revert::RemovePlans(vec![(*pos, *ty)]),
+            Command::BuildPlan { build_plan } => {
+                Revert::RemovePlans(build_plan.iter().map(|c| (c.pos, c.get_type())).collect())
+            }
+            Command::CancelBuild { pos } => {
+                self.constructions.iter().find(|c| c.pos == *pos)?;
+                Revert::ToggleCancel(*pos)
+            }
+            Command::Deconstruct { pos } => {
+                let (id, building) = self.buildings.items().find(|(_, b)| b.pos == *pos)?;
+                Revert::RestoreBuilding(id, Box::new(building.clone()))
+            }
+            Command::DeconstructConveyor { pos } => {
+                Revert::RestoreConveyor(*pos, self.tiles[*pos].conveyor)
+            }
+            Command::DeconstructPowerGrid { pos } => Revert::RestorePowerGrid(*pos),
+            Command::SetRecipe { pos, .. } => {
+                let building = self.buildings.iter().find(|b| b.intersects(*pos))?;
+                Revert::SetRecipe(*pos, building.recipe.as_ref().and_then(recipe_name))
+            }
+            _ => return None,
+        })
+    }
+
+    fn revert(&mut self, revert: &Revert) -> Result<(), String> {
+        match revert {
+            Revert::RemovePlans(plans) => {
+                let planned =
+                    |c: &Construction| !c.canceling() && plans.contains(&(c.pos, c.get_type()));
+                if self
+                    .constructions
+                    .iter()
+                    .any(|c| planned(&c) && 0. < c.progress())
+                {
+                    return Err(String::from("The construction has already started"));
+                }
+                for c in self.constructions.iter_mut() {
+                    // Let the crews take back the delivered ingredients
+                    if planned(c) && !c.ingredients.is_empty() {
+                        c.toggle_cancel();
+                    }
+                }
+                self.constructions
+                    .retain(|c| !planned(c) || !c.ingredients.is_empty());
+            }
+            Revert::ToggleCancel(pos) => self.cancel_build(pos[0], pos[1]),
+            Revert::RestoreBuilding(old_id, building) => {
+                self.remove_deconstruction(
+                    building.pos,
+                    ConstructionType::Building(building.type_),
+                )?;
+                let mut building = Building::clone(building);
+                building.expected_transports.clear();
+                let id = self.buildings.insert(building);
+                for crew in self.crews.iter_mut() {
+                    if crew.from == *old_id {
+                        crew.from = id;
+                    }
+                }
+            }
+            Revert::RestoreConveyor(pos, conveyor) => {
+                self.remove_deconstruction(*pos, ConstructionType::Conveyor(*conveyor))?;
+                if let Some(tile) = self.tiles.try_get_mut(*pos) {
+                    tile.conveyor = *conveyor;
+                }
+                self.changed_tiles.insert(*pos);
+            }
+            Revert::RestorePowerGrid(pos) => {
+                self.remove_deconstruction(*pos, ConstructionType::PowerGrid)?;
+                if let Some(tile) = self.tiles.try_get_mut(*pos) {
+                    tile.power_grid = true;
+                }
+            }
+            Revert::SetRecipe(pos, name) => self.set_recipe(pos[0], pos[1], name.as_deref())?,
+        }
+        Ok(())
+    }
+
+    /// Remove a deconstruction that has not made progress yet.
+    fn remove_deconstruction(&mut self, pos: Pos, ty: ConstructionType) -> Result<(), String> {
+        let (id, progress, time) = self
+            .constructions
+            .items()
+            .find(|(_, c)| c.pos == pos && c.canceling() && c.get_type() == ty)
+            .map(|(id, c)| (id, c.progress(), c.recipe.time))
+            .ok_or_else(|| String::from("The deconstruction does not exist"))?;
+        if progress < time {
+            return Err(String::from("The deconstruction has already started"));
+        }
+        self.constructions.remove(id);
+        Ok(())
+    }
+}
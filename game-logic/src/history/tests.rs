@@ -0,0 +1,163 @@
+use super::*;
+use crate::{building::BuildingType, direction::Direction, ItemType};
+
+fn building_pos(game: &AsteroidColoniesGame, ty: BuildingType) -> Pos {
+    game.iter_building().find(|b| b.type_ == ty).unwrap().pos
+}
+
+fn recipe_at(game: &AsteroidColoniesGame, pos: Pos) -> Option<String> {
+    game.iter_building()
+        .find(|b| b.pos == pos)
+        .unwrap()
+        .recipe
+        .as_ref()
+        .and_then(recipe_name)
+}
+
+#[test]
+fn test_undo_deconstruct() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let pos = building_pos(&game, BuildingType::MediumStorage);
+    game.apply_command(&Command::Deconstruct { pos }, None)
+        .unwrap();
+    assert!(game.iter_building().all(|b| b.pos != pos));
+
+    game.apply_command(&Command::Undo, None).unwrap();
+    let building = game.iter_building().find(|b| b.pos == pos).unwrap();
+    assert_eq!(building.inventory.get(&ItemType::ConveyorComponent), 20);
+    drop(building);
+    assert_eq!(game.iter_construction().count(), 0);
+    assert_eq!(
+        game.apply_command(&Command::Undo, None).unwrap_err(),
+        "Nothing to undo"
+    );
+
+    game.apply_command(&Command::Redo, None).unwrap();
+    assert!(game.iter_building().all(|b| b.pos != pos));
+    for c in game.constructions.iter_mut() {
+        c.progress -= 1.;
+    }
+    assert_eq!(
+        game.apply_command(&Command::Undo, None).unwrap_err(),
+        "The deconstruction has already started"
+    );
+    // The entry is kept to try again
+    assert!(game.history().can_undo(None));
+}
+
+#[test]
+fn test_undo_deconstruct_cabin() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.excavate(30, 50, None).unwrap();
+    game.tick().unwrap();
+    assert_eq!(game.iter_crew().count(), 1);
+    let pos = building_pos(&game, BuildingType::CrewCabin);
+    game.apply_command(&Command::Deconstruct { pos }, None)
+        .unwrap();
+    game.apply_command(&Command::Undo, None).unwrap();
+
+    // The crew out of the cabin still lives in it
+    let id = game
+        .buildings
+        .items()
+        .find(|(_, b)| b.pos == pos)
+        .unwrap()
+        .0;
+    assert!(game.iter_crew().all(|crew| crew.from == id));
+}
+
+#[test]
+fn test_undo_tiles() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let conv_pos = building_pos(&game, BuildingType::Storage);
+    let conv_pos = [conv_pos[0] - 2, conv_pos[1] + 1];
+    let conveyor = game.tiles()[conv_pos].conveyor;
+    assert!(conveyor.is_some());
+    game.apply_command(&Command::DeconstructConveyor { pos: conv_pos }, None)
+        .unwrap();
+    game.apply_command(&Command::DeconstructPowerGrid { pos: [26, 50] }, None)
+        .unwrap();
+    assert!(!game.tiles()[[26, 50]].power_grid);
+
+    game.apply_command(&Command::Undo, None).unwrap();
+    assert!(game.tiles()[[26, 50]].power_grid);
+    assert!(game.tiles()[conv_pos].conveyor.is_none());
+    game.apply_command(&Command::Undo, None).unwrap();
+    assert_eq!(game.tiles()[conv_pos].conveyor, conveyor);
+    assert_eq!(game.iter_construction().count(), 0);
+}
+
+#[test]
+fn test_undo_build_plan() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let conveyor = Conveyor::One(Direction::Left, Direction::Right);
+    let build_plan = vec![crate::construction::Construction::new_conveyor(
+        [26, 50],
+        conveyor,
+        0,
+        false,
+    )];
+    game.apply_command(&Command::BuildPlan { build_plan }, None)
+        .unwrap();
+    game.apply_command(&Command::Undo, None).unwrap();
+    assert_eq!(game.iter_construction().count(), 0);
+    game.apply_command(&Command::Redo, None).unwrap();
+    assert_eq!(game.iter_construction().count(), 1);
+
+    // A new command clears the commands to redo
+    game.apply_command(&Command::Undo, None).unwrap();
+    game.apply_command(&Command::CancelBuild { pos: [0, 0] }, None)
+        .unwrap();
+    let pos = building_pos(&game, BuildingType::Assembler);
+    game.apply_command(
+        &Command::SetRecipe {
+            pos,
+            name: Some("Gear".to_string()),
+        },
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        game.apply_command(&Command::Redo, None).unwrap_err(),
+        "Nothing to redo"
+    );
+}
+
+#[test]
+fn test_undo_recipe() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let pos = building_pos(&game, BuildingType::Assembler);
+    for _ in 0..MAX_HISTORY + 1 {
+        game.apply_command(
+            &Command::SetRecipe {
+                pos,
+                name: Some("Gear".to_string()),
+            },
+            None,
+        )
+        .unwrap();
+    }
+    assert_eq!(recipe_at(&game, pos).as_deref(), Some("Gear"));
+
+    // Other players have their own history
+    let player = Some(PlayerId(1));
+    assert!(!game.history().can_undo(player));
+    assert_eq!(
+        game.apply_command(&Command::Undo, player).unwrap_err(),
+        "Nothing to undo"
+    );
+
+    for _ in 0..MAX_HISTORY {
+        game.apply_command(&Command::Undo, None).unwrap();
+    }
+    assert!(!game.history().can_undo(None));
+    assert!(game.history().can_redo(None));
+    // The oldest command has been forgotten
+    assert_eq!(recipe_at(&game, pos).as_deref(), Some("Gear"));
+}
+
+#[test]
+fn test_command_json() {
+    let command: Command = serde_json::from_str(r#"{"type":"Undo"}"#).unwrap();
+    assert!(matches!(command, Command::Undo));
+}
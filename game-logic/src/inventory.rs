@@ -84,7 +84,10 @@ impl Inventory {
         self.countable.get_mut(ty)
     }
 
-    pub fn entry(&mut self, ty: ItemType) -> std::collections::btree_map::Entry<ItemType, usize> {
+    pub fn entry(
+        &mut self,
+        ty: ItemType,
+    ) -> std::collections::btree_map::Entry<'_, ItemType, usize> {
         self.countable.entry(ty)
     }
 
@@ -104,7 +107,7 @@ impl Inventory {
         self.countable.is_empty()
     }
 
-    pub fn keys(&self) -> std::collections::btree_map::Keys<ItemType, usize> {
+    pub fn keys(&self) -> std::collections::btree_map::Keys<'_, ItemType, usize> {
         self.countable.keys()
     }
 
@@ -112,6 +115,14 @@ impl Inventory {
         self.countable.insert(key, value)
     }
 
+    /// Add all the items and ores in `other`.
+    pub fn merge(&mut self, other: &Inventory) {
+        for (item, amount) in other {
+            *self.countable.entry(*item).or_default() += *amount;
+        }
+        self.add_ores(&other.ores);
+    }
+
     pub fn add_ores(&mut self, ores: &OreAccum) {
         self.ores.cilicate += ores.cilicate;
         self.ores.iron += ores.iron;
@@ -0,0 +1,51 @@
+//! Items dropped on tiles when they have nowhere to go
+#[cfg(test)]
+mod tests;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::{EntitySet, RefOption},
+    inventory::Inventory,
+    AsteroidColoniesGame, Pos,
+};
+
+/// Items lying on a tile, because they could not be delivered or returned, or a deconstruction
+/// had no storage to put them in. Crews pick them up with the cleanup task.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ItemPile {
+    pub pos: Pos,
+    pub inventory: Inventory,
+}
+
+impl ItemPile {
+    pub fn is_empty(&self) -> bool {
+        self.inventory.is_empty() && self.inventory.ores().is_empty()
+    }
+}
+
+/// Drop the items on the tile, adding them to the pile already there if any.
+pub(crate) fn drop_items(item_piles: &mut EntitySet<ItemPile>, pos: Pos, inventory: &Inventory) {
+    if let Some(pile) = item_piles.iter_mut().find(|p| p.pos == pos) {
+        pile.inventory.merge(inventory);
+        return;
+    }
+    let pile = ItemPile {
+        pos,
+        inventory: inventory.clone(),
+    };
+    if !pile.is_empty() {
+        item_piles.insert(pile);
+    }
+}
+
+impl AsteroidColoniesGame {
+    pub fn iter_item_pile(&self) -> impl Iterator<Item = RefOption<'_, ItemPile>> {
+        self.item_piles.iter()
+    }
+
+    /// The pile of items on the tile, if any.
+    pub fn item_pile_at(&self, pos: Pos) -> Option<RefOption<'_, ItemPile>> {
+        self.item_piles.iter().find(|p| p.pos == pos)
+    }
+}
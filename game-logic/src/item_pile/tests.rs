@@ -0,0 +1,133 @@
+use super::*;
+use crate::{
+    building::{Building, BuildingType, OreAccum},
+    tile::{TileState, Tiles},
+    ItemType,
+};
+
+#[test]
+fn test_drop_items() {
+    let mut piles = EntitySet::new();
+    drop_items(&mut piles, [1, 2], &Inventory::new());
+    assert_eq!(piles.len(), 0);
+
+    drop_items(&mut piles, [1, 2], &[(ItemType::Gear, 2)].into());
+    drop_items(
+        &mut piles,
+        [1, 2],
+        &[(ItemType::Gear, 1), (ItemType::Wire, 3)].into(),
+    );
+    drop_items(&mut piles, [2, 2], &[(ItemType::Wire, 1)].into());
+    assert_eq!(piles.len(), 2);
+    let pile = piles.iter().find(|p| p.pos == [1, 2]).unwrap();
+    assert_eq!(pile.inventory.get(&ItemType::Gear), 3);
+    assert_eq!(pile.inventory.get(&ItemType::Wire), 3);
+}
+
+#[test]
+fn test_deconstruct_without_storage() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let pos = game
+        .iter_building()
+        .find(|b| b.type_ == BuildingType::MediumStorage)
+        .unwrap()
+        .pos;
+    game.deconstruct(pos[0], pos[1]).unwrap();
+    game.buildings.retain(|b| !b.type_.is_storage());
+    for c in game.constructions.iter_mut() {
+        c.progress = 0.;
+    }
+    game.process_constructions();
+
+    assert_eq!(game.iter_construction().count(), 0);
+    let pile = game.item_pile_at(pos).unwrap();
+    assert_eq!(pile.inventory.get(&ItemType::ConveyorComponent), 20);
+    assert_eq!(pile.inventory.get(&ItemType::PowerGridComponent), 2);
+}
+
+#[test]
+fn test_cleanup_item_pile() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let cabin = game
+        .iter_building()
+        .find(|b| b.type_ == BuildingType::CrewCabin)
+        .unwrap()
+        .pos;
+    // A conveyor tile next to the cabin
+    let pos = [cabin[0] + 2, cabin[1] - 1];
+    drop_items(&mut game.item_piles, pos, &[(ItemType::Gear, 2)].into());
+    game.cleanup_item(pos, None).unwrap();
+    for _ in 0..100 {
+        game.tick().unwrap();
+    }
+
+    assert!(game.item_pile_at(pos).is_none());
+    assert_eq!(game.iter_global_task().count(), 0);
+    let gears: usize = game
+        .iter_building()
+        .map(|b| b.inventory.get(&ItemType::Gear))
+        .sum();
+    assert_eq!(gears, 2);
+}
+
+#[test]
+fn test_cleanup_ore_pile() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let cabin = game
+        .iter_building()
+        .find(|b| b.type_ == BuildingType::CrewCabin)
+        .unwrap()
+        .pos;
+    let pos = [cabin[0] + 2, cabin[1] - 1];
+    let mut ores = Inventory::new();
+    ores.add_ores(&OreAccum {
+        iron: 3.,
+        ..OreAccum::default()
+    });
+    drop_items(&mut game.item_piles, pos, &ores);
+    game.cleanup_item(pos, None).unwrap();
+    game.tick().unwrap();
+    assert_eq!(game.iter_crew().count(), 1);
+    // Check the cabin as soon as the crew is back, before a furnace pulls the ores from it
+    for _ in 0..100 {
+        game.tick().unwrap();
+        if game.iter_crew().count() == 0 {
+            break;
+        }
+    }
+
+    // The crew brings the ores back to the cabin
+    assert!(game.item_pile_at(pos).is_none());
+    assert_eq!(game.iter_global_task().count(), 0);
+    assert_eq!(game.iter_crew().count(), 0);
+    let cabin = game
+        .iter_building()
+        .find(|b| b.type_ == BuildingType::CrewCabin)
+        .unwrap();
+    assert_eq!(cabin.crews, 4);
+    assert_eq!(cabin.inventory.ores().iron, 3.);
+}
+
+#[test]
+fn test_deconstruct_unreachable_storage() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let pos = game
+        .iter_building()
+        .find(|b| b.type_ == BuildingType::MediumStorage)
+        .unwrap()
+        .pos;
+    game.deconstruct(pos[0], pos[1]).unwrap();
+    game.buildings.retain(|b| !b.type_.is_storage());
+    // A storage with space, but behind the rock
+    game.tiles = Tiles::new();
+    game.tiles[pos].state = TileState::Empty;
+    game.buildings
+        .insert(Building::new([pos[0] + 3, pos[1]], BuildingType::Storage));
+    for c in game.constructions.iter_mut() {
+        c.progress = 0.;
+    }
+    game.process_constructions();
+
+    assert_eq!(game.iter_construction().count(), 0);
+    assert!(game.item_pile_at(pos).is_some());
+}
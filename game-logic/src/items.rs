@@ -1,6 +1,6 @@
 use ::serde::{Deserialize, Serialize};
 
-use crate::{hash_map, Recipe};
+use crate::{ruleset::ruleset, Recipe};
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
 pub enum ItemType {
@@ -17,47 +17,43 @@ pub enum ItemType {
     PowerGridComponent,
     ConveyorComponent,
     AssemblerComponent,
+    /// Breathing air for crews
+    Oxygen,
+    Food,
 }
 
-static RECIPES: std::sync::OnceLock<Vec<Recipe>> = std::sync::OnceLock::new();
-pub(crate) fn recipes() -> &'static [Recipe] {
-    RECIPES.get_or_init(|| {
-        vec![
-            Recipe {
-                inputs: hash_map!(ItemType::Wire => 1, ItemType::IronIngot => 1),
-                outputs: hash_map!(ItemType::PowerGridComponent => 1),
-                time: 100.,
-            },
-            Recipe {
-                inputs: hash_map!(ItemType::IronIngot => 1),
-                outputs: hash_map!(ItemType::ConveyorComponent => 1),
-                time: 120.,
-            },
-            Recipe {
-                inputs: hash_map!(ItemType::IronIngot => 1),
-                outputs: hash_map!(ItemType::Gear => 2),
-                time: 70.,
-            },
-            Recipe {
-                inputs: hash_map!(ItemType::CopperIngot => 1),
-                outputs: hash_map!(ItemType::Wire => 2),
-                time: 50.,
-            },
-            Recipe {
-                inputs: hash_map!(ItemType::Wire => 1, ItemType::IronIngot => 1),
-                outputs: hash_map!(ItemType::Circuit => 1),
-                time: 120.,
-            },
-            Recipe {
-                inputs: hash_map!(ItemType::Gear => 2, ItemType::Circuit => 2),
-                outputs: hash_map!(ItemType::AssemblerComponent => 1),
-                time: 200.,
-            },
-            Recipe {
-                inputs: hash_map!(ItemType::Wire => 1, ItemType::LithiumIngot => 1),
-                outputs: hash_map!(ItemType::Battery => 1),
-                time: 150.,
-            },
+impl ItemType {
+    pub(crate) fn all() -> [Self; 14] {
+        [
+            Self::RawOre,
+            Self::IronIngot,
+            Self::CopperIngot,
+            Self::LithiumIngot,
+            Self::Cilicate,
+            Self::Gear,
+            Self::Wire,
+            Self::Circuit,
+            Self::Battery,
+            Self::PowerGridComponent,
+            Self::ConveyorComponent,
+            Self::AssemblerComponent,
+            Self::Oxygen,
+            Self::Food,
         ]
-    })
+    }
+}
+
+pub(crate) fn recipes() -> &'static [Recipe] {
+    &ruleset().recipes
+}
+
+/// The name of a recipe, which is the name of its first output.
+pub(crate) fn recipe_name(recipe: &Recipe) -> Option<String> {
+    recipe.outputs.keys().next().map(|key| format!("{:?}", key))
+}
+
+pub(crate) fn find_recipe(name: &str) -> Option<&'static Recipe> {
+    recipes()
+        .iter()
+        .find(|recipe| recipe_name(recipe).as_deref() == Some(name))
 }
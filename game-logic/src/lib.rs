@@ -1,28 +1,63 @@
 pub use crate::{
+    blueprint::{Blueprint, BlueprintBuilding},
     building::Recipe,
     construction::get_build_menu,
     conveyor::Conveyor,
     crew::Crew,
     direction::Direction,
-    game::{AsteroidColoniesGame, SerializeGame},
+    filter::{ItemSet, SplitterFilter},
+    game::{AsteroidColoniesGame, CalculateBackImage, SerializeGame},
+    history::{CommandHistory, MAX_HISTORY},
     inventory::{CountableInventory, Inventory},
+    item_pile::ItemPile,
     items::ItemType,
+    life_support::CrewNeeds,
+    lockstep::{Command, PlayerCommand, Step, StepMessage},
+    logistics::{Logistics, LogisticsMode},
+    migration::{migrate_save, save_version, SAVE_VERSION},
+    player::PlayerId,
+    power::{PowerNetwork, PowerNetworkId, PowerNetworks},
+    research::{Research, ResearchProject, ResearchStatus, Unlock, Upgrade},
+    ruleset::{
+        default_ruleset, load_ruleset, ruleset, BuildCost, BuildingStats, ConveyorTierStats,
+        Ruleset,
+    },
+    skills::{CabinSkills, CrewSkills, Job},
+    state_hash::StateHash,
+    stats::{ProductionStats, StatsCounters, StatsWindow, STATS_BUCKETS},
+    sync::{EntitiesDigest, SyncAck},
     tile::{new_hasher, Chunk, ImageIdx, Position, Tile, TileState, Tiles, CHUNK_SIZE},
-    transport::{Transport, TransportPayload},
+    transport::{Transport, TransportPayload, SLOTS_PER_TILE, TILE_LENGTH},
     xor128::Xor128,
 };
 
+mod blueprint;
 pub mod building;
 pub mod construction;
 pub mod conveyor;
 mod crew;
 mod direction;
 mod entity;
+mod filter;
 mod game;
+mod history;
 mod inventory;
+mod item_pile;
 mod items;
+mod life_support;
+mod lockstep;
+mod logistics;
+mod migration;
 pub mod perlin_noise;
+mod player;
+mod power;
 mod push_pull;
+mod research;
+mod ruleset;
+mod skills;
+mod state_hash;
+mod stats;
+mod sync;
 pub mod task;
 mod tile;
 mod transport;
@@ -80,9 +115,10 @@ extern "C" {
     pub fn log(s: &str);
 }
 
+/// Diagnostics go to stderr, so that they do not mix with the output of command line tools.
 #[cfg(not(target_family = "wasm"))]
 pub fn log(s: &str) {
-    println!("{}", s);
+    eprintln!("{}", s);
 }
 
 pub const TILE_SIZE: f64 = 32.;
@@ -0,0 +1,100 @@
+//! Needs of crews for oxygen, food and rest, which crew cabins supply.
+//!
+//! A cabin consumes oxygen and food items from its inventory to keep the levels up, and crews
+//! rest while they are at home in a powered cabin. Crews work slower when any of the levels is
+//! low, but they never stop working, so that the colony can build what it lacks.
+#[cfg(test)]
+mod tests;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{items::ItemType, AsteroidColoniesGame};
+
+/// Oxygen that a crew breathes per tick
+const OXYGEN_CONSUMPTION: f64 = 1. / 8000.;
+/// Food that a crew eats per tick
+const FOOD_CONSUMPTION: f64 = 1. / 16000.;
+/// Rest that a crew loses per tick while it is out of the cabin
+const FATIGUE: f64 = 1. / 4000.;
+/// Rest that a crew recovers per tick in a fully powered cabin
+const REST_RECOVERY: f64 = 1. / 400.;
+/// The level that an oxygen or food item restores
+const SUPPLY_PER_ITEM: f64 = 0.25;
+/// Crews still work a little even if their needs are not met at all, so the colony can recover
+const MIN_EFFICIENCY: f64 = 0.1;
+
+/// The amount of supplies that a crew cabin keeps in its inventory
+pub(crate) const SUPPLY_STOCK: [(ItemType, usize); 2] =
+    [(ItemType::Oxygen, 4), (ItemType::Food, 4)];
+
+/// Levels of the needs of the crews in a cabin, each between 0 and 1.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CrewNeeds {
+    pub oxygen: f64,
+    pub food: f64,
+    pub rest: f64,
+}
+
+impl CrewNeeds {
+    pub fn new() -> Self {
+        Self {
+            oxygen: 1.,
+            food: 1.,
+            rest: 1.,
+        }
+    }
+
+    pub fn min_level(&self) -> f64 {
+        self.oxygen.min(self.food).min(self.rest)
+    }
+
+    /// The multiplier to the working speed of the crews. They work at full speed as long as all
+    /// the levels are at least half.
+    pub fn efficiency(&self) -> f64 {
+        (2. * self.min_level()).clamp(MIN_EFFICIENCY, 1.)
+    }
+}
+
+impl Default for CrewNeeds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsteroidColoniesGame {
+    pub(super) fn process_life_support(&mut self) {
+        for (id, building) in self.buildings.items_mut() {
+            if !building.type_.is_cabin() {
+                continue;
+            }
+            let needs = building.needs.get_or_insert_with(CrewNeeds::new);
+            let population = building.population;
+            if population == 0 {
+                continue;
+            }
+            let home = building.crews.min(population) as f64;
+            let away = (population - building.crews.min(population)) as f64;
+            needs.oxygen -= OXYGEN_CONSUMPTION * population as f64;
+            needs.food -= FOOD_CONSUMPTION * population as f64;
+            // Resting needs the cabin to be powered
+            let power_ratio = self.power_networks.power_ratio(id);
+            needs.rest += (REST_RECOVERY * home * power_ratio - FATIGUE * away) / population as f64;
+
+            for (item, level) in [
+                (ItemType::Oxygen, &mut needs.oxygen),
+                (ItemType::Food, &mut needs.food),
+            ] {
+                while *level <= 1. - SUPPLY_PER_ITEM {
+                    let Some(count) = building.inventory.get_mut(&item).filter(|c| 0 < **c) else {
+                        break;
+                    };
+                    *count -= 1;
+                    *level += SUPPLY_PER_ITEM;
+                    self.production_stats.consume(Some(building.type_), item, 1);
+                }
+                *level = level.clamp(0., 1.);
+            }
+            needs.rest = needs.rest.clamp(0., 1.);
+        }
+    }
+}
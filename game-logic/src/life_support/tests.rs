@@ -0,0 +1,108 @@
+use super::*;
+use crate::{
+    btree_map,
+    building::{Building, BuildingType},
+    stats::StatsWindow,
+};
+
+fn cabin(game: &mut AsteroidColoniesGame) -> &mut Building {
+    game.buildings
+        .iter_mut()
+        .find(|b| b.type_ == BuildingType::CrewCabin)
+        .unwrap()
+}
+
+#[test]
+fn test_efficiency() {
+    let mut needs = CrewNeeds::new();
+    assert_eq!(needs.efficiency(), 1.);
+    needs.food = 0.25;
+    assert_eq!(needs.efficiency(), 0.5);
+    needs.rest = 0.;
+    assert_eq!(needs.efficiency(), MIN_EFFICIENCY);
+}
+
+#[test]
+fn test_consume_supplies() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    assert_eq!(cabin(&mut game).needs, Some(CrewNeeds::new()));
+    cabin(&mut game).needs.as_mut().unwrap().oxygen = 0.6;
+    game.tick().unwrap();
+
+    let cabin = cabin(&mut game);
+    assert_eq!(cabin.inventory.get(&ItemType::Oxygen), 9);
+    assert_eq!(cabin.inventory.get(&ItemType::Food), 10);
+    let needs = cabin.needs.unwrap();
+    assert!(0.8 < needs.oxygen && needs.oxygen < 0.85);
+    assert!(needs.food < 1.);
+    let total = game.production_stats().total(StatsWindow::Day);
+    assert_eq!(
+        total.consumed_by[&BuildingType::CrewCabin][&ItemType::Oxygen],
+        1
+    );
+}
+
+#[test]
+fn test_dispatch_without_supplies() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let cabin_mut = cabin(&mut game);
+    cabin_mut.inventory.remove(&ItemType::Oxygen);
+    cabin_mut.inventory.remove(&ItemType::Food);
+    cabin_mut.needs = Some(CrewNeeds {
+        oxygen: 0.,
+        food: 0.,
+        rest: 0.,
+    });
+    let storage = game
+        .buildings
+        .iter_mut()
+        .find(|b| b.type_ == BuildingType::MediumStorage)
+        .unwrap();
+    for (item, count) in [
+        (ItemType::IronIngot, 2),
+        (ItemType::Wire, 2),
+        (ItemType::Circuit, 1),
+    ] {
+        *storage.inventory.entry(item).or_default() += count;
+    }
+    game.build(27, 52, BuildingType::OxygenGenerator, None)
+        .unwrap();
+
+    // Crews work slowly, but they can still build the generator that the colony needs
+    for _ in 0..5000 {
+        game.tick().unwrap();
+        if game
+            .iter_building()
+            .any(|b| b.type_ == BuildingType::OxygenGenerator)
+        {
+            return;
+        }
+    }
+    panic!("The oxygen generator was not built");
+}
+
+#[test]
+fn test_oxygen_generator() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    // Turn the assembler into an oxygen generator, since it is powered
+    let generator = game
+        .buildings
+        .iter_mut()
+        .find(|b| b.type_ == BuildingType::Assembler)
+        .unwrap();
+    let pos = generator.pos;
+    *generator = Building::new_inventory(
+        pos,
+        BuildingType::OxygenGenerator,
+        btree_map!(ItemType::Cilicate => 1).into(),
+    );
+    assert!(game.set_recipe(pos[0], pos[1], Some("Gear")).is_err());
+    for _ in 0..310 {
+        game.tick().unwrap();
+    }
+    let total = game.production_stats().total(StatsWindow::Day);
+    assert_eq!(
+        total.produced_by[&BuildingType::OxygenGenerator][&ItemType::Oxygen],
+        2
+    );
+}
@@ -0,0 +1,251 @@
+//! Lockstep replication of the game.
+//!
+//! The game logic is deterministic, so instead of sending the state, the server can broadcast
+//! the commands that it accepted, tagged with the tick they are applied on, and the clients
+//! simulate the same ticks locally. State hashes are attached periodically, so that the clients
+//! can detect desyncs and ask the server for the whole state.
+#[cfg(test)]
+mod tests;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    console_log,
+    construction::{Construction, ConstructionType},
+    filter::{ItemSet, SplitterFilter},
+    items::ItemType,
+    logistics::Logistics,
+    player::PlayerId,
+    skills::Job,
+    state_hash::StateHash,
+    AsteroidColoniesGame, Pos,
+};
+
+/// A command that players can issue to change the game.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload")]
+pub enum Command {
+    Excavate {
+        x: i32,
+        y: i32,
+    },
+    Move {
+        from: Pos,
+        to: Pos,
+    },
+    MoveItem {
+        from: Pos,
+        to: Pos,
+        item: ItemType,
+    },
+    Build {
+        pos: Pos,
+        #[serde(rename = "type")]
+        ty: ConstructionType,
+    },
+    BuildPlan {
+        build_plan: Vec<Construction>,
+    },
+    CancelBuild {
+        pos: Pos,
+    },
+    Deconstruct {
+        pos: Pos,
+    },
+    DeconstructConveyor {
+        pos: Pos,
+    },
+    DeconstructPowerGrid {
+        pos: Pos,
+    },
+    SetRecipe {
+        pos: Pos,
+        name: Option<String>,
+    },
+    Cleanup {
+        pos: Pos,
+    },
+    SetResearch {
+        name: Option<String>,
+    },
+    /// Set the priority of a job for the crews of a cabin
+    SetJobPriority {
+        pos: Pos,
+        job: Job,
+        priority: u8,
+    },
+    /// Set the priority of a job for one of the crews of a cabin, or let it follow the cabin
+    /// with `None`
+    SetCrewJobPriority {
+        pos: Pos,
+        crew: usize,
+        job: Job,
+        priority: Option<u8>,
+    },
+    /// Set the priority of a construction plan
+    SetConstructionPriority {
+        pos: Pos,
+        priority: u8,
+    },
+    /// Pause or resume a construction plan
+    PauseConstruction {
+        pos: Pos,
+        paused: bool,
+    },
+    /// Set or clear the item filter of a splitter
+    SetSplitterFilter {
+        pos: Pos,
+        filter: Option<SplitterFilter>,
+    },
+    /// Set or clear the items that a building pushes out
+    SetOutputFilter {
+        pos: Pos,
+        items: Option<ItemSet>,
+    },
+    /// Make a storage a provider or a requester of items, or clear the setting
+    SetLogistics {
+        pos: Pos,
+        logistics: Option<Logistics>,
+    },
+    /// Revert the last build, deconstruct, cancel or recipe command of the player
+    Undo,
+    /// Apply the last undone command of the player again
+    Redo,
+}
+
+/// A command with the player who issued it, which becomes the owner of the created entities.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerCommand {
+    pub owner: Option<PlayerId>,
+    pub command: Command,
+}
+
+/// The commands applied before a tick, which the server broadcasts in lockstep mode.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Step {
+    /// The global time when the commands are applied, before ticking.
+    pub tick: usize,
+    pub commands: Vec<PlayerCommand>,
+    /// The state hash after the tick, attached periodically.
+    pub state_hash: Option<StateHash>,
+}
+
+/// The text message that carries a [`Step`] on the websocket.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename = "step")]
+pub struct StepMessage {
+    pub payload: Step,
+}
+
+impl StepMessage {
+    pub fn from_json(msg: &str) -> Result<Self, String> {
+        serde_json::from_str(msg).map_err(|e| format!("{e}"))
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| format!("{e}"))
+    }
+}
+
+impl AsteroidColoniesGame {
+    pub fn apply_command(
+        &mut self,
+        command: &Command,
+        owner: Option<PlayerId>,
+    ) -> Result<(), String> {
+        match command {
+            Command::Undo => self.undo(owner),
+            Command::Redo => self.redo(owner),
+            _ => self.apply_recorded(command, owner, true),
+        }
+    }
+
+    pub(crate) fn execute_command(
+        &mut self,
+        command: &Command,
+        owner: Option<PlayerId>,
+    ) -> Result<(), String> {
+        match command {
+            Command::Excavate { x, y } => {
+                self.excavate(*x, *y, owner)?;
+            }
+            Command::Move { from, to } => self.move_building(*from, *to)?,
+            Command::MoveItem { from, to, item } => self.move_item(*from, *to, *item)?,
+            Command::Build { pos, ty } => match ty {
+                ConstructionType::Building(ty) => self.build(pos[0], pos[1], *ty, owner)?,
+                ConstructionType::PowerGrid => {
+                    self.build_power_grid(pos[0], pos[1])?;
+                }
+                _ => return Err("Invalid build type".to_string()),
+            },
+            Command::BuildPlan { build_plan } => self.build_plan(build_plan, owner),
+            Command::CancelBuild { pos } => self.cancel_build(pos[0], pos[1]),
+            Command::Deconstruct { pos } => self.deconstruct(pos[0], pos[1])?,
+            Command::DeconstructConveyor { pos } => self.deconstruct_conveyor(pos[0], pos[1])?,
+            Command::DeconstructPowerGrid { pos } => self.deconstruct_power_grid(pos[0], pos[1])?,
+            Command::SetRecipe { pos, name } => self.set_recipe(pos[0], pos[1], name.as_deref())?,
+            Command::Cleanup { pos } => self.cleanup_item(*pos, owner)?,
+            Command::SetResearch { name } => self.set_research(name.as_deref())?,
+            Command::SetJobPriority { pos, job, priority } => {
+                self.set_job_priority(*pos, *job, *priority)?
+            }
+            Command::SetCrewJobPriority {
+                pos,
+                crew,
+                job,
+                priority,
+            } => self.set_crew_job_priority(*pos, *crew, *job, *priority)?,
+            Command::SetConstructionPriority { pos, priority } => {
+                self.set_construction_priority(*pos, *priority)?
+            }
+            Command::PauseConstruction { pos, paused } => self.pause_construction(*pos, *paused)?,
+            Command::SetSplitterFilter { pos, filter } => {
+                self.set_splitter_filter(*pos, *filter)?
+            }
+            Command::SetOutputFilter { pos, items } => self.set_output_filter(*pos, *items)?,
+            Command::SetLogistics { pos, logistics } => {
+                self.set_logistics(*pos, logistics.clone())?
+            }
+            Command::Undo | Command::Redo => {
+                return Err(String::from("Undo and redo cannot be recorded"))
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a step from the server and advance a tick. Steps older than the current time are
+    /// already reflected in the state and ignored.
+    ///
+    /// It returns an error if a step was missed or the state hash does not match, in which case
+    /// the client should ask the server to resync.
+    pub fn apply_step(&mut self, step: &Step) -> Result<(), String> {
+        if step.tick < self.global_time {
+            return Ok(());
+        }
+        if self.global_time < step.tick {
+            return Err(format!(
+                "Missed steps from {} to {}",
+                self.global_time, step.tick
+            ));
+        }
+        for command in &step.commands {
+            // The server has accepted the command, so it should succeed unless we are desynced,
+            // which the state hash will tell.
+            if let Err(e) = self.apply_command(&command.command, command.owner) {
+                console_log!("Command {:?} failed: {e}", command.command);
+            }
+        }
+        self.tick()?;
+        if let Some(state_hash) = step.state_hash {
+            let diverged = state_hash.diverged(&self.state_hash());
+            if !diverged.is_empty() {
+                return Err(format!(
+                    "State diverged at tick {} in: {}",
+                    step.tick,
+                    diverged.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use super::*;
+
+fn state(game: &AsteroidColoniesGame) -> serde_json::Value {
+    serde_json::from_str(&game.serialize(false).unwrap()).unwrap()
+}
+
+/// Run the server side of a step: apply the commands as they arrive and tick.
+fn server_step(server: &mut AsteroidColoniesGame, commands: Vec<PlayerCommand>) -> Step {
+    let tick = server.get_global_time();
+    let commands = commands
+        .into_iter()
+        .filter(|c| server.apply_command(&c.command, c.owner).is_ok())
+        .collect();
+    server.tick().unwrap();
+    Step {
+        tick,
+        commands,
+        state_hash: Some(server.state_hash()),
+    }
+}
+
+#[test]
+fn test_lockstep() {
+    let mut server = AsteroidColoniesGame::new(None).unwrap();
+    let mut client = AsteroidColoniesGame::new(None).unwrap();
+    let owner = Some(PlayerId(1));
+    for i in 0..30 {
+        let commands = match i {
+            1 => vec![PlayerCommand {
+                owner,
+                command: Command::Excavate { x: 30, y: 50 },
+            }],
+            // This one fails because the tile is already empty, and is not sent to the clients
+            2 => vec![PlayerCommand {
+                owner,
+                command: Command::Excavate { x: 26, y: 50 },
+            }],
+            _ => vec![],
+        };
+        let step = server_step(&mut server, commands);
+        if i == 2 {
+            assert!(step.commands.is_empty());
+        }
+        client.apply_step(&step).unwrap();
+    }
+    assert_eq!(state(&client), state(&server));
+    assert!(server.iter_global_task().any(|t| t.owner() == owner));
+
+    // Old steps are ignored, and a missing step is an error
+    let step = server_step(&mut server, vec![]);
+    let old = Step {
+        tick: 0,
+        ..step.clone()
+    };
+    client.apply_step(&old).unwrap();
+    let step2 = server_step(&mut server, vec![]);
+    assert_eq!(
+        client.apply_step(&step2).unwrap_err(),
+        format!("Missed steps from {} to {}", step.tick, step2.tick)
+    );
+}
+
+#[test]
+fn test_lockstep_desync() {
+    let mut server = AsteroidColoniesGame::new(None).unwrap();
+    let mut client = AsteroidColoniesGame::new(None).unwrap();
+    // A command that the client applied locally without going through the server
+    client.excavate(30, 50, None).unwrap();
+    for _ in 0..100 {
+        let step = server_step(&mut server, vec![]);
+        if let Err(e) = client.apply_step(&step) {
+            let prefix = format!("State diverged at tick {} in: ", step.tick);
+            assert!(e.starts_with(&prefix), "{e}");
+            assert!(e.contains("global_tasks"), "{e}");
+            return;
+        }
+    }
+    panic!("Desync was not detected");
+}
@@ -0,0 +1,157 @@
+//! Logistics requests and provisions, which balance items between storages.
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    building::Building,
+    crew::{expected_crew_deliveries, expected_crew_pickups, Crew},
+    entity::EntitySet,
+    items::ItemType,
+    push_pull::{find_item_path, rect_iter},
+    transport::{expected_deliveries, send_items, Transport},
+    AsteroidColoniesGame, Pos,
+};
+
+/// Ticks between matching requesters with providers over conveyors. It finds a path for each
+/// pair of them, which is too expensive to do every tick.
+pub(crate) const LOGISTICS_INTERVAL: usize = 10;
+
+/// The role of a storage in the logistics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogisticsMode {
+    /// Gives away the items above the target counts to requesters.
+    Provider,
+    /// Receives items from providers until they reach the target counts.
+    Requester,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Logistics {
+    pub mode: LogisticsMode,
+    /// The counts of items to keep in the inventory. A provider gives away all of the items
+    /// without a target, while a requester requests only the items with a target.
+    pub targets: BTreeMap<ItemType, usize>,
+}
+
+impl Logistics {
+    pub fn target(&self, item: &ItemType) -> usize {
+        self.targets.get(item).copied().unwrap_or(0)
+    }
+}
+
+impl Building {
+    /// The number of the item that this requester still needs, counting the ones on the way by
+    /// conveyors or crews, limited by the vacancy of the storage.
+    pub(crate) fn logistics_demand(
+        &self,
+        item: ItemType,
+        transports: &EntitySet<Transport>,
+        crews: &EntitySet<Crew>,
+    ) -> usize {
+        let Some(logistics) = self
+            .logistics
+            .as_ref()
+            .filter(|l| l.mode == LogisticsMode::Requester)
+        else {
+            return 0;
+        };
+        let expected = expected_deliveries(transports, &self.expected_transports);
+        let crew_expected = expected_crew_deliveries(crews, self.pos);
+        let count = |map: &HashMap<ItemType, usize>| map.get(&item).copied().unwrap_or(0);
+        let current = self.inventory.get(&item) + count(&expected) + count(&crew_expected);
+        let occupied = self.inventory_size()
+            + expected.values().sum::<usize>()
+            + crew_expected.values().sum::<usize>();
+        logistics
+            .target(&item)
+            .saturating_sub(current)
+            .min(self.type_.capacity().saturating_sub(occupied))
+    }
+
+    /// The number of the item that this provider can give away, except the ones that crews are
+    /// going to pick up.
+    pub(crate) fn logistics_supply(&self, item: ItemType, crews: &EntitySet<Crew>) -> usize {
+        let Some(logistics) = self
+            .logistics
+            .as_ref()
+            .filter(|l| l.mode == LogisticsMode::Provider)
+        else {
+            return 0;
+        };
+        let pickups: usize = rect_iter(self.pos, self.type_.size())
+            .filter_map(|pos| expected_crew_pickups(crews, pos).get(&item).copied())
+            .sum();
+        self.inventory
+            .get(&item)
+            .saturating_sub(logistics.target(&item) + pickups)
+    }
+}
+
+impl AsteroidColoniesGame {
+    /// Make the storage at the position a provider or a requester, or clear the setting.
+    pub fn set_logistics(&mut self, pos: Pos, logistics: Option<Logistics>) -> Result<(), String> {
+        let Some(building) = self.buildings.iter_mut().find(|b| b.intersects(pos)) else {
+            return Err("The building does not exist at the target".to_string());
+        };
+        if logistics.is_some() && !building.type_.is_storage() {
+            return Err("Only storages can provide or request items".to_string());
+        }
+        building.logistics = logistics;
+        Ok(())
+    }
+
+    /// Send items from providers to requesters over conveyors. Crews haul the items that cannot
+    /// be sent, when they are dispatched from cabins. It runs every [`LOGISTICS_INTERVAL`] ticks.
+    pub(super) fn process_logistics(&mut self) {
+        for mut requester in self.buildings.iter_borrow_mut() {
+            let Some(logistics) = &requester.logistics else {
+                continue;
+            };
+            let items: Vec<_> = logistics.targets.keys().copied().collect();
+            for item in items {
+                let mut demand = requester.logistics_demand(item, &self.transports, &self.crews);
+                for mut provider in self.buildings.iter_borrow_mut() {
+                    if demand == 0 {
+                        break;
+                    }
+                    let supply = provider.logistics_supply(item, &self.crews);
+                    if supply == 0 {
+                        continue;
+                    }
+                    let Some(path) = find_item_path(
+                        &self.tiles,
+                        item,
+                        provider.pos,
+                        provider.type_.size(),
+                        requester.pos,
+                        requester.type_.size(),
+                    ) else {
+                        continue;
+                    };
+                    // Each item takes its own slot on the conveyors
+                    let amount = supply.min(demand);
+                    let ids = send_items(
+                        &mut self.transports,
+                        provider.pos,
+                        requester.pos,
+                        item,
+                        amount,
+                        path,
+                    );
+                    requester.expected_transports.extend(ids);
+                    let count = provider.inventory.entry(item).or_default();
+                    if *count <= amount {
+                        provider.inventory.remove(&item);
+                    } else {
+                        *count -= amount;
+                    }
+                    demand -= amount;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use super::*;
+use crate::{
+    building::BuildingType,
+    conveyor::Conveyor,
+    direction::Direction,
+    inventory::Inventory,
+    tile::{Tile, TileState, Tiles},
+    transport::TransportPayload,
+};
+
+/// A game with a provider storage at [-1, 0] holding 5 gears and keeping 2 of them, and a
+/// requester storage at [4, 0] requesting 10 of them. They are connected with a conveyor if
+/// `conveyor` is true. The floor around them is empty for crews to walk.
+fn logistics_game(conveyor: bool) -> AsteroidColoniesGame {
+    use Direction::*;
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.tiles = Tiles::new();
+    game.buildings = EntitySet::new();
+    game.constructions = EntitySet::new();
+    game.transports = EntitySet::new();
+    game.crews = EntitySet::new();
+    for y in 0..4 {
+        for x in -1..5 {
+            game.tiles[[x, y]].state = TileState::Empty;
+        }
+    }
+    if conveyor {
+        for x in 0..4 {
+            game.tiles[[x, 0]] = Tile::new_with_conveyor(Conveyor::One(Left, Right));
+        }
+    }
+    let inventory: Inventory = [(ItemType::Gear, 5)].into_iter().collect();
+    let mut provider = Building::new_inventory([-1, 0], BuildingType::Storage, inventory);
+    provider.logistics = Some(Logistics {
+        mode: LogisticsMode::Provider,
+        targets: [(ItemType::Gear, 2)].into_iter().collect(),
+    });
+    game.buildings.insert(provider);
+    let mut requester = Building::new([4, 0], BuildingType::Storage);
+    requester.logistics = Some(Logistics {
+        mode: LogisticsMode::Requester,
+        targets: [(ItemType::Gear, 10)].into_iter().collect(),
+    });
+    game.buildings.insert(requester);
+    game
+}
+
+fn gears_at(game: &AsteroidColoniesGame, pos: Pos) -> usize {
+    game.iter_building()
+        .find(|b| b.pos == pos)
+        .unwrap()
+        .inventory
+        .get(&ItemType::Gear)
+}
+
+#[test]
+fn test_set_logistics() {
+    let mut game = logistics_game(false);
+    game.buildings
+        .insert(Building::new([0, 2], BuildingType::Assembler));
+    let logistics = Logistics {
+        mode: LogisticsMode::Requester,
+        targets: [(ItemType::Wire, 3)].into_iter().collect(),
+    };
+    assert!(game.set_logistics([0, 2], Some(logistics.clone())).is_err());
+    assert!(game.set_logistics([0, 2], None).is_ok());
+    assert!(game.set_logistics([4, 0], Some(logistics.clone())).is_ok());
+    let requester = game.iter_building().find(|b| b.pos == [4, 0]).unwrap();
+    assert_eq!(requester.logistics, Some(logistics));
+}
+
+#[test]
+fn test_demand_supply() {
+    let game = logistics_game(false);
+    let provider = game.iter_building().find(|b| b.pos == [-1, 0]).unwrap();
+    assert_eq!(provider.logistics_supply(ItemType::Gear, &game.crews), 3);
+    assert_eq!(provider.logistics_supply(ItemType::Wire, &game.crews), 0);
+    assert_eq!(
+        provider.logistics_demand(ItemType::Gear, &game.transports, &game.crews),
+        0
+    );
+    let requester = game.iter_building().find(|b| b.pos == [4, 0]).unwrap();
+    assert_eq!(
+        requester.logistics_demand(ItemType::Gear, &game.transports, &game.crews),
+        10
+    );
+    assert_eq!(
+        requester.logistics_demand(ItemType::Wire, &game.transports, &game.crews),
+        0
+    );
+    assert_eq!(requester.logistics_supply(ItemType::Gear, &game.crews), 0);
+}
+
+#[test]
+fn test_logistics_conveyor() {
+    let mut game = logistics_game(true);
+    game.process_logistics();
+    // The items are sent one by one, since each of them takes a slot on the conveyor
+    assert_eq!(game.transports.len(), 3);
+    assert!(game
+        .transports
+        .iter()
+        .all(|transport| transport.dest == [4, 0]
+            && transport.payload == TransportPayload::Item(ItemType::Gear, 1)));
+    assert_eq!(gears_at(&game, [-1, 0]), 2);
+
+    // The provider does not give away the items it keeps
+    game.process_logistics();
+    assert_eq!(game.transports.len(), 3);
+
+    for _ in 0..20 {
+        game.process_transports();
+    }
+    assert_eq!(game.transports.len(), 0);
+    assert_eq!(gears_at(&game, [4, 0]), 3);
+}
+
+#[test]
+fn test_logistics_crew() {
+    let mut game = logistics_game(false);
+    game.buildings
+        .insert(Building::new([0, 2], BuildingType::CrewCabin).with_crews(2));
+    for _ in 0..100 {
+        game.tick().unwrap();
+    }
+    assert_eq!(game.transports.len(), 0);
+    assert_eq!(gears_at(&game, [-1, 0]), 2);
+    assert_eq!(gears_at(&game, [4, 0]), 3);
+}
@@ -0,0 +1,282 @@
+//! Save format versioning and migrations from older versions.
+//!
+//! A save file records the version of the schema it was written with. When the schema of
+//! `SerializeGame` or anything in it changes, bump [`SAVE_VERSION`] and append a migration
+//! that converts the JSON of the previous version to the new one.
+#[cfg(test)]
+mod tests;
+
+use serde_json::{json, Value};
+
+use crate::{
+    building::BuildingType, construction::DEFAULT_CONSTRUCTION_PRIORITY, life_support::SUPPLY_STOCK,
+};
+
+/// The version of the save format that this build writes.
+pub const SAVE_VERSION: u32 = 15;
+
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// Migrations indexed by the version they convert from.
+const MIGRATIONS: [Migration; SAVE_VERSION as usize] = [
+    migrate_v0,
+    migrate_v1,
+    migrate_v2,
+    migrate_v3,
+    migrate_v4,
+    migrate_v5,
+    migrate_v6,
+    migrate_v7,
+    migrate_v8,
+    migrate_v9,
+    migrate_v10,
+    migrate_v11,
+    migrate_v12,
+    migrate_v13,
+    migrate_v14,
+];
+
+/// Returns the save format version of serialized game data. Saves written before the
+/// versioning was introduced have no version field and are regarded as version 0.
+pub fn save_version(value: &Value) -> Result<u32, String> {
+    let Some(version) = value.get("version") else {
+        return Ok(0);
+    };
+    version
+        .as_u64()
+        .and_then(|v| u32::try_from(v).ok())
+        .ok_or_else(|| format!("Invalid save version: {version}"))
+}
+
+/// Convert serialized game data of any older version to the current version.
+pub fn migrate_save(mut value: Value) -> Result<Value, String> {
+    if !value.is_object() {
+        return Err("Save data is not an object".to_string());
+    }
+    let version = save_version(&value)?;
+    if SAVE_VERSION < version {
+        return Err(format!(
+            "Save version {version} is newer than supported version {SAVE_VERSION}"
+        ));
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(&mut value).map_err(|e| format!("Migration from version {from}: {e}"))?;
+        value["version"] = Value::from(from as u32 + 1);
+    }
+    Ok(value)
+}
+
+/// Version 1 introduced the version field without changing anything else.
+fn migrate_v0(_value: &mut Value) -> Result<(), String> {
+    Ok(())
+}
+
+/// Version 2 added the wall-clock time of the save, which is unknown for older saves.
+fn migrate_v1(value: &mut Value) -> Result<(), String> {
+    value["saved_at"] = Value::Null;
+    Ok(())
+}
+
+/// Version 3 added owners to buildings, constructions and global tasks. Entities in older saves
+/// have no owner, which means anyone can operate them.
+fn migrate_v2(value: &mut Value) -> Result<(), String> {
+    for key in ["buildings", "constructions"] {
+        for entity in entity_payloads(value, key)? {
+            entity["owner"] = Value::Null;
+        }
+    }
+    for task in entity_payloads(value, "global_tasks")? {
+        let Some(task) = task.as_object_mut() else {
+            return Err(format!("Invalid global task: {task}"));
+        };
+        if let Some(Value::Array(args)) = task.get_mut("Excavate") {
+            args.push(Value::Null);
+        } else if let Some(pos) = task.get_mut("Cleanup") {
+            *pos = Value::Array(vec![pos.take(), Value::Null]);
+        }
+    }
+    Ok(())
+}
+
+/// Version 4 added the population to buildings. Cabins in older saves always had as many crews
+/// as they can house.
+fn migrate_v3(value: &mut Value) -> Result<(), String> {
+    for building in entity_payloads(value, "buildings")? {
+        building["population"] = Value::from(building_type(building)?.max_crews());
+    }
+    Ok(())
+}
+
+/// Version 5 added production statistics, which start empty for older saves.
+fn migrate_v4(value: &mut Value) -> Result<(), String> {
+    value["production_stats"] = json!({"minute": [], "hour": [], "day": []});
+    Ok(())
+}
+
+/// Version 6 added research. Older saves have completed no project.
+fn migrate_v5(value: &mut Value) -> Result<(), String> {
+    value["research"] = json!({"current": null, "completed": []});
+    Ok(())
+}
+
+/// Version 7 added life support to cabins. Cabins in older saves start with the needs met and
+/// the supplies that they keep in stock, since nothing produced the supplies before.
+fn migrate_v6(value: &mut Value) -> Result<(), String> {
+    for building in entity_payloads(value, "buildings")? {
+        if !building_type(building)?.is_cabin() {
+            building["needs"] = Value::Null;
+            continue;
+        }
+        building["needs"] = json!({"oxygen": 1.0, "food": 1.0, "rest": 1.0});
+        let inventory = &mut building["inventory"];
+        let Some(countable) = inventory
+            .get_mut("countable")
+            .and_then(|countable| countable.as_object_mut())
+        else {
+            return Err(format!("Invalid inventory: {inventory}"));
+        };
+        for (item, stock) in SUPPLY_STOCK {
+            let count = countable
+                .entry(format!("{item:?}"))
+                .or_insert_with(|| Value::from(0));
+            if count.as_u64().is_none_or(|count| count < stock as u64) {
+                *count = Value::from(stock);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 8 added job priorities and experience to cabins, and the index of each crew among the
+/// ones living in its cabin. Crews in older saves have no index and no experience.
+fn migrate_v7(value: &mut Value) -> Result<(), String> {
+    for building in entity_payloads(value, "buildings")? {
+        building["skills"] = if building_type(building)?.is_cabin() {
+            json!({"priorities": {}, "crews": []})
+        } else {
+            Value::Null
+        };
+    }
+    for crew in entity_payloads(value, "crews")? {
+        crew["member"] = Value::Null;
+    }
+    Ok(())
+}
+
+/// Version 9 added priorities and pausing to constructions.
+fn migrate_v8(value: &mut Value) -> Result<(), String> {
+    for construction in entity_payloads(value, "constructions")? {
+        construction["priority"] = Value::from(DEFAULT_CONSTRUCTION_PRIORITY);
+        construction["paused"] = Value::Bool(false);
+    }
+    Ok(())
+}
+
+/// Version 10 added the recipe that a construction sets to its building, which is none for
+/// constructions in older saves.
+fn migrate_v9(value: &mut Value) -> Result<(), String> {
+    for construction in entity_payloads(value, "constructions")? {
+        construction["building_recipe"] = Value::Null;
+    }
+    Ok(())
+}
+
+/// Version 11 added the undo and redo history, which is empty for older saves.
+fn migrate_v10(value: &mut Value) -> Result<(), String> {
+    value["history"] = json!([]);
+    Ok(())
+}
+
+/// Version 12 added item filters to splitters and building outputs. Nothing was filtered in
+/// older saves.
+fn migrate_v11(value: &mut Value) -> Result<(), String> {
+    for tile in tiles(value)? {
+        tile["filter"] = Value::Null;
+    }
+    for building in entity_payloads(value, "buildings")? {
+        building["output_filter"] = Value::Null;
+    }
+    Ok(())
+}
+
+/// Version 13 added conveyor tiers, the progress of transports on their tiles and the
+/// deliveries in the statistics. Older saves only had the basic conveyor.
+fn migrate_v12(value: &mut Value) -> Result<(), String> {
+    for tile in tiles(value)? {
+        tile["conveyor_tier"] = Value::from(0);
+    }
+    for construction in entity_payloads(value, "constructions")? {
+        construction["conveyor_tier"] = Value::from(0);
+    }
+    for transport in entity_payloads(value, "transports")? {
+        transport["progress"] = Value::from(0);
+    }
+    for window in ["minute", "hour", "day"] {
+        let Some(Value::Array(buckets)) = value["production_stats"].get_mut(window) else {
+            return Err(format!("Missing statistics window {window}"));
+        };
+        for bucket in buckets {
+            bucket["delivered"] = json!({});
+            bucket["deliveries"] = Value::from(0);
+        }
+    }
+    Ok(())
+}
+
+/// Version 14 added piles of items dropped on tiles.
+fn migrate_v13(value: &mut Value) -> Result<(), String> {
+    value["item_piles"] = json!({"v": [], "free": null});
+    Ok(())
+}
+
+/// Version 15 added logistics providers and requesters, which no storage was in older saves.
+fn migrate_v14(value: &mut Value) -> Result<(), String> {
+    for building in entity_payloads(value, "buildings")? {
+        building["logistics"] = Value::Null;
+    }
+    Ok(())
+}
+
+fn building_type(building: &Value) -> Result<BuildingType, String> {
+    serde_json::from_value(building["type_"].clone())
+        .map_err(|e| format!("Invalid building type: {e}"))
+}
+
+/// All the tiles in the serialized `Tiles`, including the one for each uniform chunk.
+fn tiles(value: &mut Value) -> Result<Vec<&mut Value>, String> {
+    let chunks = value
+        .get_mut("tiles")
+        .and_then(|tiles| tiles.get_mut("chunks"))
+        .and_then(|chunks| chunks.as_object_mut())
+        .ok_or_else(|| "Missing tiles".to_string())?;
+    let mut tiles = vec![];
+    for chunk in chunks.values_mut() {
+        let Some((kind, Value::Array(payload))) = chunk
+            .as_object_mut()
+            .and_then(|chunk| chunk.iter_mut().next())
+        else {
+            return Err("Invalid tiles chunk".to_string());
+        };
+        match (kind.as_str(), payload.first_mut()) {
+            ("Tiles", Some(Value::Array(chunk_tiles))) => tiles.extend(chunk_tiles.iter_mut()),
+            ("Uniform", Some(tile)) => tiles.push(tile),
+            _ => return Err(format!("Invalid tiles chunk {kind}")),
+        }
+    }
+    Ok(tiles)
+}
+
+/// Occupied entries of a serialized `EntitySet`.
+fn entity_payloads<'a>(
+    value: &'a mut Value,
+    key: &str,
+) -> Result<impl Iterator<Item = &'a mut Value>, String> {
+    let entries = value
+        .get_mut(key)
+        .and_then(|set| set.get_mut("v"))
+        .and_then(|v| v.as_array_mut())
+        .ok_or_else(|| format!("Missing entity set {key}"))?;
+    Ok(entries
+        .iter_mut()
+        .filter_map(|entry| entry.get_mut("payload")?.get_mut("Occupied")))
+}
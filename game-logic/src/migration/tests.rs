@@ -0,0 +1,324 @@
+use super::*;
+use crate::{
+    life_support::CrewNeeds,
+    transport::{Transport, TransportPayload},
+    AsteroidColoniesGame, ItemType,
+};
+
+fn saved_game() -> Value {
+    let game = AsteroidColoniesGame::new(None).unwrap();
+    serde_json::from_str(&game.serialize(false).unwrap()).unwrap()
+}
+
+#[test]
+fn test_current_version() {
+    let value = saved_game();
+    assert_eq!(save_version(&value), Ok(SAVE_VERSION));
+    let migrated = migrate_save(value.clone()).unwrap();
+    assert_eq!(migrated, value);
+}
+
+#[test]
+fn test_migrate_unversioned() {
+    let mut value = saved_game();
+    let obj = value.as_object_mut().unwrap();
+    obj.remove("version");
+    obj.remove("saved_at");
+    assert_eq!(save_version(&value), Ok(0));
+
+    let migrated = migrate_save(value).unwrap();
+    assert_eq!(save_version(&migrated), Ok(SAVE_VERSION));
+    assert_eq!(migrated["saved_at"], Value::Null);
+
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+}
+
+#[test]
+fn test_newer_version() {
+    let mut value = saved_game();
+    value["version"] = Value::from(SAVE_VERSION + 1);
+    assert_eq!(
+        migrate_save(value).unwrap_err(),
+        format!(
+            "Save version {} is newer than supported version {SAVE_VERSION}",
+            SAVE_VERSION + 1
+        )
+    );
+}
+
+#[test]
+fn test_bin_version_mismatch() {
+    let game = AsteroidColoniesGame::new(None).unwrap();
+    let mut data = game.serialize_bin().unwrap();
+    let mut other = AsteroidColoniesGame::new(None).unwrap();
+    other.deserialize_bin(&data).unwrap();
+
+    data[0] = data[0].wrapping_add(1);
+    assert!(other
+        .deserialize_bin(&data)
+        .unwrap_err()
+        .starts_with("Save version mismatch"));
+}
+
+#[test]
+fn test_migrate_owners() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let owner = Some(crate::PlayerId(1));
+    game.cleanup_item([1, 2], owner).unwrap();
+    let mut value: Value = serde_json::from_str(&game.serialize(false).unwrap()).unwrap();
+
+    // Downgrade to version 2 by removing the owners
+    value["version"] = Value::from(2);
+    for key in ["buildings", "constructions"] {
+        for entity in entity_payloads(&mut value, key).unwrap() {
+            entity.as_object_mut().unwrap().remove("owner");
+        }
+    }
+    for task in entity_payloads(&mut value, "global_tasks").unwrap() {
+        task["Cleanup"] = task["Cleanup"][0].take();
+    }
+    assert!(entity_payloads(&mut value, "buildings").unwrap().count() > 0);
+
+    let migrated = migrate_save(value).unwrap();
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+    assert!(game.iter_building().all(|b| b.owner.is_none()));
+    assert!(game
+        .iter_global_task()
+        .all(|t| matches!(*t, crate::task::GlobalTask::Cleanup([1, 2], None))));
+}
+
+#[test]
+fn test_migrate_population() {
+    let mut value = saved_game();
+    value["version"] = Value::from(3);
+    for building in entity_payloads(&mut value, "buildings").unwrap() {
+        building.as_object_mut().unwrap().remove("population");
+    }
+    let migrated = migrate_save(value).unwrap();
+
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+    let cabin = game
+        .iter_building()
+        .find(|b| b.type_ == BuildingType::CrewCabin)
+        .unwrap();
+    assert_eq!(cabin.population, 4);
+    let furnace = game
+        .iter_building()
+        .find(|b| b.type_ == BuildingType::Furnace)
+        .unwrap();
+    assert_eq!(furnace.population, 0);
+}
+
+#[test]
+fn test_migrate_production_stats() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    for _ in 0..10 {
+        game.tick().unwrap();
+    }
+    let mut value: Value = serde_json::from_str(&game.serialize(false).unwrap()).unwrap();
+    value["version"] = Value::from(4);
+    value.as_object_mut().unwrap().remove("production_stats");
+    let migrated = migrate_save(value).unwrap();
+
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+    assert_eq!(
+        game.production_stats()
+            .window(crate::stats::StatsWindow::Minute)
+            .len(),
+        0
+    );
+}
+
+#[test]
+fn test_migrate_research() {
+    let mut value = saved_game();
+    value["version"] = Value::from(5);
+    value.as_object_mut().unwrap().remove("research");
+    let migrated = migrate_save(value).unwrap();
+
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+    assert!(game.research().completed.is_empty());
+}
+
+#[test]
+fn test_migrate_life_support() {
+    let mut value = saved_game();
+    value["version"] = Value::from(6);
+    for building in entity_payloads(&mut value, "buildings").unwrap() {
+        building.as_object_mut().unwrap().remove("needs");
+        let countable = building["inventory"]["countable"].as_object_mut().unwrap();
+        countable.remove("Oxygen");
+        countable.insert("Food".to_string(), Value::from(20));
+    }
+    let migrated = migrate_save(value).unwrap();
+
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+    let cabin = game
+        .iter_building()
+        .find(|b| b.type_ == BuildingType::CrewCabin)
+        .unwrap();
+    assert_eq!(cabin.needs, Some(CrewNeeds::new()));
+    assert_eq!(cabin.inventory.get(&ItemType::Oxygen), 4);
+    assert_eq!(cabin.inventory.get(&ItemType::Food), 20);
+    let furnace = game
+        .iter_building()
+        .find(|b| b.type_ == BuildingType::Furnace)
+        .unwrap();
+    assert!(furnace.needs.is_none());
+    assert_eq!(furnace.inventory.get(&ItemType::Oxygen), 0);
+}
+
+#[test]
+fn test_migrate_skills() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.excavate(30, 50, None).unwrap();
+    for _ in 0..5 {
+        game.tick().unwrap();
+    }
+    let mut value: Value = serde_json::from_str(&game.serialize(false).unwrap()).unwrap();
+    value["version"] = Value::from(7);
+    for key in ["buildings", "crews"] {
+        for entity in entity_payloads(&mut value, key).unwrap() {
+            let entity = entity.as_object_mut().unwrap();
+            entity.remove("skills");
+            entity.remove("member");
+        }
+    }
+    assert!(entity_payloads(&mut value, "crews").unwrap().count() > 0);
+    let migrated = migrate_save(value).unwrap();
+
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+    let cabin = game
+        .iter_building()
+        .find(|b| b.type_ == BuildingType::CrewCabin)
+        .unwrap();
+    assert_eq!(cabin.skills, Some(Default::default()));
+    assert!(game.iter_crew().all(|crew| crew.member.is_none()));
+}
+
+#[test]
+fn test_migrate_construction_priority() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.build(27, 52, BuildingType::Storage, None).unwrap();
+    let mut value: Value = serde_json::from_str(&game.serialize(false).unwrap()).unwrap();
+    value["version"] = Value::from(8);
+    for construction in entity_payloads(&mut value, "constructions").unwrap() {
+        let construction = construction.as_object_mut().unwrap();
+        construction.remove("priority");
+        construction.remove("paused");
+        construction.remove("building_recipe");
+    }
+    let migrated = migrate_save(value).unwrap();
+
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+    let construction = game.iter_construction().next().unwrap();
+    assert_eq!(construction.priority, DEFAULT_CONSTRUCTION_PRIORITY);
+    assert!(!construction.paused);
+    assert!(construction.building_recipe.is_none());
+}
+
+#[test]
+fn test_migrate_history() {
+    let mut value = saved_game();
+    value["version"] = Value::from(10);
+    value.as_object_mut().unwrap().remove("history");
+    let migrated = migrate_save(value).unwrap();
+    assert_eq!(migrated["history"], json!([]));
+
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+}
+
+#[test]
+fn test_migrate_filters() {
+    let mut value = saved_game();
+    value["version"] = Value::from(11);
+    let tiles = tiles(&mut value).unwrap();
+    assert!(!tiles.is_empty());
+    for tile in tiles {
+        tile.as_object_mut().unwrap().remove("filter");
+    }
+    for building in entity_payloads(&mut value, "buildings").unwrap() {
+        building.as_object_mut().unwrap().remove("output_filter");
+    }
+    let migrated = migrate_save(value).unwrap();
+
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+    assert!(game.iter_building().all(|b| b.output_filter.is_none()));
+}
+
+#[test]
+fn test_migrate_conveyor_tiers() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    for _ in 0..10 {
+        game.tick().unwrap();
+    }
+    let mut transport = Transport::new(
+        [26, 50],
+        [26, 51],
+        TransportPayload::Item(ItemType::Gear, 1),
+        vec![[26, 51], [26, 50]],
+    );
+    transport.progress = 5;
+    game.transports.insert(transport);
+    let mut value: Value = serde_json::from_str(&game.serialize(false).unwrap()).unwrap();
+    value["version"] = Value::from(12);
+    for tile in tiles(&mut value).unwrap() {
+        tile.as_object_mut().unwrap().remove("conveyor_tier");
+    }
+    for key in ["constructions", "transports"] {
+        for entity in entity_payloads(&mut value, key).unwrap() {
+            let entity = entity.as_object_mut().unwrap();
+            entity.remove("conveyor_tier");
+            entity.remove("progress");
+        }
+    }
+    let buckets = value["production_stats"]["minute"].as_array_mut().unwrap();
+    assert!(!buckets.is_empty());
+    for bucket in buckets {
+        let bucket = bucket.as_object_mut().unwrap();
+        bucket.remove("delivered");
+        bucket.remove("deliveries");
+    }
+    let migrated = migrate_save(value).unwrap();
+
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+    let transport = game.iter_transport().next().unwrap();
+    assert_eq!(transport.progress, 0);
+}
+
+#[test]
+fn test_migrate_item_piles() {
+    let mut value = saved_game();
+    value["version"] = Value::from(13);
+    value.as_object_mut().unwrap().remove("item_piles");
+    let migrated = migrate_save(value).unwrap();
+
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+    assert_eq!(game.item_piles.len(), 0);
+}
+
+#[test]
+fn test_migrate_logistics() {
+    let mut value = saved_game();
+    value["version"] = Value::from(14);
+    for building in entity_payloads(&mut value, "buildings").unwrap() {
+        building.as_object_mut().unwrap().remove("logistics");
+    }
+    let migrated = migrate_save(value).unwrap();
+
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.deserialize_value(migrated).unwrap();
+    assert!(game.iter_building().all(|b| b.logistics.is_none()));
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// An identifier of a player, assigned by the server. The game logic does not know the players
+/// themselves; it only records who owns buildings, construction plans and tasks.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PlayerId(pub u32);
+
+impl std::fmt::Display for PlayerId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Player {}", self.0)
+    }
+}
@@ -0,0 +1,282 @@
+//! Power networks formed by connected power grid tiles
+#[cfg(test)]
+mod tests;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde::Serialize;
+
+use crate::{
+    building::{Building, BuildingId},
+    direction::Direction,
+    entity::EntitySet,
+    push_pull::rect_iter,
+    tile::{Chunk, CHUNK_SIZE},
+    Pos, Tiles,
+};
+
+pub type PowerNetworkId = usize;
+
+/// A connected component of power grid tiles and the buildings touching them.
+///
+/// Each network balances generation, demand and battery charge on its own, so
+/// a colony can have an isolated grid that runs out of power while others do not.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PowerNetwork {
+    /// The number of power grid tiles in this network
+    pub tiles: usize,
+    pub buildings: Vec<BuildingId>,
+    /// Total generated power, in kW
+    pub power_gen: isize,
+    /// Total demanded power, in kW
+    pub power_demand: isize,
+    /// Power that batteries in this network can accept in a tick
+    pub chargeable: isize,
+    /// Power that batteries in this network can provide in a tick
+    pub dischargeable: isize,
+    /// Energy stored in the batteries in this network
+    pub energy: usize,
+    /// The fraction of the demand that was satisfied in the last tick.
+    pub power_ratio: f64,
+    /// Used power for the last tick, in kW
+    pub used_power: f64,
+}
+
+/// All power networks in the colony, recomputed every tick from the tiles and buildings.
+#[derive(Clone, Debug, Default)]
+pub struct PowerNetworks {
+    networks: Vec<PowerNetwork>,
+    tiles: HashMap<Pos, PowerNetworkId>,
+    buildings: HashMap<BuildingId, PowerNetworkId>,
+}
+
+impl PowerNetworks {
+    pub(crate) fn new(tiles: &Tiles, buildings: &EntitySet<Building>) -> Self {
+        let grid = grid_tiles(tiles);
+
+        // Label connected grid tiles. Seeds are sorted so that the network ids do not depend on
+        // HashMap iteration order.
+        let mut seeds: Vec<_> = grid.iter().copied().collect();
+        seeds.sort_by_key(|pos| [pos[1], pos[0]]);
+        let mut labels: HashMap<Pos, usize> = HashMap::new();
+        let mut num_labels = 0;
+        for seed in seeds {
+            if labels.contains_key(&seed) {
+                continue;
+            }
+            let label = num_labels;
+            num_labels += 1;
+            let mut queue = VecDeque::from([seed]);
+            labels.insert(seed, label);
+            while let Some(pos) = queue.pop_front() {
+                for dir in Direction::all() {
+                    let dv = dir.to_vec();
+                    let next = [pos[0] + dv[0], pos[1] + dv[1]];
+                    if grid.contains(&next) && !labels.contains_key(&next) {
+                        labels.insert(next, label);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        // A building touching more than one grid conducts power between them, so merge them.
+        let mut union_find: Vec<usize> = (0..num_labels).collect();
+        fn find(union_find: &mut [usize], mut i: usize) -> usize {
+            while union_find[i] != i {
+                union_find[i] = union_find[union_find[i]];
+                i = union_find[i];
+            }
+            i
+        }
+        let mut building_labels = vec![];
+        for (id, building) in buildings.items() {
+            let touching: HashSet<usize> = touching_tiles(&building)
+                .filter_map(|pos| labels.get(&pos).copied())
+                .collect();
+            let mut touching: Vec<_> = touching.into_iter().collect();
+            touching.sort();
+            if let Some(&first) = touching.first() {
+                for &other in &touching[1..] {
+                    let (a, b) = (find(&mut union_find, first), find(&mut union_find, other));
+                    union_find[a.max(b)] = a.min(b);
+                }
+                building_labels.push((id, first));
+            }
+        }
+
+        // Compact the roots into sequential network ids
+        let mut root_to_id = HashMap::new();
+        let mut networks = vec![];
+        let label_to_id: Vec<PowerNetworkId> = (0..num_labels)
+            .map(|label| {
+                let root = find(&mut union_find, label);
+                *root_to_id.entry(root).or_insert_with(|| {
+                    networks.push(PowerNetwork::default());
+                    networks.len() - 1
+                })
+            })
+            .collect();
+
+        let tiles: HashMap<Pos, PowerNetworkId> = labels
+            .into_iter()
+            .map(|(pos, label)| (pos, label_to_id[label]))
+            .collect();
+        for id in tiles.values() {
+            networks[*id].tiles += 1;
+        }
+
+        let mut building_ids = HashMap::new();
+        for (building_id, label) in building_labels {
+            let network_id = label_to_id[label];
+            let Some(building) = buildings.get(building_id) else {
+                continue;
+            };
+            let network = &mut networks[network_id];
+            network.buildings.push(building_id);
+            let gen = building.power_gen();
+            network.power_gen += gen.max(0);
+            network.power_demand += gen.min(0).abs();
+            network.chargeable += building.power_charge();
+            network.dischargeable += building.power_discharge();
+            network.energy += building.energy.unwrap_or(0);
+            building_ids.insert(building_id, network_id);
+        }
+
+        for network in &mut networks {
+            network.power_ratio = ((network.dischargeable as f64 + network.power_gen as f64)
+                / network.power_demand as f64)
+                .min(1.);
+            network.used_power = network.power_ratio * network.power_demand as f64;
+        }
+
+        Self {
+            networks,
+            tiles,
+            buildings: building_ids,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.networks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (PowerNetworkId, &PowerNetwork)> {
+        self.networks.iter().enumerate()
+    }
+
+    pub fn get(&self, id: PowerNetworkId) -> Option<&PowerNetwork> {
+        self.networks.get(id)
+    }
+
+    /// Returns the network that a power grid tile belongs to
+    pub fn at_tile(&self, pos: Pos) -> Option<PowerNetworkId> {
+        self.tiles.get(&pos).copied()
+    }
+
+    /// Returns the network that a building is connected to
+    pub fn of_building(&self, id: BuildingId) -> Option<PowerNetworkId> {
+        self.buildings.get(&id).copied()
+    }
+
+    /// Power ratio available to a building. Buildings not connected to any grid get no power.
+    pub fn power_ratio(&self, id: BuildingId) -> f64 {
+        self.of_building(id)
+            .and_then(|network| self.networks.get(network))
+            .map(|network| network.power_ratio)
+            .unwrap_or(0.)
+    }
+
+    /// Settle battery charge and discharge in each network after the tick consumed the power.
+    pub(crate) fn update_batteries(&self, buildings: &mut EntitySet<Building>) {
+        for (building_id, building) in buildings.items_mut() {
+            let Some(network) = self
+                .of_building(building_id)
+                .and_then(|id| self.networks.get(id))
+            else {
+                continue;
+            };
+            let charging_total = network.power_gen - network.power_demand;
+            if charging_total < 0 {
+                if 0 < network.dischargeable {
+                    // Drain energy from capacitors proportional to the capacity
+                    let drain_total = -charging_total;
+                    let cap = building.power_discharge();
+                    let Some(ref mut energy) = building.energy else {
+                        continue;
+                    };
+                    let drain = drain_total * cap / network.dischargeable;
+                    *energy = (*energy as isize - drain).max(0) as usize;
+                }
+            } else if 0 < network.chargeable {
+                let max_charge = building.power_charge();
+                let Some(max_energy) = building.type_.energy_capacity() else {
+                    continue;
+                };
+                let Some(ref mut energy) = building.energy else {
+                    continue;
+                };
+                let charge = charging_total * max_charge / network.chargeable;
+                *energy = (*energy as isize + charge).clamp(0, max_energy as isize) as usize;
+            }
+        }
+    }
+
+    /// Power ratio aggregated over all networks, weighted by the demand.
+    pub(crate) fn total_power_ratio(&self) -> f64 {
+        let demand: isize = self.networks.iter().map(|n| n.power_demand).sum();
+        if demand == 0 {
+            return 1.;
+        }
+        self.total_used_power() / demand as f64
+    }
+
+    pub(crate) fn total_used_power(&self) -> f64 {
+        self.networks.iter().map(|n| n.used_power).sum()
+    }
+}
+
+/// Collect all tiles that have a power grid installed.
+fn grid_tiles(tiles: &Tiles) -> HashSet<Pos> {
+    let mut grid = HashSet::new();
+    for (chunk_pos, chunk) in tiles.chunks() {
+        let origin = [
+            chunk_pos.x * CHUNK_SIZE as i32,
+            chunk_pos.y * CHUNK_SIZE as i32,
+        ];
+        match chunk {
+            Chunk::Tiles(chunk_tiles, _) => {
+                for (i, tile) in chunk_tiles.iter().enumerate() {
+                    if tile.power_grid {
+                        grid.insert([
+                            origin[0] + (i % CHUNK_SIZE) as i32,
+                            origin[1] + (i / CHUNK_SIZE) as i32,
+                        ]);
+                    }
+                }
+            }
+            Chunk::Uniform(tile, _) => {
+                if tile.power_grid {
+                    grid.extend(rect_iter(origin, [CHUNK_SIZE; 2]));
+                }
+            }
+        }
+    }
+    grid
+}
+
+/// Iterate tiles covered by or adjacent to a building.
+fn touching_tiles(building: &Building) -> impl Iterator<Item = Pos> {
+    let pos = building.pos;
+    let size = building.type_.size();
+    rect_iter([pos[0] - 1, pos[1] - 1], [size[0] + 2, size[1] + 2]).filter(move |p| {
+        // Exclude corners, since they are not adjacent
+        let x_out = p[0] < pos[0] || pos[0] + size[0] as i32 <= p[0];
+        let y_out = p[1] < pos[1] || pos[1] + size[1] as i32 <= p[1];
+        !(x_out && y_out)
+    })
+}
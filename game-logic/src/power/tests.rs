@@ -0,0 +1,71 @@
+use super::*;
+use crate::{building::BuildingType, Tile, TileState};
+
+fn grid_line(tiles: &mut Tiles, y: i32, xs: std::ops::Range<i32>) {
+    for x in xs {
+        let tile = &mut tiles[[x, y]];
+        *tile = Tile::new();
+        tile.state = TileState::Empty;
+        tile.power_grid = true;
+    }
+}
+
+#[test]
+fn test_separate_networks() {
+    let mut tiles = Tiles::new();
+    grid_line(&mut tiles, 0, 0..5);
+    grid_line(&mut tiles, 3, 0..5);
+
+    let buildings: EntitySet<_> = [
+        Building::new([0, 1], BuildingType::Power),
+        Building::new([4, 1], BuildingType::Excavator),
+        Building::new([0, 4], BuildingType::CrewCabin),
+        Building::new([10, 10], BuildingType::Excavator),
+    ]
+    .into_iter()
+    .collect();
+    let ids: Vec<_> = buildings.items().map(|(id, _)| id).collect();
+
+    let networks = PowerNetworks::new(&tiles, &buildings);
+
+    assert_eq!(networks.len(), 2);
+    let first = networks.of_building(ids[0]).unwrap();
+    assert_eq!(networks.of_building(ids[1]), Some(first));
+    let second = networks.of_building(ids[2]).unwrap();
+    assert_ne!(first, second);
+    assert_eq!(networks.of_building(ids[3]), None);
+
+    let first = networks.get(first).unwrap();
+    assert_eq!(first.tiles, 5);
+    assert_eq!(first.power_gen, 250);
+    assert_eq!(first.power_demand, 10);
+    assert_eq!(first.power_ratio, 1.);
+
+    // The crew cabin has no generator in its network
+    assert_eq!(networks.get(second).unwrap().power_ratio, 0.);
+    assert_eq!(networks.power_ratio(ids[2]), 0.);
+
+    // Not connected to any grid
+    assert_eq!(networks.power_ratio(ids[3]), 0.);
+}
+
+#[test]
+fn test_building_bridges_networks() {
+    let mut tiles = Tiles::new();
+    grid_line(&mut tiles, 0, 0..3);
+    grid_line(&mut tiles, 2, 0..3);
+
+    // The storage at y = 1 touches both grid lines
+    let buildings: EntitySet<_> = [
+        Building::new([0, 1], BuildingType::Storage),
+        Building::new([2, 3], BuildingType::Power),
+    ]
+    .into_iter()
+    .collect();
+
+    let networks = PowerNetworks::new(&tiles, &buildings);
+
+    assert_eq!(networks.len(), 1);
+    assert_eq!(networks.get(0).unwrap().tiles, 6);
+    assert_eq!(networks.at_tile([2, 0]), networks.at_tile([2, 2]));
+}
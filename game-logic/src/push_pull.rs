@@ -6,14 +6,15 @@ use std::collections::HashSet;
 
 use crate::{
     building::{Building, OreAccum},
-    conveyor::Conveyor,
+    conveyor::{find_underground_exit, Conveyor},
     direction::Direction,
     entity::{EntityEntry, EntitySet, EntryPayload, RefMutOption},
+    filter::ItemSet,
     inventory::Inventory,
     items::ItemType,
     transport::{
-        expected_deliveries, find_multipath_should_expand, CPos, LevelTarget, Transport,
-        TransportId, TransportPayload,
+        expected_deliveries, find_multipath_should_expand, send_items, CPos, LevelTarget,
+        Transport, TransportId, TransportPayload,
     },
     Pos, Tile, Tiles, WIDTH,
 };
@@ -53,12 +54,6 @@ pub(crate) fn pull_inputs<'a>(
     this_inventory: &mut Inventory,
     buildings: &EntitySet<Building>,
 ) {
-    let intersects_goal = |[ix, iy]: [i32; 2]| {
-        this_pos[0] <= ix
-            && ix < this_size[0] as i32 + this_pos[0]
-            && this_pos[1] <= iy
-            && iy < this_size[1] as i32 + this_pos[1]
-    };
     // let start = std::time::Instant::now();
     // crate::console_log!("pulling to at {:?} size {:?}", this_pos, this_size);
     let expected = expected_deliveries(transports, expected_transports);
@@ -74,33 +69,14 @@ pub(crate) fn pull_inputs<'a>(
         if amount == 0 {
             continue;
         }
-        let size = src.type_.size();
-        let start_pos = rect_iter(src.pos, size);
-        let start_neighbors = neighbors_set(rect_iter(src.pos, size));
-        let path = find_multipath_should_expand(
-            start_pos,
-            intersects_goal,
-            |from_direction, pos| {
-                if intersects_goal(pos) {
-                    return true;
-                }
-                push_pull_passable(tiles, from_direction, &start_neighbors, pos)
-            },
-            |to, pos, from| push_pull_should_expand(tiles, to, pos, from),
-        );
+        let path = find_item_path(tiles, *ty, src.pos, src.type_.size(), this_pos, this_size);
         let Some(path) = path else {
             continue;
         };
         let src_pos = src.pos;
         let src_count = src.inventory.entry(*ty).or_default();
         let amount = (*src_count).min(*count - this_count);
-        let id = transports.insert(Transport {
-            src: src_pos,
-            dest: this_pos,
-            path,
-            payload: TransportPayload::Item(*ty, amount),
-        });
-        expected_transports.insert(id);
+        expected_transports.extend(send_items(transports, src_pos, this_pos, *ty, amount, path));
         if *src_count <= amount {
             src.inventory.remove(ty);
         } else {
@@ -111,6 +87,36 @@ pub(crate) fn pull_inputs<'a>(
     // println!("pull_inputs took {} sec", time);
 }
 
+/// Find a path over conveyors for an item from the rectangle of a source building to the
+/// rectangle of a destination building.
+pub(crate) fn find_item_path(
+    tiles: &impl TileSampler,
+    item: ItemType,
+    src_pos: Pos,
+    src_size: [usize; 2],
+    dest_pos: Pos,
+    dest_size: [usize; 2],
+) -> Option<Vec<Pos>> {
+    let intersects_goal = |[ix, iy]: [i32; 2]| {
+        dest_pos[0] <= ix
+            && ix < dest_size[0] as i32 + dest_pos[0]
+            && dest_pos[1] <= iy
+            && iy < dest_size[1] as i32 + dest_pos[1]
+    };
+    let start_neighbors = neighbors_set(rect_iter(src_pos, src_size));
+    find_multipath_should_expand(
+        rect_iter(src_pos, src_size),
+        intersects_goal,
+        |from_direction, pos| {
+            if intersects_goal(pos) {
+                return true;
+            }
+            push_pull_passable(tiles, from_direction, &start_neighbors, pos)
+        },
+        |to, pos, from| push_pull_should_expand(tiles, Some(item), to, pos, from),
+    )
+}
+
 const FURNACE_CAPACITY: f64 = 100.;
 
 /// Pull ores for a furnace
@@ -145,7 +151,7 @@ pub(crate) fn pull_ores<'a>(
                 }
                 push_pull_passable(tiles, from_direction, &start_neighbors, pos)
             },
-            |to, pos, from| push_pull_should_expand(tiles, to, pos, from),
+            |to, pos, from| push_pull_should_expand(tiles, None, to, pos, from),
         );
         let Some(path) = path else {
             continue;
@@ -161,12 +167,12 @@ pub(crate) fn pull_ores<'a>(
             copper: src_count.copper.min(FURNACE_CAPACITY - this_ores.copper),
             lithium: src_count.lithium.min(FURNACE_CAPACITY - this_ores.lithium),
         };
-        let id = transports.insert(Transport {
-            src: src_pos,
-            dest: this_pos,
+        let id = transports.insert(Transport::new(
+            src_pos,
+            this_pos,
+            TransportPayload::Ores(amount),
             path,
-            payload: TransportPayload::Ores(amount),
-        });
+        ));
         expected_transports.insert(id);
         src_count.cilicate = (src_count.cilicate - amount.cilicate).max(0.);
         src_count.iron = (src_count.iron - amount.iron).max(0.);
@@ -230,6 +236,10 @@ pub(crate) trait HasInventory {
     fn pos(&self) -> Pos;
     fn size(&self) -> [usize; 2];
     fn inventory(&mut self) -> &mut Inventory;
+    /// The items allowed to be pushed out, if restricted.
+    fn output_filter(&self) -> Option<ItemSet> {
+        None
+    }
 }
 
 impl HasInventory for Building {
@@ -244,6 +254,10 @@ impl HasInventory for Building {
     fn inventory(&mut self) -> &mut Inventory {
         &mut self.inventory
     }
+
+    fn output_filter(&self) -> Option<ItemSet> {
+        self.output_filter
+    }
 }
 
 pub(crate) fn push_outputs<'a, 'b>(
@@ -258,16 +272,81 @@ pub(crate) fn push_outputs<'a, 'b>(
 {
     let pos = this.pos();
     let size = this.size();
-    let start_pos = || rect_iter(pos, size);
-    let start_neighbors = neighbors_set(start_pos());
-    // crate::console_log!(
-    //     "pusheing from {:?} size {:?}, neighbors: {:?}",
-    //     pos,
-    //     size,
-    //     start_neighbors
-    // );
+    let start_neighbors = neighbors_set(rect_iter(pos, size));
+    let filter = this.output_filter();
+    let product = this
+        .inventory()
+        .iter()
+        .find(|(t, count)| {
+            is_output(**t) && filter.map(|f| f.contains(**t)).unwrap_or(true) && 0 < **count
+        })
+        .map(|(t, _)| *t);
+
+    // Push away outputs
+    if let Some(item) = product {
+        let dest = find_storage(
+            tiles,
+            transports,
+            buildings,
+            pos,
+            size,
+            &start_neighbors,
+            Some(item),
+        );
+        if let Some((mut dest, path)) = dest {
+            let id = transports.insert(Transport::new(
+                pos,
+                dest.pos,
+                TransportPayload::Item(item, 1),
+                path,
+            ));
+            dest.expected_transports.insert(id);
+            let amount = this.inventory().entry(item).or_default();
+            if *amount <= 1 {
+                this.inventory().remove(&item);
+            } else {
+                *amount -= 1;
+            }
+        }
+    }
+
+    if output_ores && !this.inventory().ores().is_empty() {
+        let dest = find_storage(
+            tiles,
+            transports,
+            buildings,
+            pos,
+            size,
+            &start_neighbors,
+            None,
+        );
+        if let Some((mut dest, path)) = dest {
+            let ores_mut = this.inventory().ores_mut();
+            let ores_copy = *ores_mut;
+            *ores_mut = OreAccum::default();
+            let id = transports.insert(Transport::new(
+                pos,
+                dest.pos,
+                TransportPayload::Ores(ores_copy),
+                path,
+            ));
+            dest.expected_transports.insert(id);
+        }
+    }
+}
+
+/// Find a storage with a vacancy that the item (or ores if `None`) can reach from the rectangle.
+fn find_storage<'a>(
+    tiles: &impl TileSampler,
+    transports: &EntitySet<Transport>,
+    buildings: &'a EntitySet<Building>,
+    pos: Pos,
+    size: [usize; 2],
+    start_neighbors: &HashSet<Pos>,
+    item: Option<ItemType>,
+) -> Option<(RefMutOption<'a, Building>, Vec<Pos>)> {
     // let start = std::time::Instant::now();
-    let dest = buildings.iter_borrow_mut().find_map(|b| {
+    buildings.iter_borrow_mut().find_map(|b| {
         if !b.type_.is_storage() {
             return None;
         }
@@ -287,59 +366,20 @@ pub(crate) fn push_outputs<'a, 'b>(
                 && iy < b_size[1] as i32 + b.pos[1]
         };
         let path = find_multipath_should_expand(
-            start_pos(),
+            rect_iter(pos, size),
             |pos| pos == b.pos,
             |from_direction, pos| {
                 if intersects(pos) {
                     return true;
                 }
-                push_pull_passable(tiles, from_direction, &start_neighbors, pos)
+                push_pull_passable(tiles, from_direction, start_neighbors, pos)
             },
-            |to, pos, from| push_pull_should_expand(tiles, to, pos, from),
+            |to, pos, from| push_pull_should_expand(tiles, item, to, pos, from),
         )?;
         Some((b, path))
-    });
+    })
     // let time = start.elapsed().as_secs_f64();
     // println!("searching {:?} nodes path took {} sec", dest.as_ref().map(|(_, path)| path.len()), time);
-
-    // Push away outputs
-    if let Some((mut dest, path)) = dest {
-        let product = this
-            .inventory()
-            .iter_mut()
-            .find(|(t, count)| is_output(**t) && 0 < **count);
-        if let Some((&item, amount)) = product {
-            let id = transports.insert(Transport {
-                src: pos,
-                dest: dest.pos,
-                path: path.clone(),
-                payload: TransportPayload::Item(item, 1),
-            });
-            dest.expected_transports.insert(id);
-            // *dest.inventory.entry(*product.0).or_default() += 1;
-            if *amount <= 1 {
-                this.inventory().remove(&item);
-            } else {
-                *amount -= 1;
-            }
-            // this.output_path = Some(path);
-        }
-
-        if output_ores {
-            let ores_mut = this.inventory().ores_mut();
-            if !ores_mut.is_empty() {
-                let ores_copy = *ores_mut;
-                *ores_mut = OreAccum::default();
-                let id = transports.insert(Transport {
-                    src: pos,
-                    dest: dest.pos,
-                    path,
-                    payload: TransportPayload::Ores(ores_copy),
-                });
-                dest.expected_transports.insert(id);
-            }
-        }
-    }
 }
 
 pub(crate) fn send_item<'a, 'b>(
@@ -368,6 +408,12 @@ where
     if dest.type_.capacity() <= expected_inventory_size {
         return Err("Destination capacity is full".to_string());
     }
+    let item = src
+        .inventory()
+        .iter()
+        .find(|(t, count)| is_output(**t) && 0 < **count)
+        .map(|(t, _)| *t)
+        .ok_or_else(|| "The designated item was not found")?;
     let path = find_multipath_should_expand(
         start_pos(),
         |pos| dest.intersects(pos),
@@ -377,27 +423,80 @@ where
             }
             push_pull_passable(tiles, from_direction, &start_neighbors, pos)
         },
-        |to, pos, from| push_pull_should_expand(tiles, to, pos, from),
+        |to, pos, from| push_pull_should_expand(tiles, Some(item), to, pos, from),
     )
     .ok_or_else(|| "Could not find a path from source to dest")?;
 
-    let (&item, amount) = src
-        .inventory()
-        .iter_mut()
-        .find(|(t, count)| is_output(**t) && 0 < **count)
-        .ok_or_else(|| "The designated item was not found")?;
-
-    let id = transports.insert(Transport {
-        src: pos,
-        dest: dest.pos,
-        path,
-        payload: TransportPayload::Item(item, *amount),
-    });
-    dest.expected_transports.insert(id);
+    let amount = src.inventory().get(&item);
+    let ids = send_items(transports, pos, dest.pos, item, amount, path);
+    dest.expected_transports.extend(ids);
     src.inventory().remove(&item);
     Ok(())
 }
 
+/// Find a new path for a transport on `path` to the rectangle at `dest_pos` and `dest_size`,
+/// after some tiles on the path have changed. The search starts from the tile that the transport
+/// is on, or from the exit of the tunnel that it is passing through, whose tiles are kept.
+/// If `in_building` is true, the transport departs from the building as it does at the source.
+/// Returns `None` if the transport cannot reach the destination anymore.
+pub(crate) fn reroute_path(
+    tiles: &impl TileSampler,
+    path: &[Pos],
+    item: Option<ItemType>,
+    dest_pos: Pos,
+    dest_size: [usize; 2],
+    in_building: bool,
+) -> Option<Vec<Pos>> {
+    let intersects_goal = |[ix, iy]: [i32; 2]| {
+        dest_pos[0] <= ix
+            && ix < dest_size[0] as i32 + dest_pos[0]
+            && dest_pos[1] <= iy
+            && iy < dest_size[1] as i32 + dest_pos[1]
+    };
+    let last = path.len().checked_sub(1)?;
+    let [x, y] = path[last];
+    let next = path[last.checked_sub(1)?];
+    let dir = Direction::from_vec([next[0] - x, next[1] - y])?;
+    let tile = tiles.at(path[last]);
+    let start = if in_building || tile.is_some_and(|tile| tile.conveyor.has_to(dir)) {
+        last
+    } else {
+        // It may be going straight through a tunnel, so look for the exit ahead
+        let dir_vec = dir.to_vec();
+        let exit = (0..last)
+            .rev()
+            .take_while(|&i| path[i] == [path[i + 1][0] + dir_vec[0], path[i + 1][1] + dir_vec[1]])
+            .find(|&i| {
+                tiles
+                    .at(path[i])
+                    .is_some_and(|tile| tile.conveyor == Conveyor::UndergroundOut(dir))
+            });
+        match exit {
+            Some(exit) => exit,
+            None if tile.is_some_and(|tile| tile.conveyor.is_some()) => last,
+            None => return None,
+        }
+    };
+    let start_neighbors = if in_building {
+        neighbors_set(std::iter::once(path[start]))
+    } else {
+        HashSet::new()
+    };
+    let mut new_path = find_multipath_should_expand(
+        std::iter::once(path[start]),
+        intersects_goal,
+        |from_direction, pos| {
+            if intersects_goal(pos) {
+                return true;
+            }
+            push_pull_passable(tiles, from_direction, &start_neighbors, pos)
+        },
+        |to, pos, from| push_pull_should_expand(tiles, item, to, pos, from),
+    )?;
+    new_path.extend_from_slice(&path[start + 1..]);
+    Some(new_path)
+}
+
 fn push_pull_passable(
     tiles: &impl TileSampler,
     from_direction: Option<Direction>,
@@ -407,6 +506,10 @@ fn push_pull_passable(
     let Some(tile) = tiles.at(pos) else {
         return false;
     };
+    if let Conveyor::UndergroundOut(dir) = tile.conveyor {
+        // Exits are only reachable through their tunnels, see `push_pull_should_expand`.
+        return from_direction == Some(dir);
+    }
     if tile.conveyor.is_some() && start_neighbors.contains(&pos) {
         // crate::console_log!("next to start");
         return true;
@@ -433,8 +536,11 @@ fn prev_tile_connects_to(tiles: &impl TileSampler, from_dir: Option<Direction>,
     prev_tile.conveyor.has_to(dir)
 }
 
+/// Decide how the search expands from a tile. `item` is the item to transport, or `None` for
+/// ores, which filtered splitters sort.
 fn push_pull_should_expand(
     tiles: &impl TileSampler,
+    item: Option<ItemType>,
     to: Direction,
     cpos: CPos,
     from: Option<Direction>,
@@ -444,12 +550,29 @@ fn push_pull_should_expand(
         return LevelTarget::One;
     };
     let conv = &tile.conveyor;
+    if let Conveyor::UndergroundIn(dir) = *conv {
+        if let Some(span) = find_underground_exit(tiles, cpos.pos).filter(|_| to == dir) {
+            return LevelTarget::Tunnel(span);
+        }
+        // An entrance under the source building does not block other directions
+        if from.is_some() {
+            return LevelTarget::None;
+        }
+    }
+    if let Some(filter) = tile.filter {
+        if matches!(conv, Conveyor::Splitter(_)) && !filter.allows(item, to) {
+            return LevelTarget::None;
+        }
+    }
     let dir_vec = to.to_vec();
     let next_pos = [cpos.pos[0] + dir_vec[0], cpos.pos[1] + dir_vec[1]];
     let Some(next_tile) = tiles.at(next_pos) else {
         return LevelTarget::One;
     };
     let next_conv = &next_tile.conveyor;
+    if matches!(next_conv, Conveyor::UndergroundOut(_)) {
+        return LevelTarget::None;
+    }
     if next_conv.has_two()
         && (conv.has_to(Up) || conv.has_to(Down))
         && (next_conv.has(Up) || next_conv.has(Down))
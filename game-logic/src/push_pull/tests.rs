@@ -61,6 +61,8 @@ fn test_pull_inputs() {
         dest: [1, 3],
         payload: TransportPayload::Item(ItemType::RawOre, 1),
         path: vec![[1, 3], [1, 2], [0, 2], [0, 1], [0, 0], [1, 0], [1, -1]],
+        progress: 0,
+        moved: 0,
     });
 
     assert_eq!(transports, expected)
@@ -110,6 +112,8 @@ fn test_push_outputs() {
         dest: [1, -1],
         payload: TransportPayload::Item(ItemType::RawOre, 1),
         path: vec![[1, -1], [1, 0], [2, 0], [2, 1], [2, 2], [1, 2], [1, 3]],
+        progress: 0,
+        moved: 0,
     });
 
     assert_eq!(transports, expected)
@@ -242,6 +246,8 @@ fn test_pull_inputs2() {
             [1, 0],
             [1, -1],
         ],
+        progress: 0,
+        moved: 0,
     });
 
     assert_eq!(transports, expected)
@@ -292,7 +298,156 @@ fn test_push_outputs2() {
             [1, 3],
             [1, 4],
         ],
+        progress: 0,
+        moved: 0,
     });
 
     assert_eq!(transports, expected)
 }
+
+/// A straight line of conveyors going down, with an underground conveyor from [1, 1] to [1, 4]
+/// if `tunnel` is true. Otherwise, a conveyor on the surface leads to the back of the exit.
+struct MockTunnel {
+    tunnel: bool,
+}
+
+impl TileSampler for MockTunnel {
+    fn at(&self, pos: [i32; 2]) -> Option<&Tile> {
+        use {Conveyor::*, Direction::*};
+        static SOLID: Tile = Tile::new();
+        static UD: Tile = Tile::new_with_conveyor(One(Up, Down));
+        static IN: Tile = Tile::new_with_conveyor(UndergroundIn(Down));
+        static OUT: Tile = Tile::new_with_conveyor(UndergroundOut(Down));
+        match pos {
+            [1, 0] | [1, 5] => Some(&UD),
+            [1, 1] if self.tunnel => Some(&IN),
+            [1, 1..=3] if !self.tunnel => Some(&UD),
+            [1, 4] => Some(&OUT),
+            _ => Some(&SOLID),
+        }
+    }
+}
+
+#[test]
+fn test_pull_inputs_underground() {
+    let inputs = btree_map!(ItemType::RawOre => 1);
+
+    let storage: EntitySet<_> = [Building::new_inventory(
+        [1, -1],
+        BuildingType::Storage,
+        inputs.clone().into(),
+    )]
+    .into_iter()
+    .collect();
+
+    let mut transports = EntitySet::new();
+
+    pull_inputs(
+        &inputs,
+        &MockTunnel { tunnel: true },
+        &mut transports,
+        &mut HashSet::new(),
+        [1, 6],
+        [1, 1],
+        &mut Inventory::new(),
+        &storage,
+    );
+
+    let mut expected = EntitySet::new();
+    expected.insert(Transport {
+        src: [1, -1],
+        dest: [1, 6],
+        payload: TransportPayload::Item(ItemType::RawOre, 1),
+        // The tiles under the tunnel are included to keep the speed of transports
+        path: vec![
+            [1, 6],
+            [1, 5],
+            [1, 4],
+            [1, 3],
+            [1, 2],
+            [1, 1],
+            [1, 0],
+            [1, -1],
+        ],
+        progress: 0,
+        moved: 0,
+    });
+
+    assert_eq!(transports, expected);
+
+    // The exit cannot be entered from the surface
+    let mut transports = EntitySet::new();
+    pull_inputs(
+        &inputs,
+        &MockTunnel { tunnel: false },
+        &mut transports,
+        &mut HashSet::new(),
+        [1, 6],
+        [1, 1],
+        &mut Inventory::new(),
+        &storage,
+    );
+    assert_eq!(transports.len(), 0);
+}
+
+#[test]
+fn test_reroute_path() {
+    // The transport at [0, 1] had a path over a tile that does not exist anymore
+    let path = reroute_path(
+        &MockTiles,
+        &[[1, -1], [1, 0], [1, 1], [0, 1]],
+        Some(ItemType::RawOre),
+        [1, -1],
+        [1, 1],
+        false,
+    );
+    assert_eq!(
+        path,
+        Some(vec![
+            [1, -1],
+            [1, 0],
+            [2, 0],
+            [2, 1],
+            [2, 2],
+            [1, 2],
+            [0, 2],
+            [0, 1]
+        ])
+    );
+
+    // A transport on a tile without a conveyor cannot go anywhere
+    let path = reroute_path(
+        &MockTiles,
+        &[[1, -1], [1, 0], [1, 1]],
+        Some(ItemType::RawOre),
+        [1, -1],
+        [1, 1],
+        false,
+    );
+    assert_eq!(path, None);
+}
+
+#[test]
+fn test_reroute_path_underground() {
+    // The transport under the tunnel keeps going to the exit
+    let path = reroute_path(
+        &MockTunnel { tunnel: true },
+        &[[2, 6], [2, 5], [1, 5], [1, 4], [1, 3], [1, 2]],
+        Some(ItemType::RawOre),
+        [1, 6],
+        [1, 1],
+        false,
+    );
+    assert_eq!(path, Some(vec![[1, 6], [1, 5], [1, 4], [1, 3], [1, 2]]));
+
+    // Without the tunnel, it has nowhere to go
+    let path = reroute_path(
+        &MockTunnel { tunnel: false },
+        &[[1, 6], [1, 5], [1, 4], [1, 3], [1, 2]],
+        Some(ItemType::RawOre),
+        [1, 6],
+        [1, 1],
+        false,
+    );
+    assert_eq!(path, None);
+}
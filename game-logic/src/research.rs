@@ -0,0 +1,185 @@
+//! Research projects, which laboratories work on by consuming items, and which unlock recipes,
+//! buildings and upgrades.
+//!
+//! The projects are defined in the ruleset. Recipes and buildings that no project unlocks are
+//! available from the start, so a ruleset without research works as before.
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    building::{BuildingType, Recipe},
+    construction::{get_build_menu, BuildMenuItem, ConstructionType},
+    items::ItemType,
+    ruleset::ruleset,
+    AsteroidColoniesGame,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResearchProject {
+    pub name: String,
+    /// Items that a laboratory consumes to research the project
+    pub inputs: BTreeMap<ItemType, usize>,
+    pub time: f64,
+    /// Projects that need to be completed before this one
+    #[serde(default)]
+    pub requires: Vec<String>,
+    pub unlocks: Vec<Unlock>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Unlock {
+    /// The recipe that produces the item
+    Recipe(ItemType),
+    Building(BuildingType),
+    Upgrade(Upgrade),
+}
+
+/// An upgrade that makes all buildings of a type work faster.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Upgrade {
+    pub building: BuildingType,
+    /// Multiplier to the working speed
+    pub speed: f64,
+}
+
+pub(crate) fn research_project(name: &str) -> Option<&'static ResearchProject> {
+    ruleset()
+        .research
+        .iter()
+        .find(|project| project.name == name)
+}
+
+/// The progress of research in a colony.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Research {
+    /// The project that laboratories work on
+    pub current: Option<String>,
+    pub completed: BTreeSet<String>,
+}
+
+impl Research {
+    pub fn is_unlocked(&self, target: &Unlock) -> bool {
+        let mut locked = false;
+        for project in &ruleset().research {
+            if project.unlocks.contains(target) {
+                if self.completed.contains(&project.name) {
+                    return true;
+                }
+                locked = true;
+            }
+        }
+        !locked
+    }
+
+    pub fn is_recipe_unlocked(&self, recipe: &Recipe) -> bool {
+        recipe
+            .outputs
+            .keys()
+            .all(|item| self.is_unlocked(&Unlock::Recipe(*item)))
+    }
+
+    pub fn is_building_unlocked(&self, ty: BuildingType) -> bool {
+        self.is_unlocked(&Unlock::Building(ty))
+    }
+
+    /// Whether the project can be started, which means it is not completed yet and all the
+    /// projects it requires are.
+    pub fn is_available(&self, project: &ResearchProject) -> bool {
+        !self.completed.contains(&project.name)
+            && project
+                .requires
+                .iter()
+                .all(|name| self.completed.contains(name))
+    }
+
+    /// Working speed multiplier of a building type, given by the completed upgrades.
+    pub fn speed(&self, ty: BuildingType) -> f64 {
+        ruleset()
+            .research
+            .iter()
+            .filter(|project| self.completed.contains(&project.name))
+            .flat_map(|project| &project.unlocks)
+            .filter_map(|unlock| match unlock {
+                Unlock::Upgrade(upgrade) if upgrade.building == ty => Some(upgrade.speed),
+                _ => None,
+            })
+            .product()
+    }
+
+    pub(crate) fn current_project(&self) -> Option<&'static ResearchProject> {
+        research_project(self.current.as_ref()?)
+    }
+
+    pub(crate) fn complete(&mut self, name: &str) {
+        if self.current.as_deref() == Some(name) {
+            self.current = None;
+        }
+        self.completed.insert(name.to_string());
+    }
+}
+
+/// A research project with its state in the colony.
+#[derive(Clone, Debug, Serialize)]
+pub struct ResearchStatus {
+    pub project: &'static ResearchProject,
+    pub completed: bool,
+    pub available: bool,
+    pub current: bool,
+}
+
+impl AsteroidColoniesGame {
+    pub fn research(&self) -> &Research {
+        &self.research
+    }
+
+    pub fn research_status(&self) -> Vec<ResearchStatus> {
+        ruleset()
+            .research
+            .iter()
+            .map(|project| ResearchStatus {
+                project,
+                completed: self.research.completed.contains(&project.name),
+                available: self.research.is_available(project),
+                current: self.research.current.as_ref() == Some(&project.name),
+            })
+            .collect()
+    }
+
+    /// Choose the project that laboratories work on, or stop researching with `None`.
+    pub fn set_research(&mut self, name: Option<&str>) -> Result<(), String> {
+        let Some(name) = name else {
+            self.research.current = None;
+            return Ok(());
+        };
+        let project = research_project(name)
+            .ok_or_else(|| format!("Research project {name} does not exist"))?;
+        if self.research.completed.contains(name) {
+            return Err(format!("Research project {name} is already completed"));
+        }
+        if !self.research.is_available(project) {
+            return Err(format!(
+                "Research project {name} requires {}",
+                project.requires.join(", ")
+            ));
+        }
+        self.research.current = Some(name.to_string());
+        Ok(())
+    }
+
+    /// The items in the build menu that are unlocked.
+    pub fn build_menu(&self) -> Vec<&'static BuildMenuItem> {
+        get_build_menu()
+            .iter()
+            .filter(|item| match item.type_ {
+                ConstructionType::Building(ty) => self.research.is_building_unlocked(ty),
+                _ => true,
+            })
+            .collect()
+    }
+}
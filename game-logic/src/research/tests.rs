@@ -0,0 +1,124 @@
+use super::*;
+use crate::{btree_map, stats::StatsWindow};
+
+fn assembler_pos(game: &AsteroidColoniesGame) -> [i32; 2] {
+    game.iter_building()
+        .find(|b| b.type_ == BuildingType::Assembler)
+        .unwrap()
+        .pos
+}
+
+fn recipe_outputs(game: &AsteroidColoniesGame) -> Vec<ItemType> {
+    let [x, y] = assembler_pos(game);
+    game.get_recipes(x, y)
+        .unwrap()
+        .iter()
+        .flat_map(|recipe| recipe.outputs.keys().copied())
+        .collect()
+}
+
+fn in_build_menu(game: &AsteroidColoniesGame, ty: BuildingType) -> bool {
+    game.build_menu()
+        .iter()
+        .any(|item| item.type_ == ConstructionType::Building(ty))
+}
+
+#[test]
+fn test_locked() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    assert!(!in_build_menu(&game, BuildingType::Battery));
+    assert!(in_build_menu(&game, BuildingType::Laboratory));
+    assert_eq!(
+        game.build(26, 50, BuildingType::Battery, None).unwrap_err(),
+        "Battery is not researched yet"
+    );
+    let outputs = recipe_outputs(&game);
+    assert!(outputs.contains(&ItemType::Gear));
+    assert!(!outputs.contains(&ItemType::Battery));
+    let [x, y] = assembler_pos(&game);
+    assert_eq!(
+        game.set_recipe(x, y, Some("Battery")).unwrap_err(),
+        "The recipe is not researched yet"
+    );
+
+    game.research.complete("Batteries");
+    assert!(in_build_menu(&game, BuildingType::Battery));
+    assert!(recipe_outputs(&game).contains(&ItemType::Battery));
+    game.set_recipe(x, y, Some("Battery")).unwrap();
+    game.build(26, 50, BuildingType::Battery, None).unwrap();
+}
+
+#[test]
+fn test_set_research() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    assert_eq!(
+        game.set_research(Some("Alchemy")).unwrap_err(),
+        "Research project Alchemy does not exist"
+    );
+    assert_eq!(
+        game.set_research(Some("Fast assembly")).unwrap_err(),
+        "Research project Fast assembly requires Automation"
+    );
+    game.set_research(Some("Automation")).unwrap();
+    assert_eq!(game.research().current.as_deref(), Some("Automation"));
+
+    game.research.complete("Automation");
+    assert_eq!(game.research().current, None);
+    assert_eq!(
+        game.set_research(Some("Automation")).unwrap_err(),
+        "Research project Automation is already completed"
+    );
+    let status = game.research_status();
+    let fast = status
+        .iter()
+        .find(|s| s.project.name == "Fast assembly")
+        .unwrap();
+    assert!(fast.available && !fast.completed);
+
+    assert_eq!(game.research().speed(BuildingType::Assembler), 1.);
+    game.research.complete("Fast assembly");
+    assert_eq!(game.research().speed(BuildingType::Assembler), 1.5);
+    assert_eq!(game.research().speed(BuildingType::Furnace), 1.);
+}
+
+#[test]
+fn test_laboratory() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    // Turn the assembler into a laboratory with the inputs, since it is powered
+    let lab = game
+        .buildings
+        .iter_mut()
+        .find(|b| b.type_ == BuildingType::Assembler)
+        .unwrap();
+    lab.type_ = BuildingType::Laboratory;
+    lab.inventory = btree_map!(ItemType::Wire => 5, ItemType::Circuit => 2).into();
+    game.set_research(Some("Batteries")).unwrap();
+
+    for _ in 0..2000 {
+        game.tick().unwrap();
+        if game.research().completed.contains("Batteries") {
+            break;
+        }
+    }
+    assert!(game.research().completed.contains("Batteries"));
+    assert_eq!(game.research().current, None);
+    assert!(in_build_menu(&game, BuildingType::Battery));
+
+    let lab = game
+        .iter_building()
+        .find(|b| b.type_ == BuildingType::Laboratory)
+        .unwrap();
+    assert_eq!(lab.inventory.get(&ItemType::Wire), 1);
+    assert_eq!(lab.inventory.get(&ItemType::Circuit), 0);
+    let total = game.production_stats().total(StatsWindow::Day);
+    assert_eq!(
+        total.consumed_by[&BuildingType::Laboratory][&ItemType::Wire],
+        4
+    );
+
+    // The research is saved
+    let mut copy = AsteroidColoniesGame::new(None).unwrap();
+    copy.deserialize(game.serialize(false).unwrap().as_bytes())
+        .unwrap();
+    assert!(copy.research().completed.contains("Batteries"));
+}
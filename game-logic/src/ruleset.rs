@@ -0,0 +1,353 @@
+//! Game rules loaded from data: items, recipes, the build menu and building stats.
+//!
+//! The ruleset is loaded once per process, before any game is created. If nobody loads one,
+//! the default ruleset embedded from `ruleset.json` is used. Loading the same ruleset again is
+//! allowed, so that a new game can be created with it, but a different one is rejected.
+//!
+//! Items are still variants of [`ItemType`], so a ruleset can choose a subset of them but
+//! cannot add new ones without changing the code.
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    building::{BuildingType, Recipe},
+    construction::{BuildMenuItem, ConstructionType},
+    items::ItemType,
+    research::{ResearchProject, Unlock},
+    transport::TILE_LENGTH,
+};
+
+const DEFAULT_RULESET: &str = include_str!("../ruleset.json");
+
+static RULESET: OnceLock<Ruleset> = OnceLock::new();
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ruleset {
+    /// Items available in this ruleset. Recipes and build costs can only refer to these.
+    /// They must be variants of [`ItemType`], which fails parsing otherwise.
+    pub items: Vec<ItemType>,
+    pub recipes: Vec<Recipe>,
+    pub build_menu: Vec<BuildMenuItem>,
+    pub power_grid: BuildCost,
+    pub conveyor: BuildCost,
+    /// Speed of the basic conveyor, in [`TILE_LENGTH`](crate::TILE_LENGTH) units per tick
+    #[serde(default = "default_conveyor_speed")]
+    pub conveyor_speed: u32,
+    /// Faster conveyor tiers than the basic one, from tier 1
+    #[serde(default)]
+    pub conveyor_tiers: Vec<ConveyorTierStats>,
+    /// Cost of splitters and mergers
+    pub splitter: BuildCost,
+    /// Cost of each entrance and exit of underground conveyors
+    pub underground: BuildCost,
+    pub buildings: Vec<BuildingStats>,
+    /// Items and time for a crew to move into a vacant room of a cabin. No crews come if it is
+    /// not defined.
+    #[serde(default)]
+    pub immigration: Option<BuildCost>,
+    /// Research projects. Recipes and buildings that no project unlocks are available from the
+    /// start.
+    #[serde(default)]
+    pub research: Vec<ResearchProject>,
+}
+
+/// Ingredients and time to build an infrastructure tile, which is not in the build menu.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildCost {
+    pub ingredients: BTreeMap<ItemType, usize>,
+    pub time: f64,
+}
+
+impl BuildCost {
+    pub(crate) fn to_build_menu_item(&self, type_: ConstructionType) -> BuildMenuItem {
+        BuildMenuItem {
+            type_,
+            ingredients: self.ingredients.clone(),
+            time: self.time,
+        }
+    }
+}
+
+fn default_conveyor_speed() -> u32 {
+    TILE_LENGTH
+}
+
+/// An upgraded conveyor tier, which moves items faster and costs more than the basic one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConveyorTierStats {
+    pub name: String,
+    /// In [`TILE_LENGTH`](crate::TILE_LENGTH) units per tick
+    pub speed: u32,
+    /// Cost of a conveyor tile of this tier, instead of `conveyor`. Splitters, mergers and
+    /// underground conveyors are always the basic tier.
+    pub cost: BuildCost,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildingStats {
+    pub type_: BuildingType,
+    /// Inventory capacity
+    pub capacity: usize,
+    pub size: [usize; 2],
+    #[serde(default)]
+    pub max_crews: usize,
+    /// Base generating (positive) or consuming (negative) power, in kW
+    #[serde(default)]
+    pub power_gen: isize,
+    #[serde(default)]
+    pub energy_capacity: Option<usize>,
+    /// The recipe that the building always works on, unlike assemblers whose recipe can be chosen
+    #[serde(default)]
+    pub recipe: Option<Recipe>,
+}
+
+impl Ruleset {
+    /// Parse a ruleset in JSON and validate it.
+    pub fn from_json(src: &str) -> Result<Self, String> {
+        let ruleset: Self =
+            serde_json::from_str(src).map_err(|e| format!("Failed to parse the ruleset: {e}"))?;
+        ruleset.validate()?;
+        Ok(ruleset)
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| e.to_string())
+    }
+
+    /// Check consistency of the ruleset, so that the game logic can assume it is sound.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut items = HashSet::new();
+        for item in &self.items {
+            if !items.insert(*item) {
+                return Err(format!("Item {item:?} is defined more than once"));
+            }
+        }
+
+        let check_items =
+            |what: &dyn Fn() -> String, list: &BTreeMap<ItemType, usize>| -> Result<(), String> {
+                for (item, amount) in list {
+                    if !items.contains(item) {
+                        return Err(format!("{} refers to undefined item {item:?}", what()));
+                    }
+                    if *amount == 0 {
+                        return Err(format!("{} has zero amount of {item:?}", what()));
+                    }
+                }
+                Ok(())
+            };
+        let check_time = |what: &dyn Fn() -> String, time: f64| {
+            if time.is_finite() && 0. < time {
+                Ok(())
+            } else {
+                Err(format!("{} has invalid time {time}", what()))
+            }
+        };
+
+        let mut outputs = HashSet::new();
+        for (i, recipe) in self.recipes.iter().enumerate() {
+            let what = || format!("Recipe #{i}");
+            check_items(&what, &recipe.inputs)?;
+            check_items(&what, &recipe.outputs)?;
+            check_time(&what, recipe.time)?;
+            // Recipes are selected by the name of their first output
+            let Some(output) = recipe.outputs.keys().next() else {
+                return Err(format!("{} has no outputs", what()));
+            };
+            if recipe.outputs.len() != 1 {
+                return Err(format!("{} must have exactly one output", what()));
+            }
+            if !outputs.insert(*output) {
+                return Err(format!(
+                    "{} produces {output:?}, which another recipe does too",
+                    what()
+                ));
+            }
+        }
+
+        let mut menu = HashSet::new();
+        for (i, item) in self.build_menu.iter().enumerate() {
+            let what = || format!("Build menu item #{i}");
+            let ConstructionType::Building(ty) = item.type_ else {
+                return Err(format!("{} must be a building", what()));
+            };
+            if !menu.insert(ty) {
+                return Err(format!("{} has building {ty} more than once", what()));
+            }
+            check_items(&what, &item.ingredients)?;
+            check_time(&what, item.time)?;
+        }
+
+        for (name, cost) in [
+            ("Power grid", Some(&self.power_grid)),
+            ("Conveyor", Some(&self.conveyor)),
+            ("Splitter", Some(&self.splitter)),
+            ("Underground conveyor", Some(&self.underground)),
+            ("Immigration", self.immigration.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, cost)| Some((name, cost?)))
+        {
+            let what = || format!("{name} cost");
+            check_items(&what, &cost.ingredients)?;
+            check_time(&what, cost.time)?;
+        }
+
+        if self.conveyor_speed == 0 {
+            return Err("Conveyor has zero speed".to_string());
+        }
+        if u8::MAX as usize <= self.conveyor_tiers.len() {
+            return Err(format!("There can be at most {} conveyor tiers", u8::MAX));
+        }
+        let mut tier_names = HashSet::new();
+        for (i, tier) in self.conveyor_tiers.iter().enumerate() {
+            let what = || format!("Conveyor tier {}", i + 1);
+            if tier.speed == 0 {
+                return Err(format!("{} has zero speed", what()));
+            }
+            if !tier_names.insert(tier.name.as_str()) {
+                return Err(format!(
+                    "{} has name {}, which another tier does too",
+                    what(),
+                    tier.name
+                ));
+            }
+            check_items(&what, &tier.cost.ingredients)?;
+            check_time(&what, tier.cost.time)?;
+        }
+
+        for ty in BuildingType::all() {
+            let count = self.buildings.iter().filter(|b| b.type_ == ty).count();
+            if count == 0 {
+                return Err(format!("Building {ty} has no stats"));
+            }
+            if 1 < count {
+                return Err(format!("Building {ty} has stats defined more than once"));
+            }
+        }
+        for stats in &self.buildings {
+            if stats.size[0] == 0 || stats.size[1] == 0 {
+                return Err(format!("Building {} has zero size", stats.type_));
+            }
+            if let Some(recipe) = &stats.recipe {
+                let what = || format!("Recipe of building {}", stats.type_);
+                check_items(&what, &recipe.inputs)?;
+                check_items(&what, &recipe.outputs)?;
+                check_time(&what, recipe.time)?;
+                if recipe.outputs.is_empty() {
+                    return Err(format!("{} has no outputs", what()));
+                }
+            }
+        }
+
+        let mut projects = HashSet::new();
+        for (i, project) in self.research.iter().enumerate() {
+            let what = || format!("Research project #{i}");
+            check_items(&what, &project.inputs)?;
+            check_time(&what, project.time)?;
+            // Requiring only the projects defined before makes sure there are no cycles
+            for required in &project.requires {
+                if !projects.contains(required.as_str()) {
+                    return Err(format!(
+                        "{} requires {required}, which is not defined before it",
+                        what()
+                    ));
+                }
+            }
+            for unlock in &project.unlocks {
+                match unlock {
+                    Unlock::Recipe(item) => {
+                        if !outputs.contains(item) {
+                            return Err(format!(
+                                "{} unlocks a recipe of {item:?}, which does not exist",
+                                what()
+                            ));
+                        }
+                    }
+                    Unlock::Building(ty) => {
+                        if !menu.contains(ty) {
+                            return Err(format!(
+                                "{} unlocks building {ty}, which is not in the build menu",
+                                what()
+                            ));
+                        }
+                    }
+                    Unlock::Upgrade(upgrade) => {
+                        if !upgrade.speed.is_finite() || upgrade.speed <= 0. {
+                            return Err(format!(
+                                "{} has invalid upgrade speed {}",
+                                what(),
+                                upgrade.speed
+                            ));
+                        }
+                    }
+                }
+            }
+            if !projects.insert(project.name.as_str()) {
+                return Err(format!(
+                    "{} has name {}, which another project does too",
+                    what(),
+                    project.name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The speed of a conveyor tier. Unknown tiers fall back to the basic conveyor.
+    pub fn conveyor_speed(&self, tier: u8) -> u32 {
+        tier.checked_sub(1)
+            .and_then(|i| self.conveyor_tiers.get(i as usize))
+            .map(|tier| tier.speed)
+            .unwrap_or(self.conveyor_speed)
+    }
+
+    pub fn conveyor_tier_name(&self, tier: u8) -> &str {
+        tier.checked_sub(1)
+            .and_then(|i| self.conveyor_tiers.get(i as usize))
+            .map(|tier| tier.name.as_str())
+            .unwrap_or("Basic")
+    }
+
+    pub fn building(&self, ty: BuildingType) -> &BuildingStats {
+        self.buildings
+            .iter()
+            .find(|b| b.type_ == ty)
+            .expect("The ruleset should have been validated to have all building types")
+    }
+}
+
+/// Set the ruleset for this process. It must be called before any game is created, because
+/// the game logic assumes the rules do not change during the play. It succeeds without doing
+/// anything if the same ruleset is already loaded.
+pub fn load_ruleset(ruleset: Ruleset) -> Result<(), String> {
+    ruleset.validate()?;
+    let json = ruleset.to_json()?;
+    // Compared in JSON, since the ruleset has floats and does not implement Eq
+    if RULESET.set(ruleset).is_err()
+        && RULESET.get().map(|loaded| loaded.to_json()) != Some(Ok(json))
+    {
+        return Err("A different ruleset is already loaded".to_string());
+    }
+    Ok(())
+}
+
+/// Returns the ruleset in use, loading the default one if nothing is loaded yet.
+pub fn ruleset() -> &'static Ruleset {
+    RULESET.get_or_init(default_ruleset)
+}
+
+pub fn default_ruleset() -> Ruleset {
+    Ruleset::from_json(DEFAULT_RULESET).expect("The default ruleset should be valid")
+}
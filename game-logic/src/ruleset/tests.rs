@@ -0,0 +1,169 @@
+use super::*;
+
+fn default_json() -> serde_json::Value {
+    serde_json::from_str(DEFAULT_RULESET).unwrap()
+}
+
+fn validate_json(value: serde_json::Value) -> Result<Ruleset, String> {
+    Ruleset::from_json(&value.to_string())
+}
+
+#[test]
+fn test_default_ruleset() {
+    let ruleset = default_ruleset();
+    assert_eq!(ruleset.building(BuildingType::Power).power_gen, 250);
+    assert_eq!(ruleset.building(BuildingType::CrewCabin).size, [2, 2]);
+    assert_eq!(ruleset.building(BuildingType::CrewCabin).max_crews, 4);
+    assert_eq!(
+        ruleset.building(BuildingType::Battery).energy_capacity,
+        Some(10000)
+    );
+
+    // Round trip
+    let reloaded = Ruleset::from_json(&ruleset.to_json().unwrap()).unwrap();
+    assert_eq!(reloaded.recipes.len(), ruleset.recipes.len());
+}
+
+#[test]
+fn test_undefined_item() {
+    let mut json = default_json();
+    let items = json["items"].as_array_mut().unwrap();
+    items.retain(|item| item != "Gear");
+    assert_eq!(
+        validate_json(json).unwrap_err(),
+        "Recipe #2 refers to undefined item Gear"
+    );
+}
+
+#[test]
+fn test_missing_building() {
+    let mut json = default_json();
+    let buildings = json["buildings"].as_array_mut().unwrap();
+    buildings.retain(|b| b["type_"] != "Furnace");
+    assert_eq!(
+        validate_json(json).unwrap_err(),
+        "Building Furnace has no stats"
+    );
+}
+
+#[test]
+fn test_invalid_build_menu() {
+    let mut json = default_json();
+    json["build_menu"][0]["time"] = serde_json::json!(0.);
+    assert_eq!(
+        validate_json(json).unwrap_err(),
+        "Build menu item #0 has invalid time 0"
+    );
+
+    let mut json = default_json();
+    json["build_menu"][0]["type_"] = serde_json::json!("PowerGrid");
+    assert_eq!(
+        validate_json(json).unwrap_err(),
+        "Build menu item #0 must be a building"
+    );
+}
+
+#[test]
+fn test_parse_error() {
+    let mut json = default_json();
+    json["items"][0] = serde_json::json!("Unobtainium");
+    let err = validate_json(json).unwrap_err();
+    assert!(err.starts_with("Failed to parse the ruleset: unknown variant `Unobtainium`"));
+}
+
+#[test]
+fn test_invalid_research() {
+    let mut json = default_json();
+    json["research"][0]["requires"] = serde_json::json!(["Automation"]);
+    assert_eq!(
+        validate_json(json).unwrap_err(),
+        "Research project #0 requires Automation, which is not defined before it"
+    );
+
+    let mut json = default_json();
+    json["research"][1]["name"] = serde_json::json!("Batteries");
+    assert_eq!(
+        validate_json(json).unwrap_err(),
+        "Research project #1 has name Batteries, which another project does too"
+    );
+
+    let mut json = default_json();
+    json["research"][0]["unlocks"] = serde_json::json!([{ "Recipe": "RawOre" }]);
+    assert_eq!(
+        validate_json(json).unwrap_err(),
+        "Research project #0 unlocks a recipe of RawOre, which does not exist"
+    );
+
+    let mut json = default_json();
+    json["research"][0]["unlocks"] = serde_json::json!([{ "Building": "Power" }]);
+    assert_eq!(
+        validate_json(json).unwrap_err(),
+        "Research project #0 unlocks building Power, which is not in the build menu"
+    );
+
+    // Rulesets without research are still valid
+    let mut json = default_json();
+    json.as_object_mut().unwrap().remove("research");
+    assert!(validate_json(json).unwrap().research.is_empty());
+}
+
+#[test]
+fn test_invalid_building_recipe() {
+    let mut json = default_json();
+    let buildings = json["buildings"].as_array_mut().unwrap();
+    let greenhouse = buildings
+        .iter_mut()
+        .find(|b| b["type_"] == "Greenhouse")
+        .unwrap();
+    greenhouse["recipe"]["outputs"] = serde_json::json!({});
+    assert_eq!(
+        validate_json(json).unwrap_err(),
+        "Recipe of building Greenhouse has no outputs"
+    );
+}
+
+#[test]
+fn test_conveyor_tiers() {
+    let ruleset = default_ruleset();
+    assert_eq!(ruleset.conveyor_speed(0), TILE_LENGTH);
+    assert_eq!(ruleset.conveyor_tier_name(0), "Basic");
+    assert_eq!(ruleset.conveyor_tier_name(2), "Express");
+    assert!(ruleset.conveyor_speed(1) < ruleset.conveyor_speed(2));
+    // Unknown tiers fall back to the basic one
+    assert_eq!(ruleset.conveyor_speed(100), TILE_LENGTH);
+
+    let mut json = default_json();
+    json.as_object_mut().unwrap().remove("conveyor_tiers");
+    json.as_object_mut().unwrap().remove("conveyor_speed");
+    let ruleset = validate_json(json).unwrap();
+    assert!(ruleset.conveyor_tiers.is_empty());
+    assert_eq!(ruleset.conveyor_speed, TILE_LENGTH);
+
+    let mut json = default_json();
+    json["conveyor_tiers"][1]["speed"] = serde_json::json!(0);
+    assert_eq!(
+        validate_json(json).unwrap_err(),
+        "Conveyor tier 2 has zero speed"
+    );
+
+    let mut json = default_json();
+    json["conveyor_tiers"][1]["name"] = serde_json::json!("Fast");
+    assert_eq!(
+        validate_json(json).unwrap_err(),
+        "Conveyor tier 2 has name Fast, which another tier does too"
+    );
+}
+
+#[test]
+fn test_load_ruleset_again() {
+    // The default ruleset may be loaded already by other tests, but loading it again is fine
+    load_ruleset(default_ruleset()).unwrap();
+    load_ruleset(default_ruleset()).unwrap();
+
+    let mut json = default_json();
+    json["conveyor"]["time"] = serde_json::json!(123.);
+    assert_eq!(
+        load_ruleset(validate_json(json).unwrap()).unwrap_err(),
+        "A different ruleset is already loaded"
+    );
+}
@@ -0,0 +1,198 @@
+//! Job priorities and experience of crews.
+//!
+//! Crews at home are just a count in their cabin, so the cabin keeps the skills of each crew
+//! living in it, indexed by [`Crew::member`]. A dispatched crew carries the index and gains
+//! experience in its own entry, which it keeps when it returns. Priorities can be set for the
+//! whole cabin or overridden per crew.
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    building::{Building, BuildingId},
+    entity::EntitySet,
+    AsteroidColoniesGame, Crew, Pos,
+};
+
+/// Kinds of work that crews are sent out for
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum Job {
+    Excavate,
+    Build,
+    /// Carrying items to constructions
+    Haul,
+    Cleanup,
+}
+
+impl Job {
+    pub fn all() -> [Self; 4] {
+        [Self::Excavate, Self::Build, Self::Haul, Self::Cleanup]
+    }
+}
+
+pub const DEFAULT_PRIORITY: u8 = 2;
+pub const MAX_PRIORITY: u8 = 3;
+/// Experience in ticks of work, at which crews work 1.5 times faster
+const EXPERIENCE_SCALE: f64 = 3000.;
+/// The limit of the speed bonus by experience
+const MAX_SPEED_BONUS: f64 = 1.;
+
+/// Job priorities and experience of a crew
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CrewSkills {
+    /// The priorities of this crew, which take precedence over the ones of the cabin
+    pub priorities: BTreeMap<Job, u8>,
+    /// Ticks that this crew has worked in each job
+    pub experience: BTreeMap<Job, f64>,
+}
+
+impl CrewSkills {
+    /// The multiplier to the working speed, which grows with experience but never doubles.
+    pub fn speed(&self, job: Job) -> f64 {
+        let experience = self.experience.get(&job).copied().unwrap_or(0.);
+        1. + MAX_SPEED_BONUS * experience / (experience + EXPERIENCE_SCALE)
+    }
+
+    pub(crate) fn gain(&mut self, job: Job) {
+        *self.experience.entry(job).or_default() += 1.;
+    }
+}
+
+/// Job priorities of the crews living in a cabin, and the skills of each of them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CabinSkills {
+    /// Crews take the jobs with higher priority first, and never the ones with 0. Crews follow
+    /// these priorities unless they have their own, and jobs in neither have
+    /// [`DEFAULT_PRIORITY`].
+    pub priorities: BTreeMap<Job, u8>,
+    /// The skills of the crews indexed by [`Crew::member`]. Crews without an entry have neither
+    /// their own priorities nor experience yet.
+    pub crews: Vec<CrewSkills>,
+}
+
+impl CabinSkills {
+    /// The priority of a job for a crew, or for the cabin if `member` is `None`.
+    pub fn priority(&self, member: Option<usize>, job: Job) -> u8 {
+        member
+            .and_then(|member| self.crews.get(member))
+            .and_then(|crew| crew.priorities.get(&job))
+            .or_else(|| self.priorities.get(&job))
+            .copied()
+            .unwrap_or(DEFAULT_PRIORITY)
+    }
+
+    pub fn set_priority(&mut self, job: Job, priority: u8) -> Result<(), String> {
+        check_priority(priority)?;
+        self.priorities.insert(job, priority);
+        Ok(())
+    }
+
+    /// Set the priority of a job for a crew, or let it follow the cabin if `priority` is `None`.
+    pub fn set_crew_priority(
+        &mut self,
+        member: usize,
+        job: Job,
+        priority: Option<u8>,
+    ) -> Result<(), String> {
+        let crew = self.crew_mut(member);
+        if let Some(priority) = priority {
+            check_priority(priority)?;
+            crew.priorities.insert(job, priority);
+        } else {
+            crew.priorities.remove(&job);
+        }
+        Ok(())
+    }
+
+    /// The multiplier to the working speed of a crew by its experience.
+    pub fn speed(&self, member: Option<usize>, job: Job) -> f64 {
+        member
+            .and_then(|member| self.crews.get(member))
+            .map_or(1., |crew| crew.speed(job))
+    }
+
+    pub(crate) fn gain(&mut self, member: Option<usize>, job: Job) {
+        if let Some(member) = member {
+            self.crew_mut(member).gain(job);
+        }
+    }
+
+    fn crew_mut(&mut self, member: usize) -> &mut CrewSkills {
+        if self.crews.len() <= member {
+            self.crews.resize_with(member + 1, CrewSkills::default);
+        }
+        &mut self.crews[member]
+    }
+
+    /// Distinct priorities of the jobs enabled for a crew, from the highest.
+    pub(crate) fn priority_levels(&self, member: Option<usize>) -> Vec<u8> {
+        let mut levels: Vec<_> = Job::all()
+            .into_iter()
+            .map(|job| self.priority(member, job))
+            .filter(|priority| 0 < *priority)
+            .collect();
+        levels.sort_unstable_by(|a, b| b.cmp(a));
+        levels.dedup();
+        levels
+    }
+}
+
+fn check_priority(priority: u8) -> Result<(), String> {
+    if MAX_PRIORITY < priority {
+        return Err(format!("Priority must be at most {MAX_PRIORITY}"));
+    }
+    Ok(())
+}
+
+impl Building {
+    /// The indices of the crews living in this cabin that are at home.
+    pub(crate) fn members_at_home(&self, id: BuildingId, crews: &EntitySet<Crew>) -> Vec<usize> {
+        (0..self.population)
+            .filter(|member| {
+                !crews
+                    .iter()
+                    .any(|crew| crew.from == id && crew.member == Some(*member))
+            })
+            .collect()
+    }
+}
+
+impl AsteroidColoniesGame {
+    fn cabin_skills_mut(&mut self, pos: Pos) -> Result<(usize, &mut CabinSkills), String> {
+        let Some(cabin) = self.buildings.iter_mut().find(|b| b.intersects(pos)) else {
+            return Err("The building does not exist at the target".to_string());
+        };
+        if !cabin.type_.is_cabin() {
+            return Err("The building is not a crew cabin".to_string());
+        }
+        let population = cabin.population;
+        Ok((
+            population,
+            cabin.skills.get_or_insert_with(CabinSkills::default),
+        ))
+    }
+
+    /// Set the priority of a job for the crews living in the cabin at the position.
+    pub fn set_job_priority(&mut self, pos: Pos, job: Job, priority: u8) -> Result<(), String> {
+        self.cabin_skills_mut(pos)?.1.set_priority(job, priority)
+    }
+
+    /// Set the priority of a job for one of the crews living in the cabin at the position, or
+    /// let it follow the priority of the cabin if `priority` is `None`.
+    pub fn set_crew_job_priority(
+        &mut self,
+        pos: Pos,
+        member: usize,
+        job: Job,
+        priority: Option<u8>,
+    ) -> Result<(), String> {
+        let (population, skills) = self.cabin_skills_mut(pos)?;
+        if population <= member {
+            return Err(format!("The cabin does not have crew {member}"));
+        }
+        skills.set_crew_priority(member, job, priority)
+    }
+}
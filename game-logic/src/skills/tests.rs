@@ -0,0 +1,119 @@
+use super::*;
+use crate::building::BuildingType;
+
+fn cabin_pos(game: &AsteroidColoniesGame) -> Pos {
+    game.iter_building()
+        .find(|b| b.type_ == BuildingType::CrewCabin)
+        .unwrap()
+        .pos
+}
+
+fn cabin_skills(game: &AsteroidColoniesGame) -> CabinSkills {
+    game.iter_building()
+        .find(|b| b.type_ == BuildingType::CrewCabin)
+        .unwrap()
+        .skills
+        .clone()
+        .unwrap()
+}
+
+#[test]
+fn test_priorities() {
+    let mut skills = CabinSkills::default();
+    assert_eq!(skills.priority(None, Job::Build), DEFAULT_PRIORITY);
+    assert_eq!(skills.priority_levels(None), vec![DEFAULT_PRIORITY]);
+    skills.set_priority(Job::Haul, 3).unwrap();
+    skills.set_priority(Job::Cleanup, 0).unwrap();
+    assert_eq!(skills.priority_levels(None), vec![3, DEFAULT_PRIORITY]);
+    assert_eq!(
+        skills.set_priority(Job::Haul, 4).unwrap_err(),
+        "Priority must be at most 3"
+    );
+
+    // A crew follows the cabin unless it has its own priority
+    skills.set_crew_priority(1, Job::Haul, Some(1)).unwrap();
+    assert_eq!(skills.priority(Some(0), Job::Haul), 3);
+    assert_eq!(skills.priority(Some(1), Job::Haul), 1);
+    assert_eq!(skills.priority(Some(1), Job::Build), DEFAULT_PRIORITY);
+    assert_eq!(skills.priority_levels(Some(1)), vec![DEFAULT_PRIORITY, 1]);
+    skills.set_crew_priority(1, Job::Haul, None).unwrap();
+    assert_eq!(skills.priority(Some(1), Job::Haul), 3);
+
+    assert_eq!(skills.speed(Some(0), Job::Build), 1.);
+    skills.gain(Some(0), Job::Build);
+    skills.crews[0]
+        .experience
+        .insert(Job::Build, EXPERIENCE_SCALE);
+    assert_eq!(skills.speed(Some(0), Job::Build), 1.5);
+    assert_eq!(skills.speed(Some(1), Job::Build), 1.);
+    assert_eq!(skills.speed(None, Job::Build), 1.);
+}
+
+#[test]
+fn test_disabled_job() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let pos = cabin_pos(&game);
+    assert_eq!(
+        game.set_job_priority([0, 0], Job::Excavate, 0).unwrap_err(),
+        "The building does not exist at the target"
+    );
+    game.set_job_priority(pos, Job::Excavate, 0).unwrap();
+    game.excavate(30, 50, None).unwrap();
+    game.tick().unwrap();
+    assert_eq!(game.iter_crew().count(), 0);
+
+    game.set_job_priority(pos, Job::Excavate, 1).unwrap();
+    game.tick().unwrap();
+    assert_eq!(game.iter_crew().count(), 1);
+}
+
+#[test]
+fn test_experience() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.excavate(30, 50, None).unwrap();
+    for _ in 0..100 {
+        game.tick().unwrap();
+    }
+    let skills = cabin_skills(&game);
+    // Only the crew that excavated gains the experience
+    let excavate = skills.crews[0].experience[&Job::Excavate];
+    assert!(0. < excavate, "{excavate}");
+    assert!(1. < skills.speed(Some(0), Job::Excavate));
+    assert_eq!(skills.speed(Some(0), Job::Build), 1.);
+    assert!(skills.crews[1..]
+        .iter()
+        .all(|crew| crew.experience.is_empty()));
+
+    // The skills are saved
+    let mut copy = AsteroidColoniesGame::new(None).unwrap();
+    copy.deserialize(game.serialize(false).unwrap().as_bytes())
+        .unwrap();
+    assert_eq!(cabin_skills(&copy), skills);
+}
+
+#[test]
+fn test_crew_priority() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let pos = cabin_pos(&game);
+    game.set_job_priority(pos, Job::Excavate, 0).unwrap();
+    assert_eq!(
+        game.set_crew_job_priority(pos, 100, Job::Excavate, Some(1))
+            .unwrap_err(),
+        "The cabin does not have crew 100"
+    );
+    game.set_crew_job_priority(pos, 1, Job::Excavate, Some(1))
+        .unwrap();
+    game.excavate(30, 50, None).unwrap();
+    game.tick().unwrap();
+    let crews: Vec<_> = game.iter_crew().map(|crew| crew.member).collect();
+    assert_eq!(crews, vec![Some(1)]);
+
+    // The crew keeps its experience after returning home
+    for _ in 0..1000 {
+        game.tick().unwrap();
+    }
+    assert_eq!(game.iter_crew().count(), 0);
+    let skills = cabin_skills(&game);
+    assert!(0. < skills.crews[1].experience[&Job::Excavate]);
+    assert!(skills.crews[0].experience.is_empty());
+}
@@ -0,0 +1,144 @@
+//! Stable hashes of the simulated state, to detect desyncs between the server and the clients.
+//!
+//! The hashes must not depend on the iteration order of HashMaps, which differs between
+//! processes, so the tiles are hashed in the order of coordinates and the entities in the order
+//! of their ids.
+#[cfg(test)]
+mod tests;
+
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::{EntityDigests, EntitySet},
+    sync::EntitiesDigest,
+    tile::new_hasher,
+    AsteroidColoniesGame, HEIGHT, WIDTH,
+};
+
+/// Hashes of each subsystem of the game state, so that we can tell which one diverged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateHash {
+    /// The global time and the random number generator
+    pub time: u64,
+    pub tiles: u64,
+    pub buildings: u64,
+    pub crews: u64,
+    pub global_tasks: u64,
+    pub transports: u64,
+    pub constructions: u64,
+    pub item_piles: u64,
+    pub research: u64,
+    /// The undo and redo stacks of the players
+    pub history: u64,
+}
+
+impl StateHash {
+    fn subsystems(&self) -> [(&'static str, u64); 10] {
+        [
+            ("time", self.time),
+            ("tiles", self.tiles),
+            ("buildings", self.buildings),
+            ("crews", self.crews),
+            ("global_tasks", self.global_tasks),
+            ("transports", self.transports),
+            ("constructions", self.constructions),
+            ("item_piles", self.item_piles),
+            ("research", self.research),
+            ("history", self.history),
+        ]
+    }
+
+    pub fn from_json(src: &str) -> Result<Self, String> {
+        serde_json::from_str(src).map_err(|e| format!("{e}"))
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| format!("{e}"))
+    }
+
+    /// A single hash of the whole state.
+    pub fn combined(&self) -> u64 {
+        let mut hasher = new_hasher();
+        for (_, hash) in self.subsystems() {
+            hash.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Names of the subsystems whose hashes differ from `other`.
+    pub fn diverged(&self, other: &Self) -> Vec<&'static str> {
+        self.subsystems()
+            .into_iter()
+            .zip(other.subsystems())
+            .filter(|((_, lhs), (_, rhs))| lhs != rhs)
+            .map(|((name, _), _)| name)
+            .collect()
+    }
+}
+
+impl AsteroidColoniesGame {
+    pub fn state_hash(&self) -> StateHash {
+        self.state_hash_with(&self.entities_digest())
+    }
+
+    /// Compute the state hash reusing the digests of the entities, which should be the result
+    /// of [`Self::entities_digest`] for the current state.
+    pub(crate) fn state_hash_with(&self, entities: &EntitiesDigest) -> StateHash {
+        let mut hasher = new_hasher();
+        self.global_time.hash(&mut hasher);
+        bincode::serialize(&self.rng)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        let time = hasher.finish();
+
+        // The cached hashes of the chunks depend on when they were uniformified and do not
+        // cover the ores, so we hash the tiles themselves.
+        let mut hasher = new_hasher();
+        let mut buf = vec![];
+        for iy in 0..HEIGHT as i32 {
+            for ix in 0..WIDTH as i32 {
+                buf.clear();
+                bincode::serialize_into(&mut buf, &self.tiles[[ix, iy]])
+                    .expect("Tile should be serializable");
+                buf.hash(&mut hasher);
+            }
+        }
+        let tiles = hasher.finish();
+
+        let mut hasher = new_hasher();
+        bincode::serialize(&self.research)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        let research = hasher.finish();
+
+        let mut hasher = new_hasher();
+        bincode::serialize(&self.history)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        let history = hasher.finish();
+
+        StateHash {
+            time,
+            tiles,
+            buildings: hash_entities(&self.buildings, &entities.buildings),
+            crews: hash_entities(&self.crews, &entities.crews),
+            global_tasks: hash_entities(&self.global_tasks, &entities.global_tasks),
+            transports: hash_entities(&self.transports, &entities.transports),
+            constructions: hash_entities(&self.constructions, &entities.constructions),
+            item_piles: hash_entities(&self.item_piles, &entities.item_piles),
+            research,
+            history,
+        }
+    }
+}
+
+fn hash_entities<T>(set: &EntitySet<T>, digests: &EntityDigests<T>) -> u64 {
+    let mut hasher = new_hasher();
+    for (id, _) in set.items() {
+        id.hash(&mut hasher);
+        digests.get(&id).hash(&mut hasher);
+    }
+    hasher.finish()
+}
@@ -0,0 +1,34 @@
+use super::*;
+
+#[test]
+fn test_state_hash_stable() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.excavate(30, 50, None).unwrap();
+    for _ in 0..20 {
+        game.tick().unwrap();
+    }
+    let hash = game.state_hash();
+    assert_eq!(hash, game.state_hash());
+
+    // A deserialized copy has HashMaps with different iteration order, but the same hash
+    let mut copy = AsteroidColoniesGame::new(None).unwrap();
+    copy.deserialize_bin(&game.serialize_bin().unwrap())
+        .unwrap();
+    assert_eq!(copy.state_hash(), hash);
+    assert_eq!(copy.state_hash().combined(), hash.combined());
+}
+
+#[test]
+fn test_state_hash_diverged() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    let base = game.state_hash();
+    assert!(base.diverged(&base).is_empty());
+
+    game.excavate(30, 50, None).unwrap();
+    assert_eq!(game.state_hash().diverged(&base), vec!["global_tasks"]);
+
+    game.tick().unwrap();
+    let diverged = game.state_hash().diverged(&base);
+    assert!(diverged.contains(&"time"));
+    assert_ne!(game.state_hash().combined(), base.combined());
+}
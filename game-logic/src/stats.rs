@@ -0,0 +1,192 @@
+//! Production and consumption statistics, accumulated in rolling windows for graphs.
+//!
+//! Each window keeps [`STATS_BUCKETS`] buckets, and each bucket aggregates the ticks in a
+//! period of the window, so that we can draw a graph of the last minute, hour or day without
+//! keeping every tick.
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{building::BuildingType, items::ItemType, transport::TransportPayload};
+
+/// The number of buckets in a window, which is the resolution of the graphs.
+pub const STATS_BUCKETS: usize = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsWindow {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl StatsWindow {
+    pub const ALL: [Self; 3] = [Self::Minute, Self::Hour, Self::Day];
+
+    /// The number of ticks aggregated in a bucket. If a tick is regarded as a second, the
+    /// whole window spans a minute, an hour and a day respectively.
+    pub fn period(&self) -> usize {
+        match self {
+            Self::Minute => 1,
+            Self::Hour => 60,
+            Self::Day => 24 * 60,
+        }
+    }
+}
+
+/// Counters accumulated over a period of ticks.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StatsCounters {
+    /// The global time at the start of the period
+    pub start: usize,
+    /// The number of ticks accumulated so far
+    pub ticks: usize,
+    pub produced: BTreeMap<ItemType, usize>,
+    /// Items consumed by recipes and constructions
+    pub consumed: BTreeMap<ItemType, usize>,
+    pub produced_by: BTreeMap<BuildingType, BTreeMap<ItemType, usize>>,
+    /// Items consumed by recipes of each building type. Constructions are not included.
+    pub consumed_by: BTreeMap<BuildingType, BTreeMap<ItemType, usize>>,
+    /// Generated power summed over the ticks, in kW ticks
+    pub power_generated: f64,
+    /// Used power summed over the ticks, in kW ticks
+    pub power_consumed: f64,
+    /// Energy stored in batteries at the end of the period
+    pub energy: usize,
+    /// Items delivered by transports, which measures the throughput of conveyors
+    pub delivered: BTreeMap<ItemType, usize>,
+    /// The number of transports delivered, including loads of ores
+    pub deliveries: usize,
+}
+
+impl StatsCounters {
+    fn merge(&mut self, other: &StatsCounters) {
+        fn add_items(dst: &mut BTreeMap<ItemType, usize>, src: &BTreeMap<ItemType, usize>) {
+            for (item, count) in src {
+                *dst.entry(*item).or_default() += count;
+            }
+        }
+        self.ticks += other.ticks;
+        add_items(&mut self.produced, &other.produced);
+        add_items(&mut self.consumed, &other.consumed);
+        for (ty, items) in &other.produced_by {
+            add_items(self.produced_by.entry(*ty).or_default(), items);
+        }
+        for (ty, items) in &other.consumed_by {
+            add_items(self.consumed_by.entry(*ty).or_default(), items);
+        }
+        add_items(&mut self.delivered, &other.delivered);
+        self.deliveries += other.deliveries;
+        self.power_generated += other.power_generated;
+        self.power_consumed += other.power_consumed;
+        self.energy = other.energy;
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ProductionStats {
+    /// Counters of the tick in progress
+    #[serde(skip)]
+    current: StatsCounters,
+    minute: VecDeque<StatsCounters>,
+    hour: VecDeque<StatsCounters>,
+    day: VecDeque<StatsCounters>,
+}
+
+impl ProductionStats {
+    pub(crate) fn produce(&mut self, building: BuildingType, item: ItemType, count: usize) {
+        *self.current.produced.entry(item).or_default() += count;
+        *self
+            .current
+            .produced_by
+            .entry(building)
+            .or_default()
+            .entry(item)
+            .or_default() += count;
+    }
+
+    /// Record consumed items. `building` is `None` if a construction consumed them.
+    pub(crate) fn consume(&mut self, building: Option<BuildingType>, item: ItemType, count: usize) {
+        *self.current.consumed.entry(item).or_default() += count;
+        if let Some(building) = building {
+            *self
+                .current
+                .consumed_by
+                .entry(building)
+                .or_default()
+                .entry(item)
+                .or_default() += count;
+        }
+    }
+
+    pub(crate) fn deliver(&mut self, payload: &TransportPayload) {
+        if let TransportPayload::Item(item, count) = payload {
+            *self.current.delivered.entry(*item).or_default() += count;
+        }
+        self.current.deliveries += 1;
+    }
+
+    pub(crate) fn record_power(&mut self, generated: f64, consumed: f64, energy: usize) {
+        self.current.power_generated += generated;
+        self.current.power_consumed += consumed;
+        self.current.energy = energy;
+    }
+
+    /// Add the counters of the tick at `global_time` to the windows.
+    pub(crate) fn end_tick(&mut self, global_time: usize) {
+        let mut current = std::mem::take(&mut self.current);
+        current.start = global_time;
+        current.ticks = 1;
+        for window in StatsWindow::ALL {
+            let period = window.period();
+            let start = global_time - global_time % period;
+            let buckets = self.window_mut(window);
+            if buckets.back().map(|bucket| bucket.start) != Some(start) {
+                buckets.push_back(StatsCounters {
+                    start,
+                    ..StatsCounters::default()
+                });
+            }
+            if let Some(bucket) = buckets.back_mut() {
+                bucket.merge(&current);
+            }
+            while STATS_BUCKETS < buckets.len() {
+                buckets.pop_front();
+            }
+        }
+    }
+
+    /// The buckets of a window, from the oldest to the latest. The latest one may still be
+    /// accumulating.
+    pub fn window(&self, window: StatsWindow) -> &VecDeque<StatsCounters> {
+        match window {
+            StatsWindow::Minute => &self.minute,
+            StatsWindow::Hour => &self.hour,
+            StatsWindow::Day => &self.day,
+        }
+    }
+
+    fn window_mut(&mut self, window: StatsWindow) -> &mut VecDeque<StatsCounters> {
+        match window {
+            StatsWindow::Minute => &mut self.minute,
+            StatsWindow::Hour => &mut self.hour,
+            StatsWindow::Day => &mut self.day,
+        }
+    }
+
+    /// The counters summed over all buckets in a window.
+    pub fn total(&self, window: StatsWindow) -> StatsCounters {
+        let buckets = self.window(window);
+        let mut total = StatsCounters {
+            start: buckets.front().map_or(0, |bucket| bucket.start),
+            ..StatsCounters::default()
+        };
+        for bucket in buckets {
+            total.merge(bucket);
+        }
+        total
+    }
+}
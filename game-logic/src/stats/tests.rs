@@ -0,0 +1,72 @@
+use super::*;
+use crate::AsteroidColoniesGame;
+
+#[test]
+fn test_windows() {
+    let mut stats = ProductionStats::default();
+    for t in 0..200 {
+        stats.produce(BuildingType::Assembler, ItemType::Gear, 1);
+        stats.consume(Some(BuildingType::Assembler), ItemType::IronIngot, 2);
+        stats.consume(None, ItemType::Wire, 1);
+        stats.record_power(10., 5., t);
+        stats.end_tick(t);
+    }
+
+    let minute = stats.window(StatsWindow::Minute);
+    assert_eq!(minute.len(), STATS_BUCKETS);
+    assert_eq!(minute.front().unwrap().start, 140);
+    assert_eq!(minute.back().unwrap().ticks, 1);
+
+    let hour = stats.window(StatsWindow::Hour);
+    assert_eq!(
+        hour.iter().map(|b| b.start).collect::<Vec<_>>(),
+        [0, 60, 120, 180]
+    );
+    // The latest bucket is still accumulating
+    assert_eq!(hour.back().unwrap().ticks, 20);
+
+    let total = stats.total(StatsWindow::Hour);
+    assert_eq!(total.ticks, 200);
+    assert_eq!(total.produced[&ItemType::Gear], 200);
+    assert_eq!(total.consumed[&ItemType::IronIngot], 400);
+    assert_eq!(total.consumed[&ItemType::Wire], 200);
+    assert_eq!(
+        total.consumed_by[&BuildingType::Assembler].get(&ItemType::Wire),
+        None
+    );
+    assert_eq!(total.power_generated, 2000.);
+    assert_eq!(total.power_consumed, 1000.);
+    assert_eq!(total.energy, 199);
+
+    let total = stats.total(StatsWindow::Minute);
+    assert_eq!(total.ticks, STATS_BUCKETS);
+    assert_eq!(
+        total.produced_by[&BuildingType::Assembler][&ItemType::Gear],
+        60
+    );
+}
+
+#[test]
+fn test_game_stats() {
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    // The excavated ores are smelted in the furnace
+    game.excavate(30, 50, None).unwrap();
+    for _ in 0..300 {
+        game.tick().unwrap();
+    }
+    let total = game.production_stats().total(StatsWindow::Hour);
+    assert_eq!(total.ticks, 300);
+    assert!(!total.produced_by[&BuildingType::Furnace].is_empty());
+    assert!(0. < total.power_generated);
+    // The ores are delivered to the furnace by transports
+    assert!(0 < total.deliveries);
+
+    // The statistics are kept in the save
+    let mut copy = AsteroidColoniesGame::new(None).unwrap();
+    copy.deserialize(game.serialize(false).unwrap().as_bytes())
+        .unwrap();
+    assert_eq!(
+        copy.production_stats().window(StatsWindow::Day),
+        game.production_stats().window(StatsWindow::Day)
+    );
+}
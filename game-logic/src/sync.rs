@@ -0,0 +1,252 @@
+//! Incremental synchronization of the game state from the server to the clients.
+//!
+//! Each update is numbered by a frame, and the client acknowledges the last frame it has
+//! received, along with the digests of the tile chunks it has. The server sends the tile chunks
+//! and the entities that differ from the acknowledged state, so that the bandwidth scales with
+//! the activity in the colony rather than its size.
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    building::Building,
+    construction::Construction,
+    crew::Crew,
+    entity::{EntityDigests, EntitySet, EntitySetDiff},
+    history::CommandHistory,
+    item_pile::ItemPile,
+    migration::SAVE_VERSION,
+    research::Research,
+    state_hash::StateHash,
+    task::GlobalTask,
+    transport::Transport,
+    AsteroidColoniesGame, Position, Tiles, Xor128,
+};
+
+/// The number of frames that a client keeps as the base of diffs. The server should receive
+/// an acknowledgement before sending this many updates.
+const MAX_SYNC_FRAMES: usize = 8;
+
+/// Digests of all entity sets in the game.
+#[derive(Clone, Debug)]
+pub struct EntitiesDigest {
+    pub(crate) buildings: EntityDigests<Building>,
+    pub(crate) crews: EntityDigests<Crew>,
+    pub(crate) global_tasks: EntityDigests<GlobalTask>,
+    pub(crate) transports: EntityDigests<Transport>,
+    pub(crate) constructions: EntityDigests<Construction>,
+    pub(crate) item_piles: EntityDigests<ItemPile>,
+}
+
+/// A copy of the entity sets that a client received in a frame.
+#[derive(Clone)]
+pub(crate) struct EntitySnapshot {
+    buildings: EntitySet<Building>,
+    crews: EntitySet<Crew>,
+    global_tasks: EntitySet<GlobalTask>,
+    transports: EntitySet<Transport>,
+    constructions: EntitySet<Construction>,
+    item_piles: EntitySet<ItemPile>,
+}
+
+impl EntitySnapshot {
+    fn new() -> Self {
+        Self {
+            buildings: EntitySet::new(),
+            crews: EntitySet::new(),
+            global_tasks: EntitySet::new(),
+            transports: EntitySet::new(),
+            constructions: EntitySet::new(),
+            item_piles: EntitySet::new(),
+        }
+    }
+}
+
+/// The message that a client sends after applying an update.
+#[derive(Serialize, Deserialize)]
+pub struct SyncAck {
+    pub chunks_digest: HashMap<Position, u64>,
+    /// The last frame the client has received, or `None` if it needs all the entities.
+    pub frame: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializeGameDiff {
+    /// The save format version. It must be the first field for `deserialize_diff` to check it.
+    version: u32,
+    frame: u64,
+    /// The frame that the entity diffs are based on, or `None` if they contain all entities.
+    base_frame: Option<u64>,
+    tiles: Tiles,
+    buildings: EntitySetDiff<Building>,
+    crews: EntitySetDiff<Crew>,
+    global_tasks: EntitySetDiff<GlobalTask>,
+    global_time: usize,
+    transports: EntitySetDiff<Transport>,
+    constructions: EntitySetDiff<Construction>,
+    item_piles: EntitySetDiff<ItemPile>,
+    rng: Xor128,
+    saved_at: Option<u64>,
+    research: Research,
+    /// The undo and redo stacks in JSON, so that clients can tell what they can undo. Commands
+    /// are tagged for JSON, which bincode cannot decode.
+    history: String,
+    /// The hash of the whole state on the server, which the client should reproduce.
+    state_hash: StateHash,
+}
+
+impl AsteroidColoniesGame {
+    /// Compute digests of all entities. It is as expensive as serializing them, so the server
+    /// should share the result among the sessions while the state does not change.
+    pub fn entities_digest(&self) -> EntitiesDigest {
+        EntitiesDigest {
+            buildings: self.buildings.digests(),
+            crews: self.crews.digests(),
+            global_tasks: self.global_tasks.digests(),
+            transports: self.transports.digests(),
+            constructions: self.constructions.digests(),
+            item_piles: self.item_piles.digests(),
+        }
+    }
+
+    /// Serialize an update for a client. `current` should be the result of
+    /// [`Self::entities_digest`] for the current state, and `base` is the frame and the digest
+    /// that the client has acknowledged.
+    pub fn serialize_with_diffs(
+        &self,
+        chunks_digest: &HashMap<Position, u64>,
+        frame: u64,
+        base: Option<(u64, &EntitiesDigest)>,
+        current: &EntitiesDigest,
+    ) -> Result<Vec<u8>, String> {
+        let tiles = self.tiles.filter_with_diffs(chunks_digest)?;
+        let base_digest = base.map(|(_, digest)| digest);
+        let ser_game = SerializeGameDiff {
+            version: SAVE_VERSION,
+            frame,
+            base_frame: base.map(|(frame, _)| frame),
+            tiles,
+            buildings: self
+                .buildings
+                .diff(base_digest.map(|d| &d.buildings), &current.buildings),
+            crews: self
+                .crews
+                .diff(base_digest.map(|d| &d.crews), &current.crews),
+            global_tasks: self
+                .global_tasks
+                .diff(base_digest.map(|d| &d.global_tasks), &current.global_tasks),
+            global_time: self.global_time,
+            transports: self
+                .transports
+                .diff(base_digest.map(|d| &d.transports), &current.transports),
+            constructions: self.constructions.diff(
+                base_digest.map(|d| &d.constructions),
+                &current.constructions,
+            ),
+            item_piles: self
+                .item_piles
+                .diff(base_digest.map(|d| &d.item_piles), &current.item_piles),
+            rng: self.rng,
+            saved_at: self.saved_at,
+            research: self.research.clone(),
+            history: serde_json::to_string(&self.history).map_err(|e| format!("{e}"))?,
+            state_hash: self.state_hash_with(current),
+        };
+        bincode::serialize(&ser_game).map_err(|e| format!("{e}"))
+    }
+
+    /// Apply an update from [`Self::serialize_with_diffs`]. The entities simulated locally since
+    /// the last update are replaced with the ones from the server. The production statistics
+    /// are not sent, so the client keeps accumulating its own.
+    ///
+    /// If the base frame of the update is not known, or the resulting state does not match the
+    /// hash from the server, it returns an error and forgets all frames, so that the next
+    /// acknowledgement asks the server for all entities.
+    pub fn deserialize_diff(&mut self, data: &[u8]) -> Result<(), String> {
+        let version: u32 = bincode::deserialize(data).map_err(|e| format!("{e}"))?;
+        if version != SAVE_VERSION {
+            return Err(format!(
+                "Save version mismatch: expected {SAVE_VERSION}, got {version}"
+            ));
+        }
+        let ser_data: SerializeGameDiff = bincode::deserialize(data).map_err(|e| format!("{e}"))?;
+        let history: CommandHistory =
+            serde_json::from_str(&ser_data.history).map_err(|e| format!("{e}"))?;
+
+        let mut snapshot = match ser_data.base_frame {
+            Some(base_frame) => {
+                let Some(snapshot) = self
+                    .sync_frames
+                    .iter()
+                    .find(|(f, _)| *f == base_frame)
+                    .map(|(_, snapshot)| snapshot.clone())
+                else {
+                    self.sync_frames.clear();
+                    return Err(format!("Unknown base frame {base_frame}"));
+                };
+                // The server never sends diffs against frames older than the acknowledged one
+                self.sync_frames.retain(|(f, _)| base_frame <= *f);
+                snapshot
+            }
+            None => {
+                self.sync_frames.clear();
+                EntitySnapshot::new()
+            }
+        };
+        snapshot.buildings.apply_diff(ser_data.buildings);
+        snapshot.crews.apply_diff(ser_data.crews);
+        snapshot.global_tasks.apply_diff(ser_data.global_tasks);
+        snapshot.transports.apply_diff(ser_data.transports);
+        snapshot.constructions.apply_diff(ser_data.constructions);
+        snapshot.item_piles.apply_diff(ser_data.item_piles);
+
+        for (pos, chunk) in ser_data.tiles.chunks {
+            self.tiles.chunks.insert(pos, chunk);
+        }
+        self.buildings = snapshot.buildings.clone();
+        self.crews = snapshot.crews.clone();
+        self.global_tasks = snapshot.global_tasks.clone();
+        self.transports = snapshot.transports.clone();
+        self.constructions = snapshot.constructions.clone();
+        self.item_piles = snapshot.item_piles.clone();
+        self.global_time = ser_data.global_time;
+        self.rng = ser_data.rng;
+        self.saved_at = ser_data.saved_at;
+        self.research = ser_data.research;
+        self.history = history;
+
+        self.sync_frames.push_back((ser_data.frame, snapshot));
+        while MAX_SYNC_FRAMES < self.sync_frames.len() {
+            self.sync_frames.pop_front();
+        }
+
+        self.rebuild_caches();
+
+        let diverged = ser_data.state_hash.diverged(&self.state_hash());
+        if !diverged.is_empty() {
+            self.sync_frames.clear();
+            return Err(format!(
+                "State diverged from the server in: {}",
+                diverged.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// Serialize the acknowledgement of the last update for the server.
+    pub fn serialize_sync_ack(&self) -> bincode::Result<Vec<u8>> {
+        let chunks_digest = self
+            .tiles
+            .chunks()
+            .iter()
+            .map(|(pos, chunk)| (*pos, chunk.get_hash()))
+            .collect();
+        bincode::serialize(&SyncAck {
+            chunks_digest,
+            frame: self.sync_frames.back().map(|(frame, _)| *frame),
+        })
+    }
+}
@@ -0,0 +1,115 @@
+use super::*;
+use crate::{direction::Direction, Command, Conveyor};
+
+fn ack(client: &AsteroidColoniesGame) -> SyncAck {
+    bincode::deserialize(&client.serialize_sync_ack().unwrap()).unwrap()
+}
+
+/// The state to compare, without the statistics that the client accumulates on its own.
+fn state(game: &AsteroidColoniesGame) -> serde_json::Value {
+    let mut state: serde_json::Value =
+        serde_json::from_str(&game.serialize(false).unwrap()).unwrap();
+    state.as_object_mut().unwrap().remove("production_stats");
+    state
+}
+
+#[test]
+fn test_sync_diffs() {
+    let mut server = AsteroidColoniesGame::new(None).unwrap();
+    let mut client = AsteroidColoniesGame::new(None).unwrap();
+    assert_eq!(ack(&client).frame, None);
+    let mut base = (1, server.entities_digest());
+    let data = server
+        .serialize_with_diffs(&HashMap::new(), base.0, None, &base.1)
+        .unwrap();
+    client.deserialize_diff(&data).unwrap();
+    assert_eq!(state(&client), state(&server));
+
+    for frame in 2..20 {
+        // Some tiles may be excavated already, but we only need some activity
+        server.excavate(7, frame as i32, None).ok();
+        for _ in 0..10 {
+            server.tick().unwrap();
+        }
+        // The client simulates on its own, which should be overwritten by the server
+        client.tick().unwrap();
+        client.tick().unwrap();
+
+        let current = server.entities_digest();
+        let ack = ack(&client);
+        assert_eq!(ack.frame, Some(base.0));
+        let data = server
+            .serialize_with_diffs(&ack.chunks_digest, frame, Some((base.0, &base.1)), &current)
+            .unwrap();
+        client.deserialize_diff(&data).unwrap();
+        assert_eq!(state(&client), state(&server));
+        base = (frame, current);
+    }
+}
+
+#[test]
+fn test_sync_unknown_base() {
+    let server = AsteroidColoniesGame::new(None).unwrap();
+    let mut client = AsteroidColoniesGame::new(None).unwrap();
+    let digest = server.entities_digest();
+    let data = server
+        .serialize_with_diffs(&HashMap::new(), 5, Some((4, &digest)), &digest)
+        .unwrap();
+    assert_eq!(
+        client.deserialize_diff(&data).unwrap_err(),
+        "Unknown base frame 4"
+    );
+
+    // The client asks for all entities after failing
+    assert_eq!(ack(&client).frame, None);
+    let data = server
+        .serialize_with_diffs(&HashMap::new(), 6, None, &digest)
+        .unwrap();
+    client.deserialize_diff(&data).unwrap();
+    assert_eq!(state(&client), state(&server));
+    assert_eq!(ack(&client).frame, Some(6));
+}
+
+#[test]
+fn test_sync_constructions() {
+    let mut server = AsteroidColoniesGame::new(None).unwrap();
+    let mut client = AsteroidColoniesGame::new(None).unwrap();
+    server.build_plan(
+        &[Construction::new_conveyor(
+            [26, 50],
+            Conveyor::One(Direction::Left, Direction::Right),
+            0,
+            false,
+        )],
+        None,
+    );
+    let digest = server.entities_digest();
+    let data = server
+        .serialize_with_diffs(&HashMap::new(), 1, None, &digest)
+        .unwrap();
+    client.deserialize_diff(&data).unwrap();
+    assert_eq!(state(&client), state(&server));
+}
+
+#[test]
+fn test_sync_history() {
+    let mut server = AsteroidColoniesGame::new(None).unwrap();
+    let mut client = AsteroidColoniesGame::new(None).unwrap();
+    server
+        .apply_command(&Command::DeconstructPowerGrid { pos: [26, 50] }, None)
+        .unwrap();
+    let digest = server.entities_digest();
+    let data = server
+        .serialize_with_diffs(&HashMap::new(), 1, None, &digest)
+        .unwrap();
+    client.deserialize_diff(&data).unwrap();
+    assert!(client.history().can_undo(None));
+    assert_eq!(client.state_hash(), server.state_hash());
+
+    // The history is a part of the state hash
+    client.apply_command(&Command::Undo, None).unwrap();
+    assert!(client
+        .state_hash()
+        .diverged(&server.state_hash())
+        .contains(&"history"));
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{collections::BTreeMap, fmt::Display};
 
 use serde::{Deserialize, Serialize};
 
@@ -10,6 +10,8 @@ use crate::{
     entity::{EntityId, EntitySet},
     game::CalculateBackImage,
     items::ItemType,
+    player::PlayerId,
+    research::Research,
     transport::find_path,
     AsteroidColoniesGame, CountableInventory, Pos, TileState, Tiles, Xor128,
 };
@@ -18,8 +20,6 @@ pub const EXCAVATE_TIME: f64 = 30.;
 pub const LABOR_EXCAVATE_TIME: f64 = 300.;
 pub const EXCAVATOR_SPEED: f64 = LABOR_EXCAVATE_TIME / EXCAVATE_TIME;
 pub const MOVE_TIME: f64 = 2.;
-pub const BUILD_POWER_GRID_TIME: f64 = 60.;
-pub const BUILD_CONVEYOR_TIME: f64 = 90.;
 pub const MOVE_ITEM_TIME: f64 = 2.;
 pub(crate) const RAW_ORE_SMELT_TIME: f64 = 30.;
 pub(crate) const EXCAVATE_ORE_AMOUNT: usize = 15;
@@ -41,13 +41,24 @@ pub enum BuildingTask {
     Assemble {
         t: f64,
         max_t: f64,
-        outputs: HashMap<ItemType, usize>,
+        outputs: BTreeMap<ItemType, usize>,
     },
     Smelt {
         t: f64,
         max_t: f64,
         output_ores: OreAccum,
     },
+    Research {
+        t: f64,
+        max_t: f64,
+        /// The name of the research project
+        project: String,
+    },
+    /// A crew is moving into a cabin
+    Immigrate {
+        t: f64,
+        max_t: f64,
+    },
 }
 
 impl Display for BuildingTask {
@@ -59,6 +70,8 @@ impl Display for BuildingTask {
             Self::MoveToExcavate { .. } => write!(f, "MoveToExcavate"),
             Self::Assemble { .. } => write!(f, "BuildItem"),
             Self::Smelt { .. } => write!(f, "Smelt"),
+            Self::Research { .. } => write!(f, "Research"),
+            Self::Immigrate { .. } => write!(f, "Immigrate"),
         }
     }
 }
@@ -66,17 +79,26 @@ impl Display for BuildingTask {
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum GlobalTask {
     /// Excavate using human labor. Very slow and inefficient.
-    Excavate(f64, [i32; 2]),
-    Cleanup(Pos),
+    Excavate(f64, [i32; 2], Option<PlayerId>),
+    Cleanup(Pos, Option<PlayerId>),
+}
+
+impl GlobalTask {
+    /// The player who issued this task.
+    pub fn owner(&self) -> Option<PlayerId> {
+        match self {
+            Self::Excavate(_, _, owner) | Self::Cleanup(_, owner) => *owner,
+        }
+    }
 }
 
 impl AsteroidColoniesGame {
-    pub fn excavate(&mut self, ix: i32, iy: i32) -> Result<bool, String> {
+    pub fn excavate(&mut self, ix: i32, iy: i32, owner: Option<PlayerId>) -> Result<bool, String> {
         if !matches!(self.tiles[[ix, iy]].state, TileState::Solid) {
             return Err("Already excavated".to_string());
         }
         self.global_tasks
-            .insert(GlobalTask::Excavate(LABOR_EXCAVATE_TIME, [ix, iy]));
+            .insert(GlobalTask::Excavate(LABOR_EXCAVATE_TIME, [ix, iy], owner));
         Ok(true)
     }
 
@@ -138,18 +160,20 @@ impl AsteroidColoniesGame {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(super) fn process_task(
         tiles: &mut Tiles,
         building: &mut Building,
         buildings: &EntitySet<Building>,
         global_tasks: &mut EntitySet<GlobalTask>,
+        research: &mut Research,
         power_ratio: f64,
         _rng: &mut Xor128,
         _calculate_back_image: Option<&mut CalculateBackImage>,
     ) -> Option<(ItemType, [i32; 2])> {
         match building.task {
             BuildingTask::Excavate(_, gt_id) => {
-                let Some(GlobalTask::Excavate(t, gt_pos)) = global_tasks.get_mut(gt_id) else {
+                let Some(GlobalTask::Excavate(t, gt_pos, _)) = global_tasks.get_mut(gt_id) else {
                     building.task = BuildingTask::None;
                     return None;
                 };
@@ -253,6 +277,27 @@ impl AsteroidColoniesGame {
                     *t = (*t - power_ratio).max(0.);
                 }
             }
+            BuildingTask::Research {
+                ref mut t,
+                ref project,
+                ..
+            } => {
+                if *t <= 0. {
+                    research.complete(project);
+                    building.task = BuildingTask::None;
+                } else {
+                    *t = (*t - power_ratio).max(0.);
+                }
+            }
+            BuildingTask::Immigrate { ref mut t, .. } => {
+                if *t <= 0. {
+                    building.population += 1;
+                    building.crews += 1;
+                    building.task = BuildingTask::None;
+                } else {
+                    *t = (*t - power_ratio).max(0.);
+                }
+            }
             BuildingTask::None => {
                 if matches!(building.type_, BuildingType::Excavator) {
                     for (gt_id, gt) in global_tasks.items() {
@@ -271,7 +316,7 @@ impl AsteroidColoniesGame {
         gt_id: GlobalTaskId,
         gt: &GlobalTask,
     ) -> Option<()> {
-        let GlobalTask::Excavate(_, task_pos) = *gt else {
+        let GlobalTask::Excavate(_, task_pos, _) = *gt else {
             return None;
         };
         // console_log!(
@@ -329,7 +374,7 @@ impl AsteroidColoniesGame {
     pub(super) fn process_global_tasks(&mut self) {
         for task in &self.global_tasks {
             match &*task {
-                GlobalTask::Excavate(t, pos) if *t <= 0. => {
+                GlobalTask::Excavate(t, pos, _) if *t <= 0. => {
                     self.tiles[*pos].state = TileState::Empty;
                     if let Some(ref f) = self.calculate_back_image {
                         f(&mut self.tiles);
@@ -340,8 +385,11 @@ impl AsteroidColoniesGame {
         }
 
         self.global_tasks.retain(|task| match task {
-            GlobalTask::Excavate(ref mut t, _) => !(*t <= 0.),
-            GlobalTask::Cleanup(pos) => self.transports.iter().any(|t| t.path.last() == Some(pos)),
+            GlobalTask::Excavate(ref mut t, _, _) => !(*t <= 0.),
+            GlobalTask::Cleanup(pos, _) => {
+                self.transports.iter().any(|t| t.path.last() == Some(pos))
+                    || self.item_piles.iter().any(|p| p.pos == *pos)
+            }
         });
     }
 }
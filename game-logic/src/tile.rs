@@ -8,8 +8,8 @@ use fnv::FnvHasher;
 use serde::{de::Visitor, Deserialize, Serialize};
 
 use crate::{
-    building::OreAccum, conveyor::Conveyor, game::PERLIN_BITS, perlin_noise::perlin_noise_pixel,
-    Pos,
+    building::OreAccum, conveyor::Conveyor, filter::SplitterFilter, game::PERLIN_BITS,
+    perlin_noise::perlin_noise_pixel, Pos,
 };
 
 pub const CHUNK_SIZE: usize = 16;
@@ -32,6 +32,10 @@ pub struct Tile {
     pub state: TileState,
     pub power_grid: bool,
     pub conveyor: Conveyor,
+    /// The item filter of a splitter
+    pub filter: Option<SplitterFilter>,
+    /// The tier of the conveyor, which decides its speed. 0 is the basic conveyor.
+    pub conveyor_tier: u8,
     pub ores: OreAccum,
     #[serde(skip)]
     pub image_idx: ImageIdx,
@@ -44,6 +48,8 @@ impl Hash for Tile {
         self.state.hash(state);
         self.power_grid.hash(state);
         self.conveyor.hash(state);
+        self.filter.hash(state);
+        self.conveyor_tier.hash(state);
     }
 }
 
@@ -53,6 +59,8 @@ impl Tile {
             state: TileState::Space,
             power_grid: false,
             conveyor: Conveyor::None,
+            filter: None,
+            conveyor_tier: 0,
             ores: OreAccum::new(),
             image_idx: ImageIdx::new(),
         }
@@ -80,6 +88,8 @@ impl Tile {
             state: TileState::Solid,
             power_grid: false,
             conveyor: Conveyor::None,
+            filter: None,
+            conveyor_tier: 0,
             ores: OreAccum {
                 cilicate,
                 iron,
@@ -96,6 +106,8 @@ impl Tile {
             state: TileState::Empty,
             power_grid: false,
             conveyor,
+            filter: None,
+            conveyor_tier: 0,
             ores: OreAccum::new(),
             image_idx: ImageIdx::new(),
         }
@@ -106,6 +118,8 @@ impl Tile {
             state: TileState::Empty,
             power_grid: true,
             conveyor: Conveyor::None,
+            filter: None,
+            conveyor_tier: 0,
             ores: OreAccum::new(),
             image_idx: ImageIdx::splat(8),
         }
@@ -1,3 +1,6 @@
+#[cfg(test)]
+mod tests;
+
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{BinaryHeap, HashMap, HashSet},
@@ -8,19 +11,79 @@ use crate::{
     building::OreAccum,
     direction::Direction,
     entity::{EntityId, EntitySet},
+    inventory::Inventory,
+    item_pile::drop_items,
     items::ItemType,
+    push_pull::reroute_path,
+    ruleset::ruleset,
     AsteroidColoniesGame, Conveyor, Pos,
 };
 
 pub type TransportId = EntityId<Transport>;
 
+/// The length of a tile in the unit of transport progress and conveyor speeds.
+pub const TILE_LENGTH: u32 = 12;
+/// The number of items a tile of a conveyor can hold, when they are packed without gaps.
+pub const SLOTS_PER_TILE: u32 = 2;
+/// The minimum distance between transports on the same lane.
+const ITEM_SPACING: u32 = TILE_LENGTH / SLOTS_PER_TILE;
+
 /// Transporting item
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Transport {
     pub src: Pos,
     pub dest: Pos,
     pub payload: TransportPayload,
+    /// The remaining tiles to the destination in reverse order, so the last one is the tile
+    /// that the transport is on.
     pub path: Vec<Pos>,
+    /// The distance traveled on the current tile toward the next one, less than
+    /// [`TILE_LENGTH`].
+    pub progress: u32,
+    /// The distance moved in the last tick, to interpolate the rendering.
+    #[serde(skip)]
+    pub moved: u32,
+}
+
+impl Transport {
+    pub(crate) fn new(src: Pos, dest: Pos, payload: TransportPayload, path: Vec<Pos>) -> Self {
+        Self {
+            src,
+            dest,
+            payload,
+            path,
+            progress: 0,
+            moved: 0,
+        }
+    }
+
+    /// The position in tiles, interpolated by the fraction `frac` of the next tick, assuming it
+    /// keeps moving as fast as in the last tick.
+    pub fn position(&self, frac: f64) -> Option<[f64; 2]> {
+        let last = self.path.len().checked_sub(1)?;
+        let dist = (self.progress as f64 + self.moved as f64 * frac) / TILE_LENGTH as f64;
+        let tiles = (dist.floor() as usize).min(last);
+        let idx = last - tiles;
+        let pos = self.path[idx];
+        let Some(next) = idx.checked_sub(1).map(|i| self.path[i]) else {
+            return Some([pos[0] as f64, pos[1] as f64]);
+        };
+        let f = dist - tiles as f64;
+        Some([
+            pos[0] as f64 + (next[0] - pos[0]) as f64 * f,
+            pos[1] as f64 + (next[1] - pos[1]) as f64 * f,
+        ])
+    }
+}
+
+/// The lane of the `idx`-th tile of a path, which is the tile and the direction to leave it.
+/// Transports only keep the spacing on the same lane, so that they can cross each other or go
+/// the opposite way. The destination at index 0 has no lane and never blocks.
+fn lane(path: &[Pos], idx: usize) -> Option<(Pos, Direction)> {
+    let pos = *path.get(idx)?;
+    let next = *path.get(idx.checked_sub(1)?)?;
+    let dir = Direction::from_vec([next[0] - pos[0], next[1] - pos[1]])?;
+    Some((pos, dir))
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -29,8 +92,80 @@ pub enum TransportPayload {
     Ores(OreAccum),
 }
 
+impl TransportPayload {
+    /// The carried items as an inventory, to drop them on a tile.
+    pub(crate) fn to_inventory(&self) -> Inventory {
+        let mut inventory = Inventory::new();
+        match self {
+            Self::Item(item, amount) => {
+                inventory.insert(*item, *amount);
+            }
+            Self::Ores(ores) => inventory.add_ores(ores),
+        }
+        inventory
+    }
+}
+
 impl AsteroidColoniesGame {
+    /// Find new paths for the transports whose paths go over tiles that changed since the last
+    /// tick, or drop their items on the tile if they cannot reach the destination anymore.
+    pub(crate) fn reroute_transports(&mut self) {
+        if self.changed_tiles.is_empty() {
+            return;
+        }
+        let changed = std::mem::take(&mut self.changed_tiles);
+        let mut dropped = vec![];
+        for (id, t) in self.transports.items_mut() {
+            let len = t.path.len();
+            if len <= 1 || !t.path.iter().any(|pos| changed.contains(pos)) {
+                continue;
+            }
+            let pos = t.path[len - 1];
+            let dest = self
+                .buildings
+                .iter()
+                .find(|b| b.intersects(t.dest))
+                .map(|b| (b.pos, b.type_.size()))
+                .or_else(|| {
+                    self.constructions
+                        .iter()
+                        .find(|c| c.intersects(t.dest))
+                        .map(|c| (c.pos, c.size()))
+                });
+            let in_building = self.buildings.iter().any(|b| b.intersects(pos));
+            let item = match t.payload {
+                TransportPayload::Item(item, _) => Some(item),
+                TransportPayload::Ores(_) => None,
+            };
+            let path = dest.and_then(|(dest_pos, dest_size)| {
+                reroute_path(&self.tiles, &t.path, item, dest_pos, dest_size, in_building)
+            });
+            if let Some(path) = path {
+                // Keep the progress if it is still heading to the same tile
+                if path.get(path.len().wrapping_sub(2)) != t.path.get(len - 2) {
+                    t.progress = 0;
+                }
+                t.path = path;
+            } else {
+                drop_items(&mut self.item_piles, pos, &t.payload.to_inventory());
+                t.path.clear();
+                dropped.push(id);
+            }
+        }
+        self.transports.retain(|t| !t.path.is_empty());
+        for id in dropped {
+            for building in self.buildings.iter_mut() {
+                building.expected_transports.remove(&id);
+            }
+            for construction in self.constructions.iter_mut() {
+                construction.clear_expected(id);
+            }
+        }
+    }
+
     pub(super) fn process_transports(&mut self) {
+        self.reroute_transports();
+
         let intersects = |pos: Pos, size: [usize; 2], [ix, iy]: Pos| {
             pos[0] <= ix
                 && ix < size[0] as i32 + pos[0]
@@ -87,16 +222,22 @@ impl AsteroidColoniesGame {
             false
         };
 
-        let occupied: HashSet<_> = self
-            .transports
-            .iter()
-            .filter_map(|t| t.path.last().copied())
-            .collect();
+        // Progress of the transports on each lane, with their order of processing to break ties
+        let mut lanes: HashMap<(Pos, Direction), Vec<(u32, usize)>> = HashMap::new();
+        for (i, t) in self.transports.iter().enumerate() {
+            if let Some(lane) = lane(&t.path, t.path.len().wrapping_sub(1)) {
+                lanes.entry(lane).or_default().push((t.progress, i));
+            }
+        }
 
-        for (id, t) in self.transports.items_mut() {
+        for (i, (id, t)) in self.transports.items_mut().enumerate() {
+            t.moved = 0;
             if t.path.len() <= 1 {
+                let payload = t.payload.clone();
                 let delivered = check_construction(id, &mut *t) || check_building(&mut *t);
-                if !delivered {
+                if delivered {
+                    self.production_stats.deliver(&payload);
+                } else {
                     let tiles = &self.tiles;
                     let return_path = find_multipath(
                         std::iter::once(t.dest),
@@ -115,15 +256,59 @@ impl AsteroidColoniesGame {
                         let t = &mut *t;
                         std::mem::swap(&mut t.src, &mut t.dest);
                         t.path = return_path;
+                        t.progress = 0;
+                        if let Some(lane) = lane(&t.path, t.path.len() - 1) {
+                            lanes.entry(lane).or_default().push((0, i));
+                        }
+                    } else if let Some(&pos) = t.path.first() {
+                        drop_items(&mut self.item_piles, pos, &t.payload.to_inventory());
+                        t.path.clear();
                     }
                 }
-            } else if t.path.len() <= 2
-                || t.path
-                    .get(t.path.len() - 2)
-                    .map(|pos| !occupied.contains(pos))
-                    .unwrap_or(true)
-            {
-                t.path.pop();
+            } else {
+                let len = t.path.len();
+                // Leaving a building goes as fast as the conveyor next to it
+                let tile = Some(&self.tiles[t.path[len - 1]])
+                    .filter(|tile| tile.conveyor.is_some())
+                    .unwrap_or(&self.tiles[t.path[len - 2]]);
+                let speed = ruleset().conveyor_speed(tile.conveyor_tier);
+                let mut limit = t.progress + speed;
+                // Keep the spacing from the transports ahead in the tiles that we can reach
+                for k in 0..len - 1 {
+                    let base = k as u32 * TILE_LENGTH;
+                    if limit + ITEM_SPACING <= base {
+                        break;
+                    }
+                    let Some(others) = lane(&t.path, len - 1 - k).and_then(|l| lanes.get(&l))
+                    else {
+                        continue;
+                    };
+                    for &(progress, j) in others {
+                        let ahead =
+                            0 < k || t.progress < progress || (t.progress == progress && j < i);
+                        if j != i && ahead {
+                            limit = limit.min((base + progress).saturating_sub(ITEM_SPACING));
+                        }
+                    }
+                }
+                let progress = limit.max(t.progress);
+                if progress == t.progress {
+                    continue;
+                }
+                if let Some(others) = lane(&t.path, len - 1).and_then(|l| lanes.get_mut(&l)) {
+                    others.retain(|(_, j)| *j != i);
+                }
+                t.moved = progress - t.progress;
+                t.progress = progress;
+                while TILE_LENGTH <= t.progress && 1 < t.path.len() {
+                    t.path.pop();
+                    t.progress -= TILE_LENGTH;
+                }
+                if t.path.len() <= 1 {
+                    t.progress = 0;
+                } else if let Some(lane) = lane(&t.path, t.path.len() - 1) {
+                    lanes.entry(lane).or_default().push((t.progress, i));
+                }
             }
         }
 
@@ -131,6 +316,28 @@ impl AsteroidColoniesGame {
     }
 }
 
+/// Send `amount` of the item over the path as a transport per item, since each item takes a slot
+/// of the conveyors. Returns the ids of the transports.
+pub(crate) fn send_items(
+    transports: &mut EntitySet<Transport>,
+    src: Pos,
+    dest: Pos,
+    item: ItemType,
+    amount: usize,
+    path: Vec<Pos>,
+) -> Vec<TransportId> {
+    (0..amount)
+        .map(|_| {
+            transports.insert(Transport::new(
+                src,
+                dest,
+                TransportPayload::Item(item, 1),
+                path.clone(),
+            ))
+        })
+        .collect()
+}
+
 /// Count all items in delivery flight and sum up in a single HashMap.
 pub(crate) fn expected_deliveries(
     transports: &EntitySet<Transport>,
@@ -197,6 +404,8 @@ pub(crate) enum LevelTarget {
     None,
     One,
     Two,
+    /// Jump over the given number of tiles through an underground conveyor
+    Tunnel(i32),
 }
 
 /// A generic path finding logic with potentially multiple starts and multiple goals.
@@ -207,7 +416,10 @@ pub(crate) enum LevelTarget {
 /// * `is_passable` takes 2 arguments, first is the direction that the search came from, second is
 /// the position.
 /// * `should_expand` takes 3 arguments, direction from, position and direction to, and returns
-/// if we should expand to that tile. It is used to implement self-intersecting conveyor tiles.
+///   if we should expand to that tile. It is used to implement self-intersecting conveyor tiles
+///   and underground conveyors.
+///
+/// The tiles passed under by a tunnel are included in the returned path.
 pub(crate) fn find_multipath_should_expand(
     start: impl Iterator<Item = Pos>,
     goal: impl Fn(Pos) -> bool,
@@ -250,12 +462,14 @@ pub(crate) fn find_multipath_should_expand(
                             dist: usize,
                             from: Option<Direction>| {
         for dir in Direction::all() {
-            let level = match should_expand(dir, pos, from) {
-                LevelTarget::One => false,
-                LevelTarget::Two => true,
-                _ => continue,
+            let (level, span) = match should_expand(dir, pos, from) {
+                LevelTarget::One => (false, 1),
+                LevelTarget::Two => (true, 1),
+                LevelTarget::Tunnel(span) => (false, span),
+                LevelTarget::None => continue,
             };
-            let dir_vec = CPos::new(dir.to_vec(), false);
+            let dir_vec = dir.to_vec();
+            let dir_vec = CPos::new([dir_vec[0] * span, dir_vec[1] * span], false);
             let mut next_pos = pos + dir_vec;
             next_pos.level = level;
             if visited.get(&next_pos).is_some_and(|e| e.dist <= dist) {
@@ -263,7 +477,7 @@ pub(crate) fn find_multipath_should_expand(
             }
             next_set.push(Entry {
                 pos: next_pos,
-                dist: dist + 1,
+                dist: dist + span as usize,
                 from: Some((dir, pos)),
             });
         }
@@ -290,6 +504,18 @@ pub(crate) fn find_multipath_should_expand(
             let mut nodes = vec![];
             while let Some(cursor_entry) = cursor {
                 nodes.push(cursor_entry.pos.pos);
+                if let Some((dir, from)) = cursor_entry.from {
+                    // Fill in the tiles under a tunnel
+                    let dir_vec = dir.to_vec();
+                    let mut pos = cursor_entry.pos.pos;
+                    loop {
+                        pos = [pos[0] - dir_vec[0], pos[1] - dir_vec[1]];
+                        if pos == from.pos {
+                            break;
+                        }
+                        nodes.push(pos);
+                    }
+                }
                 cursor = cursor_entry
                     .from
                     .and_then(|(_, pos)| visited.get(&pos))
@@ -0,0 +1,205 @@
+use super::*;
+use crate::{
+    building::{Building, BuildingType},
+    tile::{Tile, Tiles},
+};
+
+/// A game with only a straight conveyor of the tier from [0, 0] to the right, which `count`
+/// transports start from the left end of to a storage at the right end.
+fn belt_game(tier: u8, len: i32, count: usize) -> AsteroidColoniesGame {
+    use Direction::*;
+    let mut game = AsteroidColoniesGame::new(None).unwrap();
+    game.tiles = Tiles::new();
+    game.buildings = EntitySet::new();
+    game.constructions = EntitySet::new();
+    game.transports = EntitySet::new();
+    for x in 0..len {
+        let mut tile = Tile::new_with_conveyor(Conveyor::One(Left, Right));
+        tile.conveyor_tier = tier;
+        game.tiles[[x, 0]] = tile;
+    }
+    game.buildings
+        .insert(Building::new([len, 0], BuildingType::MediumStorage));
+    let path: Vec<Pos> = (-1..=len).rev().map(|x| [x, 0]).collect();
+    for _ in 0..count {
+        game.transports.insert(Transport::new(
+            [-1, 0],
+            [len, 0],
+            TransportPayload::Item(ItemType::Gear, 1),
+            path.clone(),
+        ));
+    }
+    game
+}
+
+/// The number of items dropped on tiles
+fn dropped(game: &AsteroidColoniesGame) -> usize {
+    game.item_piles
+        .iter()
+        .map(|p| p.inventory.iter().map(|(_, count)| count).sum::<usize>())
+        .sum()
+}
+
+/// The number of transports delivered in each tick, assuming each of them carries an item
+fn deliveries(game: &mut AsteroidColoniesGame, ticks: usize) -> Vec<usize> {
+    (0..ticks)
+        .map(|_| {
+            let before = game.transports.len() + dropped(game);
+            game.process_transports();
+            before - game.transports.len() - dropped(game)
+        })
+        .collect()
+}
+
+#[test]
+fn test_throughput() {
+    for tier in 0..=2 {
+        let speed = ruleset().conveyor_speed(tier);
+        let mut game = belt_game(tier, 10, 150);
+        let delivered = deliveries(&mut game, 25);
+        // A compressed belt delivers an item every time it moves the spacing
+        let steady: usize = delivered[15..25].iter().sum();
+        assert_eq!(steady as u32, 10 * speed / ITEM_SPACING, "tier {tier}");
+    }
+}
+
+#[test]
+fn test_throughput_stack() {
+    for tier in 0..=2 {
+        let speed = ruleset().conveyor_speed(tier);
+        let mut game = belt_game(tier, 10, 0);
+        let path: Vec<Pos> = (-1..=10).rev().map(|x| [x, 0]).collect();
+        send_items(
+            &mut game.transports,
+            [-1, 0],
+            [10, 0],
+            ItemType::Gear,
+            150,
+            path,
+        );
+        let mut stored = vec![];
+        for _ in 0..25 {
+            game.process_transports();
+            let storage = game.buildings.iter().next().unwrap();
+            stored.push(storage.inventory.get(&ItemType::Gear));
+        }
+        // A stack takes as many slots as the items in it, so it is no faster than single items
+        assert_eq!(
+            (stored[24] - stored[14]) as u32,
+            10 * speed / ITEM_SPACING,
+            "tier {tier}"
+        );
+    }
+}
+
+#[test]
+fn test_latency() {
+    for tier in 0..=2 {
+        let speed = ruleset().conveyor_speed(tier);
+        let mut game = belt_game(tier, 12, 1);
+        let delivered = deliveries(&mut game, 30);
+        let arrival = delivered.iter().position(|count| *count != 0).unwrap();
+        // It takes a tick to be delivered after arriving at the destination
+        let travel = (13 * TILE_LENGTH).div_ceil(speed) as usize;
+        assert_eq!(arrival, travel, "tier {tier}");
+    }
+}
+
+#[test]
+fn test_spacing() {
+    let mut game = belt_game(0, 10, 20);
+    for _ in 0..8 {
+        game.process_transports();
+        let mut positions: Vec<_> = game
+            .transports
+            .iter()
+            .filter(|t| 1 < t.path.len())
+            .map(|t| (12 - t.path.len() as u32) * TILE_LENGTH + t.progress)
+            .collect();
+        positions.sort();
+        for pair in positions.windows(2) {
+            assert!(
+                pair[0] == 0 || ITEM_SPACING <= pair[1] - pair[0],
+                "{positions:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_crossing() {
+    use Direction::*;
+    let mut game = belt_game(0, 5, 1);
+    game.tiles[[2, 0]] = Tile::new_with_conveyor(Conveyor::Two((Left, Right), (Up, Down)));
+    game.tiles[[2, -1]] = Tile::new_with_conveyor(Conveyor::One(Up, Down));
+    game.tiles[[2, 1]] = Tile::new_with_conveyor(Conveyor::One(Up, Down));
+    // A transport on the crossing tile going the other way does not block
+    let mut other = Transport::new(
+        [2, -2],
+        [2, 2],
+        TransportPayload::Item(ItemType::Wire, 1),
+        vec![[2, 2], [2, 1], [2, 0]],
+    );
+    other.progress = 2;
+    game.transports.insert(other);
+    let delivered = deliveries(&mut game, 10);
+    assert_eq!(delivered.iter().sum::<usize>(), 1);
+    assert!(game
+        .transports
+        .iter()
+        .all(|t| t.payload != TransportPayload::Item(ItemType::Gear, 1)));
+}
+
+#[test]
+fn test_position() {
+    let mut t = Transport::new(
+        [0, 0],
+        [2, 1],
+        TransportPayload::Item(ItemType::Gear, 1),
+        vec![[2, 1], [1, 1], [1, 0], [0, 0]],
+    );
+    assert_eq!(t.position(0.5), Some([0., 0.]));
+    t.progress = TILE_LENGTH / 2;
+    t.moved = TILE_LENGTH;
+    assert_eq!(t.position(0.), Some([0.5, 0.]));
+    assert_eq!(t.position(1.), Some([1., 0.5]));
+    assert_eq!(t.position(10.), Some([2., 1.]));
+}
+
+#[test]
+fn test_reroute() {
+    use Direction::*;
+    let mut game = belt_game(0, 6, 1);
+    for _ in 0..2 {
+        game.process_transports();
+    }
+    // Make a detour through the row below
+    game.tiles[[3, 0]] = Tile::new_with_conveyor(Conveyor::One(Left, Down));
+    game.tiles[[3, 1]] = Tile::new_with_conveyor(Conveyor::One(Up, Right));
+    game.tiles[[4, 1]] = Tile::new_with_conveyor(Conveyor::One(Left, Up));
+    game.tiles[[4, 0]] = Tile::new_with_conveyor(Conveyor::One(Down, Right));
+    game.changed_tiles.extend([[3, 0], [4, 0]]);
+    game.process_transports();
+    let path = game.transports.iter().next().unwrap().path.clone();
+    assert!(path.contains(&[3, 1]) && path.contains(&[4, 1]));
+    let delivered = deliveries(&mut game, 30);
+    assert_eq!(delivered.iter().sum::<usize>(), 1);
+}
+
+#[test]
+fn test_drop_on_deconstruct() {
+    let mut game = belt_game(0, 12, 4);
+    for _ in 0..6 {
+        game.process_transports();
+    }
+    game.deconstruct_conveyor(3, 0).unwrap();
+    let delivered: usize = deliveries(&mut game, 50).iter().sum();
+    // The transports behind the gap are dropped and the others are delivered
+    assert_eq!(game.transports.len(), 0);
+    assert_eq!(delivered + dropped(&game), 4);
+    assert!(0 < delivered && delivered < 4);
+    assert!(game
+        .buildings
+        .iter()
+        .all(|b| b.expected_transports.is_empty()));
+}
@@ -14,6 +14,7 @@ import circuit from '../images/circuit.png';
 import batteryItem from '../images/batteryItem.png';
 import power_grid from '../images/power_grid.png';
 import conveyor from '../images/conveyor.png';
+import underground from '../images/underground.png';
 import conveyorItem from '../images/conveyor-item.png';
 import atomicBattery from '../images/atomicBattery.png';
 import battery from '../images/battery.png';
@@ -23,10 +24,15 @@ import excavatorItem from '../images/excavatorItem.png';
 import storage from '../images/storage.png';
 import mediumStorage from '../images/mediumStorage.png';
 import crewCabin from '../images/crewCabin.png';
+import largeCrewCabin from '../images/largeCrewCabin.png';
 import assembler from '../images/assembler.png';
 import assemblerComponent from '../images/assemblerComponent.png';
 import furnace from '../images/furnace.png';
 import furnaceItem from '../images/furnaceItem.png';
+import oxygenGenerator from '../images/oxygenGenerator.png';
+import greenhouse from '../images/greenhouse.png';
+import oxygen from '../images/oxygen.png';
+import food from '../images/food.png';
 import construction from '../images/construction.png';
 import deconstruction from '../images/deconstruction.png';
 import cleanup from '../images/cleanup.png';
@@ -51,6 +57,8 @@ export function itemToIcon(item) {
         case "PowerGridComponent": return power_grid;
         case "ConveyorComponent": return conveyorItem;
         case "AssemblerComponent": return assemblerComponent;
+        case "Oxygen": return oxygen;
+        case "Food": return food;
     }
 }
 
@@ -62,14 +70,18 @@ export async function loadAllIcons() {
         ["crew", crew],
         ["power_grid", power_grid],
         ["conveyor", conveyor],
+        ["underground", underground],
         ["atomic_battery", atomicBattery],
         ["battery", battery],
         ["excavator", excavator],
         ["storage", storage],
         ["medium_storage", mediumStorage],
         ["crew_cabin", crewCabin],
+        ["large_crew_cabin", largeCrewCabin],
         ["assembler", assembler],
         ["furnace", furnace],
+        ["oxygen_generator", oxygenGenerator],
+        ["greenhouse", greenhouse],
         ["raw_ore", rawOre],
         ["iron_ingot", ironIngot],
         ["copper_ingot", copperIngot],
@@ -81,6 +93,8 @@ export async function loadAllIcons() {
         ["battery_item", batteryItem],
         ["conveyor_item", conveyorItem],
         ["assembler_component", assemblerComponent],
+        ["oxygen", oxygen],
+        ["food", food],
         ["construction", construction],
         ["deconstruction", deconstruction],
         ["cleanup", cleanup],
@@ -102,7 +116,7 @@ async function loadImage(url) {
 }
 
 export function formatCrews(building) {
-    return `${building.crews} / ${building.max_crews}`;
+    return `${building.crews} / ${building.population} (rooms: ${building.max_crews})`;
 }
 
 export function buildingToIcon(building) {
@@ -113,7 +127,11 @@ export function buildingToIcon(building) {
         case "Storage": return storage;
         case "MediumStorage": return mediumStorage;
         case "CrewCabin": return crewCabin;
+        case "LargeCrewCabin": return largeCrewCabin;
         case "Assembler": return assemblerComponent;
         case "Furnace": return furnaceItem;
+        case "Laboratory": return circuit;
+        case "OxygenGenerator": return oxygenGenerator;
+        case "Greenhouse": return greenhouse;
     }
 }
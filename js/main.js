@@ -1,6 +1,7 @@
 
 import App from './App.svelte';
 import { loadAllIcons } from './graphics.js';
+import { fetchRuleset } from './session.js';
 
 const serverSync = SERVER_SYNC;
 const baseUrl = BASE_URL;
@@ -14,7 +15,9 @@ const port = 3883;
 
     const loadedImages = await loadAllIcons();
 
-    const game = new AsteroidColonies(loadedImages, 640, 480);
+    const ruleset = serverSync ? await fetchRuleset({baseUrl}) : undefined;
+
+    const game = new AsteroidColonies(loadedImages, 640, 480, ruleset);
     let app = new App({
         target: document.body,
         props: {
@@ -2,19 +2,64 @@
 export let sessionId = null;
 export let websocket = null;
 export let tickTime = 0.5;
+/// Whether the server broadcasts commands in steps instead of pushing the state.
+export let lockstep = false;
+/// The colony to join, given by the `colony` query parameter of the page URL.
+export const colony = new URLSearchParams(location.search).get("colony") || "default";
+
+/// Fetch the ruleset of the server as a JSON string, so that the client simulates with the same rules.
+export async function fetchRuleset({baseUrl}) {
+    try {
+        const res = await fetch(`${baseUrl}/api/ruleset`);
+        if (res.ok) {
+            return await res.text();
+        }
+    } catch (e) {
+        console.log(`ruleset api returned an error: ${e}`);
+    }
+    return undefined;
+}
+
+/// Create a guest session, or log in if the server requires it.
+/// Returns null if the user canceled the login.
+async function createSession(baseUrl) {
+    const sessionRes = await fetch(`${baseUrl}/api/session`, {
+        method: "POST"
+    });
+    if (sessionRes.status !== 401) {
+        return await sessionRes.text();
+    }
+    for (;;) {
+        const name = window.prompt("Player name");
+        if (name === null) return null;
+        const password = window.prompt("Password");
+        if (password === null) return null;
+        const loginRes = await fetch(`${baseUrl}/api/login`, {
+            method: "POST",
+            headers: {"Content-Type": "application/json"},
+            body: JSON.stringify({name, password}),
+        });
+        if (loginRes.ok) {
+            return await loginRes.text();
+        }
+        window.alert(await loginRes.text());
+    }
+}
 
 export async function fetchSessionId({port, baseUrl, game}) {
     let loaded = false;
     for (let i = 0; i < 20; i++) {
         try {
-            const sessionRes = await fetch(`${baseUrl}/api/session`, {
-                method: "POST"
+            sessionId = await createSession(baseUrl);
+            if (!sessionId) break;
+            const dataRes = await fetch(`${baseUrl}/api/load?colony=${encodeURIComponent(colony)}`, {
+                headers: {Authorization: `Bearer ${sessionId}`},
             });
-            sessionId = await sessionRes.text();
-            const dataRes = await fetch(`${baseUrl}/api/load`);
             const dataText = await dataRes.text();
             game.deserialize(dataText);
             tickTime = await (await fetch(`${baseUrl}/api/tick_time`)).json();
+            lockstep = await (await fetch(`${baseUrl}/api/lockstep`)).json();
+            game.set_lockstep(lockstep);
             loaded = true;
         } catch (e) {
             console.log(`session api returned an error: ${e}`);
@@ -23,18 +68,28 @@ export async function fetchSessionId({port, baseUrl, game}) {
     }
 }
 
-export function reconnectWebSocket({baseUrl, game, onupdate = () => {}}){
+export function reconnectWebSocket({baseUrl, game, onupdate = () => {}, onstep = () => {}}){
     if(sessionId){
+        let opened = false;
+        // Set while waiting for the whole state after a failed step, to ask only once
+        let resyncing = false;
         // Is there a smarter way to switch protocol?
         const wsUrl = location.protocol === "https:" ? baseUrl.replace("https", "wss") : baseUrl.replace("http", "ws");
-        websocket = new WebSocket(`${wsUrl}/ws/${sessionId}`);
+        websocket = new WebSocket(`${wsUrl}/ws/${encodeURIComponent(colony)}/${sessionId}`);
         websocket.binaryType = "arraybuffer";
         websocket.addEventListener("message", (event) => {
             if (event.data instanceof ArrayBuffer) {
                 const byteArray = new Uint8Array(event.data);
-                game.deserialize_bin(byteArray);
-                postChunksDigest(game);
-                onupdate();
+                try {
+                    game.deserialize_diff(byteArray);
+                    resyncing = false;
+                    onupdate();
+                }
+                catch (e) {
+                    // The acknowledgement below will ask for the whole state
+                    console.log(`Failed to apply the update: ${e}`);
+                }
+                postSyncAck(game);
             }
             else {
             // console.log(`Event through WebSocket: ${event.data}`);
@@ -42,7 +97,7 @@ export function reconnectWebSocket({baseUrl, game, onupdate = () => {}}){
                 if(data.type === "clientUpdate"){
                     if(game){
                         game.deserialize(data.payload);
-                        postChunksDigest();
+                        postSyncAck(game);
                         onupdate();
                     }
                     // const payload = data.payload;
@@ -51,15 +106,46 @@ export function reconnectWebSocket({baseUrl, game, onupdate = () => {}}){
                     //     body.clientUpdate(payload.bodyState);
                     // }
                 }
+                else if(data.type === "step"){
+                    try {
+                        game.apply_step(event.data);
+                        onstep();
+                    }
+                    catch (e) {
+                        console.log(`Failed to apply the step: ${e}`);
+                        if (!resyncing) {
+                            resyncing = true;
+                            websocket.send(JSON.stringify({type: "Resync"}));
+                        }
+                    }
+                }
+                else if(data.type === "colonyArchived"){
+                    console.log(`Colony ${colony} was archived`);
+                    websocket.close();
+                }
+            }
+        });
+        websocket.addEventListener("open", () => {
+            opened = true;
+            postSyncAck(game);
+            if (lockstep) {
+                // The state loaded before connecting may be behind the first step we receive
+                resyncing = true;
+                websocket.send(JSON.stringify({type: "Resync"}));
+            }
+        });
+        websocket.addEventListener("close", () => {
+            // The server may have forgotten our session by restarting, so get a new one before
+            // the next attempt.
+            if (!opened) {
+                fetchSessionId({baseUrl, game});
             }
         });
-        websocket.addEventListener("open", () => postChunksDigest(game));
     }
 }
 
-function postChunksDigest(game) {
+/// Tell the server the tile chunks and the last update we have, so that it sends only the changes.
+function postSyncAck(game) {
     game.uniformify_tiles();
-    const chunksDigest = game.serialize_chunks_digest();
-    // websocket.send(JSON.stringify({type: "ChunksDigest", payload: {chunks_digest: chunksDigest}}));
-    websocket.send(chunksDigest);
+    websocket.send(game.serialize_sync_ack());
 }
@@ -0,0 +1,410 @@
+//! Player accounts stored in a local file, and the endpoints to register, log in and manage
+//! teams.
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ::actix_web::{error, web, HttpRequest, HttpResponse};
+use ::asteroid_colonies_logic::PlayerId;
+use ::openssl::{hash::MessageDigest, memcmp, pkcs5::pbkdf2_hmac};
+use ::serde::{Deserialize, Serialize};
+use rand::random;
+
+use crate::{
+    session::{Player, Session},
+    ServerData,
+};
+
+const HASH_ITERATIONS: usize = 100_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+#[derive(Serialize, Deserialize)]
+struct Credential {
+    /// The id recorded as the owner of the entities the player creates. Accounts created before
+    /// ownership was introduced do not have one, and are assigned one when loaded.
+    #[serde(default)]
+    id: u32,
+    /// Players in the same team can operate each other's buildings. A player creates a team or
+    /// joins one by an invitation from a member.
+    #[serde(default)]
+    team: Option<String>,
+    /// The teams that invited the player.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    invites: Vec<String>,
+    /// Administrators can archive any colony. It can only be given by editing the accounts file.
+    #[serde(default)]
+    admin: bool,
+    /// Hex encoded salt
+    salt: String,
+    /// Hex encoded PBKDF2-HMAC-SHA256 of the password
+    hash: String,
+}
+
+/// Registered players and their credentials. Passwords are never stored in plain text.
+pub(crate) struct Accounts {
+    file: PathBuf,
+    players: HashMap<String, Credential>,
+}
+
+impl Accounts {
+    /// Load accounts from the file, or start with no accounts if it does not exist.
+    pub fn load(file: &Path) -> Result<Self, String> {
+        let mut players: HashMap<String, Credential> = match fs::read_to_string(file) {
+            Ok(data) => serde_json::from_str(&data)
+                .map_err(|e| format!("Failed to parse accounts file {file:?}: {e}"))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(format!("Failed to read accounts file {file:?}: {e}")),
+        };
+        let next_id = players.values().map(|c| c.id).max().unwrap_or(0) + 1;
+        let mut missing: Vec<_> = players
+            .iter_mut()
+            .filter(|(_, c)| c.id == 0)
+            .map(|(name, c)| (name.clone(), c))
+            .collect();
+        // Assign in a stable order so that the ids do not depend on the HashMap order
+        missing.sort_by(|a, b| a.0.cmp(&b.0));
+        let assigned = !missing.is_empty();
+        for (id, (_, credential)) in (next_id..).zip(missing) {
+            credential.id = id;
+        }
+        let ret = Self {
+            file: file.to_path_buf(),
+            players,
+        };
+        if assigned {
+            ret.save()?;
+        }
+        Ok(ret)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let data = serde_json::to_string_pretty(&self.players).map_err(|e| e.to_string())?;
+        fs::write(&self.file, data).map_err(|e| format!("Failed to write accounts file: {e}"))
+    }
+
+    /// Check that a player can be registered with the name and the password, before spending
+    /// time to hash the password.
+    pub fn check_register(&self, name: &str, password: &str) -> Result<(), String> {
+        validate_player_name(name)?;
+        if password.len() < 8 {
+            return Err("Password must be at least 8 characters long".to_string());
+        }
+        if self.players.contains_key(name) {
+            return Err(format!("Player {name} already exists"));
+        }
+        Ok(())
+    }
+
+    /// Register a player with the password hashed by [`PasswordHash::new`].
+    pub fn register(
+        &mut self,
+        name: &str,
+        password: &str,
+        hash: PasswordHash,
+    ) -> Result<(), String> {
+        // Check again, since another player may have registered while hashing
+        self.check_register(name, password)?;
+        let id = self.players.values().map(|c| c.id).max().unwrap_or(0) + 1;
+        self.players.insert(
+            name.to_string(),
+            Credential {
+                id,
+                team: None,
+                invites: vec![],
+                admin: false,
+                salt: to_hex(&hash.salt),
+                hash: to_hex(&hash.hash),
+            },
+        );
+        self.save()
+    }
+
+    pub fn player(&self, name: &str) -> Option<Player> {
+        self.players.get(name).map(|c| Player {
+            name: name.to_string(),
+            id: PlayerId(c.id),
+            admin: c.admin,
+        })
+    }
+
+    fn team(&self, id: PlayerId) -> Option<&str> {
+        self.players
+            .values()
+            .find(|c| c.id == id.0)
+            .and_then(|c| c.team.as_deref())
+    }
+
+    /// Whether a player (or a guest if `None`) can modify an entity owned by `owner`.
+    /// Entities without an owner are shared by everyone, and owned ones can be modified by the
+    /// owner and the members of the owner's team.
+    pub fn can_modify(&self, player: Option<PlayerId>, owner: Option<PlayerId>) -> bool {
+        let Some(owner) = owner else {
+            return true;
+        };
+        let Some(player) = player else {
+            return false;
+        };
+        player == owner
+            || self
+                .team(player)
+                .is_some_and(|team| self.team(owner) == Some(team))
+    }
+
+    fn credential_mut(&mut self, name: &str) -> Result<&mut Credential, String> {
+        self.players
+            .get_mut(name)
+            .ok_or_else(|| format!("Player {name} does not exist"))
+    }
+
+    /// Create a team with the player as the only member.
+    pub fn create_team(&mut self, name: &str, team: &str) -> Result<(), String> {
+        validate_player_name(team).map_err(|e| e.replace("Player", "Team"))?;
+        if self
+            .players
+            .values()
+            .any(|c| c.team.as_deref() == Some(team))
+        {
+            return Err(format!("Team {team} already exists"));
+        }
+        let credential = self.credential_mut(name)?;
+        if credential.team.is_some() {
+            return Err("Leave the current team before creating one".to_string());
+        }
+        credential.team = Some(team.to_string());
+        credential.invites.retain(|invite| invite != team);
+        self.save()
+    }
+
+    /// Invite another player to the team of the player.
+    pub fn invite(&mut self, name: &str, invitee: &str) -> Result<(), String> {
+        let team = self
+            .credential_mut(name)?
+            .team
+            .clone()
+            .ok_or_else(|| "You are not in a team".to_string())?;
+        let credential = self.credential_mut(invitee)?;
+        if credential.team.as_ref() == Some(&team) {
+            return Err(format!("Player {invitee} is already in team {team}"));
+        }
+        if !credential.invites.contains(&team) {
+            credential.invites.push(team);
+        }
+        self.save()
+    }
+
+    /// Join a team that invited the player, leaving the current team if any.
+    pub fn join_team(&mut self, name: &str, team: &str) -> Result<(), String> {
+        let credential = self.credential_mut(name)?;
+        let Some(idx) = credential.invites.iter().position(|invite| invite == team) else {
+            return Err(format!("You are not invited to team {team}"));
+        };
+        credential.invites.remove(idx);
+        credential.team = Some(team.to_string());
+        self.save()
+    }
+
+    pub fn leave_team(&mut self, name: &str) -> Result<(), String> {
+        let credential = self.credential_mut(name)?;
+        if credential.team.take().is_none() {
+            return Err("You are not in a team".to_string());
+        }
+        self.save()
+    }
+
+    /// The salt and the hash of the player's password, to verify a password with.
+    pub fn password_hash(&self, name: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+        let credential = self.players.get(name)?;
+        Some((from_hex(&credential.salt)?, from_hex(&credential.hash)?))
+    }
+}
+
+/// A salted hash of a password
+pub(crate) struct PasswordHash {
+    salt: [u8; SALT_LEN],
+    hash: [u8; HASH_LEN],
+}
+
+impl PasswordHash {
+    /// Hash the password with a new salt. It takes a while, so it should not run on the async
+    /// executor.
+    pub fn new(password: &str) -> Result<Self, String> {
+        let salt: [u8; SALT_LEN] = random();
+        let hash = hash_password(password, &salt)?;
+        Ok(Self { salt, hash })
+    }
+}
+
+/// Check the password against the salt and the hash. It takes a while like [`PasswordHash::new`].
+fn verify_password(password: &str, salt: &[u8], expected: &[u8]) -> bool {
+    hash_password(password, salt)
+        .map(|hash| hash.len() == expected.len() && memcmp::eq(&hash, expected))
+        .unwrap_or(false)
+}
+
+fn hash_password(password: &str, salt: &[u8]) -> Result<[u8; HASH_LEN], String> {
+    let mut hash = [0; HASH_LEN];
+    pbkdf2_hmac(
+        password.as_bytes(),
+        salt,
+        HASH_ITERATIONS,
+        MessageDigest::sha256(),
+        &mut hash,
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(hash)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|c| {
+            let c = std::str::from_utf8(c).ok().filter(|c| c.len() == 2)?;
+            u8::from_str_radix(c, 16).ok()
+        })
+        .collect()
+}
+
+fn validate_player_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || 32 < name.len() {
+        return Err("Player name must be 1 to 32 characters long".to_string());
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("Player name can only contain alphanumerics, '_' and '-'".to_string());
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub(crate) struct LoginRequest {
+    name: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RegisterRequest {
+    name: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TeamRequest {
+    team: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct InviteRequest {
+    player: String,
+}
+
+pub(crate) async fn register(
+    data: web::Data<ServerData>,
+    body: web::Json<RegisterRequest>,
+) -> actix_web::Result<HttpResponse> {
+    data.accounts
+        .read()
+        .unwrap()
+        .check_register(&body.name, &body.password)
+        .map_err(error::ErrorBadRequest)?;
+    let password = body.password.clone();
+    let hash = web::block(move || PasswordHash::new(&password))
+        .await?
+        .map_err(error::ErrorInternalServerError)?;
+    data.accounts
+        .write()
+        .unwrap()
+        .register(&body.name, &body.password, hash)
+        .map_err(error::ErrorBadRequest)?;
+    println!("Registered player {}", body.name);
+    Ok(HttpResponse::Created().finish())
+}
+
+/// Log in and return a new session id bound to the player.
+pub(crate) async fn login(
+    data: web::Data<ServerData>,
+    body: web::Json<LoginRequest>,
+) -> actix_web::Result<HttpResponse> {
+    let invalid = || error::ErrorUnauthorized("Invalid player name or password");
+    let (salt, expected) = data
+        .accounts
+        .read()
+        .unwrap()
+        .password_hash(&body.name)
+        .ok_or_else(invalid)?;
+    let password = body.password.clone();
+    if !web::block(move || verify_password(&password, &salt, &expected)).await? {
+        return Err(invalid());
+    }
+    let player = data.accounts.read().unwrap().player(&body.name);
+    let session = data.new_session(Session { player });
+    println!("Player {} logged in with session {session}", body.name);
+    Ok(HttpResponse::Ok().body(session.to_string()))
+}
+
+/// Create a team with the logged in player as the only member.
+pub(crate) async fn create_team(
+    data: web::Data<ServerData>,
+    body: web::Json<TeamRequest>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    let player = data.request_player(&req)?;
+    data.accounts
+        .write()
+        .unwrap()
+        .create_team(&player.name, &body.team)
+        .map_err(error::ErrorBadRequest)?;
+    println!("Player {} created team {}", player.name, body.team);
+    Ok(HttpResponse::Created().finish())
+}
+
+/// Invite a player to the team of the logged in player.
+pub(crate) async fn invite_to_team(
+    data: web::Data<ServerData>,
+    body: web::Json<InviteRequest>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    let player = data.request_player(&req)?;
+    data.accounts
+        .write()
+        .unwrap()
+        .invite(&player.name, &body.player)
+        .map_err(error::ErrorBadRequest)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn join_team(
+    data: web::Data<ServerData>,
+    body: web::Json<TeamRequest>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    let player = data.request_player(&req)?;
+    data.accounts
+        .write()
+        .unwrap()
+        .join_team(&player.name, &body.team)
+        .map_err(error::ErrorBadRequest)?;
+    println!("Player {} joined team {}", player.name, body.team);
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn leave_team(
+    data: web::Data<ServerData>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    let player = data.request_player(&req)?;
+    data.accounts
+        .write()
+        .unwrap()
+        .leave_team(&player.name)
+        .map_err(error::ErrorBadRequest)?;
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,55 @@
+//! Fast-forwarding the simulation for the time the server was not running.
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::Game;
+
+/// Current wall-clock time in seconds since the UNIX epoch.
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Run the ticks missed since the game was saved, up to `max_s` seconds of game time.
+pub(crate) fn catch_up(game: &mut Game, tick_time: f64, max_s: f64) {
+    let Some(saved_at) = game.get_saved_at() else {
+        println!("The save has no timestamp; skipping catch-up");
+        return;
+    };
+    let elapsed = unix_time().saturating_sub(saved_at) as f64;
+    let simulated = elapsed.min(max_s);
+    let ticks = (simulated / tick_time) as usize;
+    if ticks == 0 {
+        return;
+    }
+    println!(
+        "Catching up {ticks} ticks for {elapsed:.0}s offline{}",
+        if simulated < elapsed {
+            format!(" (capped at {max_s}s)")
+        } else {
+            String::new()
+        }
+    );
+
+    let start = Instant::now();
+    let mut last_log = Instant::now();
+    for i in 0..ticks {
+        if let Err(e) = game.tick() {
+            println!("Tick error: {e}");
+        }
+        if 1. < last_log.elapsed().as_secs_f64() {
+            println!(
+                "Catching up: {}/{ticks} ticks ({:.1}%)",
+                i + 1,
+                (i + 1) as f64 * 100. / ticks as f64
+            );
+            last_log = Instant::now();
+        }
+    }
+    game.uniformify_tiles();
+    println!(
+        "Caught up {ticks} ticks in {:.3}s",
+        start.elapsed().as_secs_f64()
+    );
+}
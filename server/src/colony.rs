@@ -0,0 +1,490 @@
+//! A colony is an asteroid hosted by the server. Each colony has its own game state, tick loop,
+//! save file and set of subscribed sessions.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use ::actix::prelude::*;
+use ::actix_web::{error, web, HttpRequest, HttpResponse};
+use ::asteroid_colonies_logic::{
+    EntitiesDigest, PlayerCommand, PlayerId, Step, StepMessage, SAVE_VERSION,
+};
+use ::serde::{Deserialize, Serialize};
+
+use crate::{
+    catch_up::{catch_up, unix_time},
+    save::{backup_save, load_save},
+    save_file, serialize_state,
+    server::{ChatServer, NotifyState, NotifyStateEnum},
+    Game, ServerData,
+};
+
+/// The colony that clients join if they do not specify one. Its save file is `--autosave-file`.
+pub(crate) const DEFAULT_COLONY: &str = "default";
+
+/// Timing parameters shared by all colonies.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ColonyConfig {
+    /// Real time span for a simulation tick. It determines how fast the simulation evolves.
+    pub tick_time: f64,
+    pub autosave_period_s: f64,
+    pub autosave_pretty: bool,
+    pub push_period_s: f64,
+    /// Broadcast commands every tick instead of pushing the state.
+    pub lockstep: bool,
+    /// Interval in ticks to attach state hashes to the steps in lockstep mode.
+    pub state_hash_period: usize,
+    pub cleanup_period_s: f64,
+    pub max_catch_up_s: f64,
+}
+
+pub(crate) struct Colony {
+    pub name: String,
+    pub game: Mutex<Game>,
+    last_saved: Mutex<Instant>,
+    last_pushed: Mutex<Instant>,
+    last_cleanup: Mutex<Instant>,
+    autosave_file: PathBuf,
+    /// Held while writing the save file, so that an autosave in flight does not write it back
+    /// after the colony is archived and the file is moved away.
+    save_lock: Mutex<()>,
+    /// A signal from the websocket sessions to send synchronization data,
+    /// when it invoked a command to change game state.
+    ///
+    /// There will be one tick delay, but it's ok.
+    signal_push: AtomicBool,
+    /// An archived colony stops ticking and rejects commands.
+    archived: AtomicBool,
+    /// Counts the commands that modified the game, which can happen without advancing the time.
+    modifications: AtomicU64,
+    /// Digests of the entities shared by the sessions, with the global time and the modification
+    /// count they were computed at.
+    entities_digest: Mutex<Option<(usize, u64, Arc<EntitiesDigest>)>>,
+    /// Commands accepted since the last tick in lockstep mode. They are applied and broadcast
+    /// at the next tick, so that the state sent to clients between ticks never includes
+    /// commands of a step that they receive later.
+    pending_commands: Mutex<Vec<PlayerCommand>>,
+    pub srv: Addr<ChatServer>,
+}
+
+impl Colony {
+    /// Load the colony from its save file, or start a new game if the file does not exist.
+    /// It simulates the ticks missed while the server was down.
+    pub fn load(name: &str, autosave_file: PathBuf, config: &ColonyConfig) -> Result<Self, String> {
+        let mut game = Game::new(None)?;
+
+        let start = Instant::now();
+        // Refuse to start rather than overwriting a save file that we could not load by autosave.
+        let loaded = load_save(&mut game, &autosave_file)
+            .map_err(|e| format!("{e}\nFix or move away the save file to start a new game."))?;
+        if let Some(version) = loaded {
+            eprintln!(
+                "[{name}] Deserialized data {} object in {}ms",
+                game.count_tiles(),
+                start.elapsed().as_micros() as f64 * 1e-3
+            );
+            if version < SAVE_VERSION {
+                let backup = backup_save(&autosave_file, version)?;
+                eprintln!(
+                    "[{name}] Migrated save from version {version} to {SAVE_VERSION}; the original is kept in {backup:?}"
+                );
+            }
+            catch_up(&mut game, config.tick_time, config.max_catch_up_s);
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            game: Mutex::new(game),
+            last_saved: Mutex::new(Instant::now()),
+            last_pushed: Mutex::new(Instant::now()),
+            last_cleanup: Mutex::new(Instant::now()),
+            autosave_file,
+            save_lock: Mutex::new(()),
+            signal_push: AtomicBool::new(false),
+            archived: AtomicBool::new(false),
+            modifications: AtomicU64::new(0),
+            entities_digest: Mutex::new(None),
+            pending_commands: Mutex::new(vec![]),
+            srv: ChatServer::new().start(),
+        })
+    }
+
+    pub fn set_signal_push(&self, v: bool) {
+        self.signal_push.store(v, Ordering::Relaxed);
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.archived.load(Ordering::Relaxed)
+    }
+
+    /// Record that a command has modified the game. It should be called with the game locked.
+    pub fn mark_modified(&self) {
+        self.modifications.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Queue a command to apply at the next tick in lockstep mode.
+    pub fn queue_command(&self, command: PlayerCommand) {
+        self.pending_commands.lock().unwrap().push(command);
+    }
+
+    /// Apply the queued commands and return the ones that succeeded, to broadcast them.
+    fn apply_pending_commands(&self, game: &mut Game) -> Vec<PlayerCommand> {
+        let commands = std::mem::take(&mut *self.pending_commands.lock().unwrap());
+        commands
+            .into_iter()
+            .filter(
+                |command| match game.apply_command(&command.command, command.owner) {
+                    Ok(()) => {
+                        self.mark_modified();
+                        true
+                    }
+                    Err(e) => {
+                        println!("[{}] Command {:?} failed: {e}", self.name, command.command);
+                        false
+                    }
+                },
+            )
+            .collect()
+    }
+
+    /// Get the digests of the entities in the game, which should be locked by the caller.
+    /// They are computed once and shared by all sessions until the game changes.
+    pub fn entities_digest(&self, game: &Game) -> Arc<EntitiesDigest> {
+        let time = game.get_global_time();
+        let modifications = self.modifications.load(Ordering::Relaxed);
+        let mut cache = self.entities_digest.lock().unwrap();
+        match &*cache {
+            Some((cached_time, cached_modifications, digest))
+                if *cached_time == time && *cached_modifications == modifications =>
+            {
+                digest.clone()
+            }
+            _ => {
+                let digest = Arc::new(game.entities_digest());
+                *cache = Some((time, modifications, digest.clone()));
+                digest
+            }
+        }
+    }
+
+    /// Save the game synchronously, e.g. on shutdown.
+    pub fn save(&self, pretty: bool) {
+        let _lock = self.save_lock.lock().unwrap();
+        self.write_save(pretty);
+    }
+
+    fn write_save(&self, pretty: bool) {
+        let mut game = self.game.lock().unwrap();
+        game.set_saved_at(unix_time());
+        match serialize_state(&game, pretty) {
+            Ok(serialized) => save_file(&self.autosave_file, &serialized),
+            Err(e) => println!("[{}] Error saving file: {e}", self.name),
+        }
+    }
+
+    /// Stop the colony, flush the save file and move it to `archive_file`. Autosaves still in
+    /// flight skip writing after this.
+    fn archive(&self, pretty: bool, archive_file: &Path) -> std::io::Result<()> {
+        self.archived.store(true, Ordering::Relaxed);
+        let _lock = self.save_lock.lock().unwrap();
+        self.write_save(pretty);
+        fs::rename(&self.autosave_file, archive_file)
+    }
+
+    fn broadcast_step(&self, step: Step) {
+        match (StepMessage { payload: step }).to_json() {
+            Ok(text) => self.srv.do_send(NotifyState {
+                session_id: None,
+                set_state: NotifyStateEnum::Text(text),
+            }),
+            Err(e) => println!("[{}] Error serializing step: {e}", self.name),
+        }
+    }
+
+    /// Spawn the tick loop of this colony. It runs until the colony is archived.
+    pub fn start(self: &Arc<Self>, config: ColonyConfig) {
+        let colony = self.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval =
+                actix_web::rt::time::interval(std::time::Duration::from_secs_f64(config.tick_time));
+            loop {
+                interval.tick().await;
+
+                let start = Instant::now();
+
+                let mut game = colony.game.lock().unwrap();
+                // Check after locking so that we do not write the save file after archiving
+                if colony.is_archived() {
+                    println!("[{}] Tick loop stopped", colony.name);
+                    break;
+                }
+                let tick = game.get_global_time();
+                let commands = if config.lockstep {
+                    colony.apply_pending_commands(&mut game)
+                } else {
+                    vec![]
+                };
+                if let Err(e) = game.tick() {
+                    println!("[{}] Tick error: {e}", colony.name);
+                }
+
+                if config.lockstep {
+                    let state_hash = match game.get_global_time() % config.state_hash_period {
+                        0 => Some(game.state_hash()),
+                        _ => None,
+                    };
+                    colony.broadcast_step(Step {
+                        tick,
+                        commands,
+                        state_hash,
+                    });
+                }
+
+                let mut last_saved = colony.last_saved.lock().unwrap();
+                if config.autosave_period_s < last_saved.elapsed().as_secs_f64() {
+                    game.uniformify_tiles();
+                    game.set_saved_at(unix_time());
+                    if let Ok(serialized) = serialize_state(&game, config.autosave_pretty) {
+                        let colony = colony.clone();
+                        actix_web::rt::spawn(async move {
+                            let _lock = colony.save_lock.lock().unwrap();
+                            if !colony.is_archived() {
+                                save_file(&colony.autosave_file, &serialized);
+                            }
+                        });
+                    }
+                    *last_saved = Instant::now();
+                }
+
+                let mut last_pushed = colony.last_pushed.lock().unwrap();
+                if !config.lockstep
+                    && (colony.signal_push.load(Ordering::Relaxed)
+                        || config.push_period_s < last_pushed.elapsed().as_secs_f64())
+                {
+                    game.uniformify_tiles();
+                    colony.srv.do_send(NotifyState {
+                        session_id: None,
+                        set_state: NotifyStateEnum::SetStateWithDiff,
+                    });
+                    colony.set_signal_push(false);
+                    *last_pushed = Instant::now();
+                }
+
+                let mut last_cleanup = colony.last_cleanup.lock().unwrap();
+                if config.cleanup_period_s < last_cleanup.elapsed().as_secs_f64() {
+                    colony.srv.do_send(NotifyState {
+                        session_id: None,
+                        set_state: NotifyStateEnum::Cleanup,
+                    });
+                    *last_cleanup = Instant::now();
+                }
+
+                if game.get_global_time().is_multiple_of(100) {
+                    println!(
+                        "[{:?}] [{}] Tick {}, calc: {:.3}ms",
+                        std::thread::current().id(),
+                        colony.name,
+                        game.get_global_time(),
+                        start.elapsed().as_micros() as f64 * 1e-3,
+                    );
+                }
+            }
+        });
+    }
+}
+
+/// Colony names are used in file names and URLs, so we only allow a safe set of characters.
+pub(crate) fn validate_colony_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || 32 < name.len() {
+        return Err("Colony name must be 1 to 32 characters long".to_string());
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("Colony name can only contain alphanumerics, '_' and '-'".to_string());
+    }
+    Ok(())
+}
+
+pub(crate) fn colony_file(colonies_dir: &Path, name: &str) -> PathBuf {
+    colonies_dir.join(format!("{name}.json"))
+}
+
+/// Find the names of the colonies saved in the directory.
+pub(crate) fn find_colonies(colonies_dir: &Path) -> std::io::Result<Vec<String>> {
+    let entries = match fs::read_dir(colonies_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut names = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_file() || path.extension() != Some("json".as_ref()) {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if name != DEFAULT_COLONY && validate_colony_name(name).is_ok() {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn meta_file(colonies_dir: &Path, name: &str) -> PathBuf {
+    colonies_dir.join(format!("{name}.meta"))
+}
+
+/// Information of a colony besides the game, saved next to its save file.
+#[derive(Default, Serialize, Deserialize)]
+struct ColonyMeta {
+    /// The player who created the colony, who can archive it as well as administrators.
+    /// Colonies created before it was recorded can be archived only by administrators.
+    owner: Option<PlayerId>,
+}
+
+impl ColonyMeta {
+    fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        let data = serde_json::to_string(self).map_err(std::io::Error::other)?;
+        fs::write(path, data)
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct ColonyInfo {
+    name: String,
+    global_time: usize,
+}
+
+pub(crate) async fn list_colonies(
+    data: web::Data<ServerData>,
+) -> actix_web::Result<web::Json<Vec<ColonyInfo>>> {
+    let colonies = data.colonies.read().unwrap();
+    let mut list: Vec<_> = colonies
+        .values()
+        .map(|colony| ColonyInfo {
+            name: colony.name.clone(),
+            global_time: colony.game.lock().unwrap().get_global_time(),
+        })
+        .collect();
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(web::Json(list))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CreateColony {
+    name: String,
+}
+
+/// Create a colony owned by the logged in player.
+pub(crate) async fn create_colony(
+    data: web::Data<ServerData>,
+    body: web::Json<CreateColony>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    let player = data.request_player(&req)?;
+    let name = &body.name;
+    validate_colony_name(name).map_err(error::ErrorBadRequest)?;
+
+    let exists = || error::ErrorConflict(format!("Colony {name} already exists"));
+    if data.colonies.read().unwrap().contains_key(name) {
+        return Err(exists());
+    }
+    let file = colony_file(&data.colonies_dir, name);
+    if file.exists() {
+        return Err(error::ErrorConflict(format!(
+            "A save file for colony {name} already exists"
+        )));
+    }
+
+    // Generate the game before locking, so that the other colonies keep serving requests.
+    let colony =
+        Arc::new(Colony::load(name, file, &data.config).map_err(error::ErrorInternalServerError)?);
+    {
+        let mut colonies = data.colonies.write().unwrap();
+        // Another request may have created the colony while we were generating the game
+        if colonies.contains_key(name) {
+            return Err(exists());
+        }
+        colonies.insert(name.clone(), colony.clone());
+    }
+    let meta = ColonyMeta {
+        owner: Some(player.id),
+    };
+    if let Err(e) = fs::create_dir_all(&data.colonies_dir)
+        .and_then(|_| meta.save(&meta_file(&data.colonies_dir, name)))
+    {
+        data.colonies.write().unwrap().remove(name);
+        return Err(e.into());
+    }
+    colony.save(data.config.autosave_pretty);
+    colony.start(data.config);
+    println!("Created colony {name} by {}", player.name);
+
+    Ok(HttpResponse::Created().finish())
+}
+
+/// Stop a colony and move its save file to the archive directory. Only the owner of the colony
+/// or an administrator can archive it.
+pub(crate) async fn archive_colony(
+    data: web::Data<ServerData>,
+    name: web::Path<String>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    let player = data.request_player(&req)?;
+    let name = name.into_inner();
+    if name == DEFAULT_COLONY {
+        return Err(error::ErrorBadRequest(
+            "The default colony cannot be archived",
+        ));
+    }
+    let meta_path = meta_file(&data.colonies_dir, &name);
+    let colony = {
+        let mut colonies = data.colonies.write().unwrap();
+        if !colonies.contains_key(&name) {
+            return Err(error::ErrorNotFound(format!(
+                "Colony {name} does not exist"
+            )));
+        }
+        if !player.admin && ColonyMeta::load(&meta_path).owner != Some(player.id) {
+            return Err(error::ErrorForbidden(
+                "Only the owner or an administrator can archive the colony",
+            ));
+        }
+        colonies.remove(&name).unwrap()
+    };
+
+    let archive_dir = data.colonies_dir.join("archive");
+    fs::create_dir_all(&archive_dir)?;
+    let archive_name = format!("{name}-{}", unix_time());
+    let archive_file = archive_dir.join(format!("{archive_name}.json"));
+    colony.archive(data.config.autosave_pretty, &archive_file)?;
+    if meta_path.exists() {
+        fs::rename(&meta_path, archive_dir.join(format!("{archive_name}.meta")))?;
+    }
+
+    colony.srv.do_send(NotifyState {
+        session_id: None,
+        set_state: NotifyStateEnum::Archived,
+    });
+    println!("Archived colony {name} to {archive_file:?}");
+
+    Ok(HttpResponse::Ok().finish())
+}
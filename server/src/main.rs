@@ -1,31 +1,37 @@
+mod account;
+mod catch_up;
+mod colony;
+mod save;
 mod server;
 mod session;
 mod websocket;
 
 use crate::{
     // api::set_timescale::set_timescale,
-    server::{ChatServer, NotifyState, NotifyStateEnum},
-    websocket::websocket_index,
+    account::{create_team, invite_to_team, join_team, leave_team, login, register, Accounts},
+    colony::{
+        archive_colony, colony_file, create_colony, find_colonies, list_colonies, Colony,
+        ColonyConfig, DEFAULT_COLONY,
+    },
+    websocket::{websocket_colony_index, websocket_index},
 };
-use ::actix::prelude::*;
 use ::actix_cors::Cors;
 use ::actix_files::NamedFile;
-use ::actix_web::{middleware, web, App, HttpRequest, HttpServer};
-use ::asteroid_colonies_logic::AsteroidColoniesGame;
+use ::actix_web::{error, http::header, middleware, web, App, HttpRequest, HttpServer};
+use ::asteroid_colonies_logic::{
+    load_ruleset, ruleset, AsteroidColoniesGame, Ruleset, StatsWindow,
+};
 use ::clap::Parser;
 use ::openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
 use actix_web::HttpResponse;
-use session::SessionId;
+use serde::Deserialize;
+use session::{Player, Session, SessionId};
 use std::{
-    collections::HashSet,
+    collections::HashMap,
     fs,
-    io::BufReader,
     path::{Path, PathBuf},
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Mutex, RwLock,
-    },
-    time::Instant,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
 };
 
 type Game = AsteroidColoniesGame;
@@ -51,18 +57,55 @@ struct Args {
     host: String,
     #[clap(short, long, default_value = "../dist")]
     asset_path: PathBuf,
-    #[clap(long, default_value = "save.json")]
+    #[clap(
+        long,
+        default_value = "save.json",
+        help = "The save file of the default colony"
+    )]
     autosave_file: PathBuf,
+    #[clap(
+        long,
+        default_value = "colonies",
+        help = "The directory to save colonies other than the default one"
+    )]
+    colonies_dir: PathBuf,
     #[clap(long, default_value = "10")]
     autosave_period_s: f64,
+    #[clap(
+        long,
+        help = "Check that the autosave file and the colony saves can be loaded and exit without serving"
+    )]
+    check_save: bool,
+    #[clap(
+        long,
+        help = "Upgrade the autosave file and the colony saves to the current format version and exit without serving. The originals are kept as backups."
+    )]
+    migrate: bool,
     #[clap(long)]
     autosave_pretty: bool,
     #[clap(long, default_value = "10")]
     push_period_s: f64,
+    #[clap(
+        long,
+        help = "Broadcast the accepted commands every tick and let clients simulate, instead of pushing the state every push_period_s"
+    )]
+    lockstep: bool,
+    #[clap(
+        long,
+        default_value = "10",
+        help = "Interval in ticks to send state hashes to detect desyncs in lockstep mode"
+    )]
+    state_hash_period: usize,
     #[clap(long, default_value = "60")]
     cleanup_period_s: f64,
     #[clap(long, default_value = "0.2", help = "Tick time in seconds")]
     tick_time: f64,
+    #[clap(
+        long,
+        default_value = "3600",
+        help = "Maximum offline time in seconds to simulate on startup. 0 disables catch-up."
+    )]
+    max_catch_up_s: f64,
     #[cfg(not(debug_assertions))]
     #[clap(
         long,
@@ -73,6 +116,28 @@ struct Args {
     #[cfg(debug_assertions)]
     #[clap(long, default_value = ".", help = "JavaScript and Wasm path")]
     js_path: PathBuf,
+    #[clap(
+        long,
+        help = "A JSON file of the ruleset. The default ruleset is used if omitted"
+    )]
+    ruleset: Option<PathBuf>,
+    #[clap(
+        long,
+        default_value = "accounts.json",
+        help = "The file to store player accounts"
+    )]
+    accounts_file: PathBuf,
+    #[clap(
+        long,
+        help = "Refuse guest sessions, so that only logged in players can connect"
+    )]
+    require_login: bool,
+    #[clap(
+        long,
+        default_value = "86400",
+        help = "Time in seconds until a session expires and the player has to log in again"
+    )]
+    session_lifetime_s: f64,
     #[clap(long)]
     ssl_cert: Option<PathBuf>,
     #[clap(long)]
@@ -80,41 +145,74 @@ struct Args {
 }
 
 struct ServerData {
-    game: Mutex<AsteroidColoniesGame>,
+    colonies: RwLock<HashMap<String, Arc<Colony>>>,
+    /// The directory to save colonies other than the default one
+    colonies_dir: PathBuf,
+    config: ColonyConfig,
     // asset_path: PathBuf,
     js_path: PathBuf,
-    last_saved: Mutex<Instant>,
-    last_pushed: Mutex<Instant>,
-    last_cleanup: Mutex<Instant>,
-    autosave_file: PathBuf,
-    /// Real time span for a simulation tick. It determines how fast the simulation evolves.
-    tick_time: f64,
-    /// A signal from the websocket sessions to send synchronization data,
-    /// when it invoked a command to change game state.
-    ///
-    /// There will be one tick delay, but it's ok.
-    signal_push: AtomicBool,
-    srv: Addr<ChatServer>,
-    sessions: RwLock<HashSet<SessionId>>,
+    accounts: RwLock<Accounts>,
+    require_login: bool,
+    /// Sessions with the time they expire at
+    sessions: RwLock<HashMap<SessionId, (Session, Instant)>>,
+    session_lifetime: Duration,
 }
 
 impl ServerData {
-    fn new_session(&self) -> SessionId {
-        let session = SessionId::new();
+    fn new_session(&self, session: Session) -> SessionId {
+        let session_id = SessionId::new();
+        let now = Instant::now();
         let mut sessions = self.sessions.write().unwrap();
-        sessions.insert(session);
-        session
+        // Forget expired sessions here, so that they do not pile up
+        sessions.retain(|_, (_, expires_at)| now < *expires_at);
+        sessions.insert(session_id, (session, now + self.session_lifetime));
+        session_id
     }
 
-    pub fn set_signal_push(&self, v: bool) {
-        self.signal_push.store(v, Ordering::Relaxed);
+    /// Get the session if it exists and has not expired.
+    fn session(&self, session_id: SessionId) -> Option<Session> {
+        self.sessions
+            .read()
+            .unwrap()
+            .get(&session_id)
+            .filter(|(_, expires_at)| Instant::now() < *expires_at)
+            .map(|(session, _)| session.clone())
+    }
+
+    /// The session given by the `Authorization: Bearer <session id>` header of the request,
+    /// which may be a guest.
+    fn request_session(&self, req: &HttpRequest) -> actix_web::Result<Session> {
+        let session_id = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| error::ErrorUnauthorized("Session id is required"))?;
+        let session_id = SessionId::try_from(session_id).map_err(error::ErrorBadRequest)?;
+        self.session(session_id)
+            .ok_or_else(|| error::ErrorUnauthorized("Unknown session"))
+    }
+
+    /// The logged in player of the session of the request. Guests are rejected.
+    fn request_player(&self, req: &HttpRequest) -> actix_web::Result<Player> {
+        self.request_session(req)?
+            .player
+            .ok_or_else(|| error::ErrorForbidden("Login required"))
+    }
+
+    fn colony(&self, name: &str) -> Option<Arc<Colony>> {
+        self.colonies.read().unwrap().get(name).cloned()
     }
 }
 
 async fn new_session(data: web::Data<ServerData>) -> actix_web::Result<HttpResponse> {
     // let mut game = data.sessions;//.write().unwrap();
 
-    let new_session = data.new_session();
+    if data.require_login {
+        return Err(error::ErrorUnauthorized("Login required"));
+    }
+
+    let new_session = data.new_session(Session::default());
 
     // if let Some(body) = game.get(id) {
     //     data.srv.do_send(NotifyNewBody {
@@ -134,10 +232,24 @@ async fn new_session(data: web::Data<ServerData>) -> actix_web::Result<HttpRespo
     Ok(HttpResponse::Ok().body(new_session.to_string()))
 }
 
-async fn get_state(data: web::Data<ServerData>) -> actix_web::Result<HttpResponse> {
+#[derive(Deserialize)]
+struct ColonyQuery {
+    colony: Option<String>,
+}
+
+async fn get_state(
+    data: web::Data<ServerData>,
+    query: web::Query<ColonyQuery>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    data.request_session(&req)?;
     let start = Instant::now();
 
-    let game = data.game.lock().unwrap();
+    let name = query.colony.as_deref().unwrap_or(DEFAULT_COLONY);
+    let colony = data
+        .colony(name)
+        .ok_or_else(|| error::ErrorNotFound(format!("Colony {name} does not exist")))?;
+    let game = colony.game.lock().unwrap();
 
     let serialized = serialize_state(&game, false)?;
 
@@ -152,8 +264,50 @@ async fn get_state(data: web::Data<ServerData>) -> actix_web::Result<HttpRespons
         .body(serialized))
 }
 
+async fn get_ruleset() -> actix_web::Result<HttpResponse> {
+    let serialized = ruleset()
+        .to_json()
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serialized))
+}
+
 async fn get_tick_time(data: web::Data<ServerData>) -> actix_web::Result<web::Json<f64>> {
-    Ok(web::Json(data.tick_time))
+    Ok(web::Json(data.config.tick_time))
+}
+
+#[derive(Deserialize)]
+struct StatsQuery {
+    colony: Option<String>,
+    /// Return only the buckets of this window, or all windows if omitted.
+    window: Option<StatsWindow>,
+}
+
+async fn get_stats(
+    data: web::Data<ServerData>,
+    query: web::Query<StatsQuery>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    data.request_session(&req)?;
+    let name = query.colony.as_deref().unwrap_or(DEFAULT_COLONY);
+    let colony = data
+        .colony(name)
+        .ok_or_else(|| error::ErrorNotFound(format!("Colony {name} does not exist")))?;
+    let game = colony.game.lock().unwrap();
+    let stats = game.production_stats();
+    let serialized = match query.window {
+        Some(window) => serde_json::to_string(stats.window(window)),
+        None => serde_json::to_string(stats),
+    }
+    .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serialized))
+}
+
+async fn get_lockstep(data: web::Data<ServerData>) -> actix_web::Result<web::Json<bool>> {
+    Ok(web::Json(data.config.lockstep))
 }
 
 #[cfg(not(debug_assertions))]
@@ -222,112 +376,76 @@ fn save_file(autosave_file: &Path, serialized: &str) {
 async fn main() -> std::io::Result<()> {
     let args = Args::parse();
 
-    let mut game =
-        Game::new(None).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    if let Some(ruleset_file) = &args.ruleset {
+        let src = fs::read_to_string(ruleset_file)?;
+        let ruleset = Ruleset::from_json(&src).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Error in ruleset {ruleset_file:?}: {e}"),
+            )
+        })?;
+        load_ruleset(ruleset)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        println!("Loaded ruleset {ruleset_file:?}");
+    }
 
-    let start = Instant::now();
-    if let Ok(data) = fs::File::open(&args.autosave_file).map(BufReader::new) {
-        if let Err(e) = game.deserialize(data) {
-            eprintln!("Error on loading serialized data: {}", e);
-        } else {
-            eprintln!(
-                "Deserialized data {} object in {}ms",
-                game.count_tiles(),
-                start.elapsed().as_micros() as f64 * 1e-3
-            );
+    if args.check_save || args.migrate {
+        let mut paths = vec![args.autosave_file.clone()];
+        for name in find_colonies(&args.colonies_dir)? {
+            paths.push(colony_file(&args.colonies_dir, &name));
         }
+        let res = if args.check_save {
+            save::for_each_save(&paths, save::check_save)
+        } else {
+            save::for_each_save(&paths, |path| {
+                save::migrate_save_file(path, args.autosave_pretty)
+            })
+        };
+        return res.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+    }
+
+    let accounts = Accounts::load(&args.accounts_file)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    let config = ColonyConfig {
+        tick_time: args.tick_time,
+        autosave_period_s: args.autosave_period_s,
+        autosave_pretty: args.autosave_pretty,
+        push_period_s: args.push_period_s,
+        lockstep: args.lockstep,
+        state_hash_period: args.state_hash_period.max(1),
+        cleanup_period_s: args.cleanup_period_s,
+        max_catch_up_s: args.max_catch_up_s,
+    };
+
+    // Load all colonies and catch up before accepting connections
+    let mut colonies = HashMap::new();
+    let load_error = |e| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+    let default_colony =
+        Colony::load(DEFAULT_COLONY, args.autosave_file, &config).map_err(load_error)?;
+    colonies.insert(DEFAULT_COLONY.to_string(), Arc::new(default_colony));
+    for name in find_colonies(&args.colonies_dir)? {
+        let file = colony_file(&args.colonies_dir, &name);
+        let colony = Colony::load(&name, file, &config).map_err(load_error)?;
+        colonies.insert(name, Arc::new(colony));
+    }
+    println!("Hosting {} colonies", colonies.len());
+    for colony in colonies.values() {
+        colony.start(config);
     }
 
     let data = web::Data::new(ServerData {
-        game: Mutex::new(game),
+        colonies: RwLock::new(colonies),
+        colonies_dir: args.colonies_dir,
+        config,
         // asset_path: args.asset_path,
         js_path: args.js_path,
-        last_saved: Mutex::new(Instant::now()),
-        last_pushed: Mutex::new(Instant::now()),
-        last_cleanup: Mutex::new(Instant::now()),
-        autosave_file: args.autosave_file,
-        tick_time: args.tick_time,
-        signal_push: AtomicBool::new(false),
-        srv: ChatServer::new().start(),
-        sessions: RwLock::new(HashSet::new()),
+        accounts: RwLock::new(accounts),
+        require_login: args.require_login,
+        sessions: RwLock::new(HashMap::new()),
+        session_lifetime: Duration::from_secs_f64(args.session_lifetime_s),
     });
     let data_copy = data.clone();
-    let data_copy2 = data.clone();
-
-    let autosave_period_s = args.autosave_period_s;
-    let autosave_pretty = args.autosave_pretty;
-    let push_period_s = args.push_period_s;
-    let cleanup_period_s = args.cleanup_period_s;
-
-    actix_web::rt::spawn(async move {
-        let mut interval =
-            actix_web::rt::time::interval(std::time::Duration::from_secs_f64(args.tick_time));
-        loop {
-            interval.tick().await;
-
-            let start = Instant::now();
-
-            let mut game = data_copy.game.lock().unwrap();
-            if let Err(e) = game.tick() {
-                println!("Tick error: {e}");
-            }
-
-            let mut last_saved = data_copy.last_saved.lock().unwrap();
-            if autosave_period_s < last_saved.elapsed().as_secs_f64() {
-                game.uniformify_tiles();
-                if let Ok(serialized) = serialize_state(&game, autosave_pretty) {
-                    let autosave_file = data_copy.autosave_file.clone();
-                    actix_web::rt::spawn(async move {
-                        save_file(&autosave_file, &serialized);
-                    });
-                }
-                *last_saved = Instant::now();
-            }
-
-            let mut last_pushed = data_copy.last_pushed.lock().unwrap();
-            if data_copy.signal_push.load(Ordering::Relaxed)
-                || push_period_s < last_pushed.elapsed().as_secs_f64()
-            {
-                game.uniformify_tiles();
-                // if let Ok(serialized) = serialize_state(&game, false) {
-                //     data_copy.srv.do_send(NotifyState {
-                //         session_id: None,
-                //         set_state: NotifyStateEnum::SetState(SetStateWs(serialized)),
-                //     });
-                // }
-                // if let Ok(serialized) = game.serialize_bin() {
-                //     data_copy.srv.do_send(NotifyState {
-                //         session_id: None,
-                //         set_state: NotifyStateEnum::SetStateBin(SetStateBinWs(serialized)),
-                //     });
-                // }
-                data_copy.srv.do_send(NotifyState {
-                    session_id: None,
-                    set_state: NotifyStateEnum::SetStateWithDiff,
-                });
-                data_copy.set_signal_push(false);
-                *last_pushed = Instant::now();
-            }
-
-            let mut last_cleanup = data_copy.last_cleanup.lock().unwrap();
-            if cleanup_period_s < last_cleanup.elapsed().as_secs_f64() {
-                data_copy.srv.do_send(NotifyState {
-                    session_id: None,
-                    set_state: NotifyStateEnum::Cleanup,
-                });
-                *last_cleanup = Instant::now();
-            }
-
-            if game.get_global_time() % 100 == 0 {
-                println!(
-                    "[{:?}] Tick {}, calc: {:.3}ms",
-                    std::thread::current().id(),
-                    game.get_global_time(),
-                    start.elapsed().as_micros() as f64 * 1e-3,
-                );
-            }
-        }
-    });
 
     let builder = args.ssl_cert.zip(args.ssl_priv_key).map(|(cert, key)| {
         let mut builder = SslAcceptor::mozilla_modern_v5(SslMethod::tls_server()).unwrap();
@@ -348,9 +466,25 @@ async fn main() -> std::io::Result<()> {
             .wrap(cors)
             .app_data(data.clone())
             .service(websocket_index)
+            .service(websocket_colony_index)
             .route("/api/session", web::post().to(new_session))
+            .route("/api/register", web::post().to(register))
+            .route("/api/login", web::post().to(login))
+            .route("/api/team", web::post().to(create_team))
+            .route("/api/team/invite", web::post().to(invite_to_team))
+            .route("/api/team/join", web::post().to(join_team))
+            .route("/api/team/leave", web::post().to(leave_team))
             .route("/api/load", web::get().to(get_state))
-            .route("/api/tick_time", web::get().to(get_tick_time));
+            .route("/api/tick_time", web::get().to(get_tick_time))
+            .route("/api/lockstep", web::get().to(get_lockstep))
+            .route("/api/stats", web::get().to(get_stats))
+            .route("/api/ruleset", web::get().to(get_ruleset))
+            .route("/api/colonies", web::get().to(list_colonies))
+            .route("/api/colonies", web::post().to(create_colony))
+            .route(
+                "/api/colonies/{name}/archive",
+                web::post().to(archive_colony),
+            );
         // .route("/api/time_scale", web::post().to(set_timescale));
         #[cfg(not(debug_assertions))]
         {
@@ -371,9 +505,9 @@ async fn main() -> std::io::Result<()> {
     .run()
     .await;
 
-    match serialize_state(&data_copy2.game.lock().unwrap(), autosave_pretty) {
-        Ok(serialized) => save_file(&data_copy2.autosave_file, &serialized),
-        Err(e) => println!("Error saving file: {e}"),
+    let colonies = data_copy.colonies.read().unwrap();
+    for colony in colonies.values() {
+        colony.save(data_copy.config.autosave_pretty);
     }
     Ok(())
 }
@@ -0,0 +1,90 @@
+//! Loading, checking and migrating save files.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ::asteroid_colonies_logic::{save_version, SAVE_VERSION};
+
+use crate::{save_file, serialize_state, Game};
+
+/// Load a save file into the game and return the format version that the file was written
+/// with, or `None` if the file does not exist.
+pub(crate) fn load_save(game: &mut Game, path: &Path) -> Result<Option<u32>, String> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {path:?}: {e}")),
+    };
+    let value: serde_json::Value =
+        serde_json::from_str(&data).map_err(|e| format!("Failed to parse {path:?}: {e}"))?;
+    let version = save_version(&value)?;
+    game.deserialize_value(value)
+        .map_err(|e| format!("Failed to load {path:?} (version {version}): {e}"))?;
+    Ok(Some(version))
+}
+
+/// Copy the save file aside before it is overwritten in a newer format. An existing backup of
+/// the same version is left untouched.
+pub(crate) fn backup_save(path: &Path, version: u32) -> Result<PathBuf, String> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{version}.bak"));
+    let backup = PathBuf::from(backup);
+    if !backup.exists() {
+        fs::copy(path, &backup).map_err(|e| format!("Failed to back up to {backup:?}: {e}"))?;
+    }
+    Ok(backup)
+}
+
+/// Validate a save file without modifying it.
+pub(crate) fn check_save(path: &Path) -> Result<(), String> {
+    let mut game = Game::new(None)?;
+    let Some(version) = load_save(&mut game, path)? else {
+        return Err(format!("Save file {path:?} does not exist"));
+    };
+    println!(
+        "Save file {path:?} is valid: version {version}, {} tiles, global time {}",
+        game.count_tiles(),
+        game.get_global_time()
+    );
+    if version < SAVE_VERSION {
+        println!("It needs migration to version {SAVE_VERSION}. Run with --migrate to upgrade it.");
+    }
+    Ok(())
+}
+
+/// Upgrade a save file to the current format version in place, keeping a backup of the original.
+pub(crate) fn migrate_save_file(path: &Path, pretty: bool) -> Result<(), String> {
+    let mut game = Game::new(None)?;
+    let Some(version) = load_save(&mut game, path)? else {
+        return Err(format!("Save file {path:?} does not exist"));
+    };
+    if version == SAVE_VERSION {
+        println!("Save file {path:?} is already version {SAVE_VERSION}");
+        return Ok(());
+    }
+    let backup = backup_save(path, version)?;
+    let serialized = serialize_state(&game, pretty).map_err(|e| e.to_string())?;
+    save_file(path, &serialized);
+    println!("Migrated {path:?} from version {version} to {SAVE_VERSION}; the original is kept in {backup:?}");
+    Ok(())
+}
+
+/// Run a check or a migration on every save file, reporting all the failures instead of stopping
+/// at the first one.
+pub(crate) fn for_each_save(
+    paths: &[PathBuf],
+    f: impl Fn(&Path) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut failed = 0;
+    for path in paths {
+        if let Err(e) = f(path) {
+            eprintln!("{e}");
+            failed += 1;
+        }
+    }
+    if failed != 0 {
+        return Err(format!("{failed} of {} save files failed", paths.len()));
+    }
+    Ok(())
+}
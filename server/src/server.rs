@@ -168,7 +168,11 @@ pub(crate) enum NotifyStateEnum {
     SetState(SetStateWs),
     SetStateBin(SetStateBinWs),
     SetStateWithDiff,
+    /// A message to send as is, e.g. a lockstep step
+    Text(String),
     Cleanup,
+    /// The colony was archived and stopped
+    Archived,
 }
 
 #[derive(Deserialize, Serialize, Debug, Message)]
@@ -203,7 +207,11 @@ impl Handler<NotifyState> for ChatServer {
             }
             NotifyStateEnum::SetStateBin(msg) => self.send_message_bin(&msg.0, session_id),
             NotifyStateEnum::SetStateWithDiff => self.send_message_with_diff(session_id),
+            NotifyStateEnum::Text(text) => self.send_message(&text, session_id),
             NotifyStateEnum::Cleanup => self.cleanup(),
+            NotifyStateEnum::Archived => {
+                self.send_message("{\"type\": \"colonyArchived\"}", session_id)
+            }
         }
     }
 }
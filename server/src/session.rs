@@ -1,3 +1,4 @@
+use asteroid_colonies_logic::PlayerId;
 use rand::random;
 use serde::{ser::Serialize, Deserialize, Serializer};
 use std::cmp::Eq;
@@ -25,27 +26,52 @@ impl std::fmt::Display for SessionId {
     }
 }
 
-impl From<&str> for SessionId {
-    fn from(s: &str) -> Self {
+impl TryFrom<&str> for SessionId {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        if s.len() != SESSION_DIGITS * 2 {
+            return Err(format!(
+                "Session id must be {} hex digits",
+                SESSION_DIGITS * 2
+            ));
+        }
         let mut ret = [0; SESSION_DIGITS];
         for (i, c) in s.bytes().enumerate() {
-            let c = if (b'0'..=b'9').contains(&c) {
+            let c = if c.is_ascii_digit() {
                 c - b'0'
             } else if (b'a'..=b'f').contains(&c) {
                 c - b'a' + 10
             } else {
-                panic!();
+                return Err(format!("Invalid character in session id: {:?}", c as char));
             };
             ret[i / 2] |= c << ((1 - i % 2) * 4);
         }
-        Self(ret)
+        Ok(Self(ret))
     }
 }
 
-impl From<String> for SessionId {
-    fn from(s: String) -> Self {
-        Self::from(&s as &str)
+/// A session bound to a player, or a guest if `player` is `None`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Session {
+    pub player: Option<Player>,
+}
+
+impl Session {
+    pub fn player_name(&self) -> &str {
+        self.player.as_ref().map_or("guest", |p| &p.name)
     }
+
+    pub fn player_id(&self) -> Option<PlayerId> {
+        self.player.as_ref().map(|p| p.id)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Player {
+    pub name: String,
+    pub id: PlayerId,
+    pub admin: bool,
 }
 
 impl Serialize for SessionId {
@@ -1,22 +1,32 @@
-use std::{collections::HashMap, time::Instant};
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Instant,
+};
 
 use crate::{
+    account::Accounts,
+    colony::{Colony, DEFAULT_COLONY},
     server::ChatServer,
     server::{Connect, Message},
-    session::SessionId,
-    ServerData,
+    session::{Session, SessionId},
+    Game, ServerData,
 };
 use ::actix::{prelude::*, Actor, StreamHandler};
 use ::actix_web::{web, HttpRequest, HttpResponse};
 
 use ::serde::{Deserialize, Serialize};
 use actix_web_actors::ws;
-use asteroid_colonies_logic::{
-    construction::{Construction, ConstructionType},
-    ItemType, Pos, Position,
-};
+use asteroid_colonies_logic::{Command, EntitiesDigest, PlayerCommand, Pos, Position, SyncAck};
 
-/// Open a WebSocket instance and give it to the client.
+/// The number of updates kept waiting for acknowledgements. If the client falls behind more
+/// than this, it receives all the entities again.
+const MAX_UNACKED_FRAMES: usize = 8;
+
+/// Open a WebSocket instance for the default colony and give it to the client.
 /// `session_id` should be created by `/api/session` beforehand.
 #[actix_web::get("/ws/{session_id}")]
 pub(crate) async fn websocket_index(
@@ -25,23 +35,58 @@ pub(crate) async fn websocket_index(
     req: HttpRequest,
     stream: web::Payload,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let session_id: SessionId = session_id.into_inner().into();
+    start_session(&data, DEFAULT_COLONY, &session_id, &req, stream)
+}
+
+/// Open a WebSocket instance to join the given colony.
+#[actix_web::get("/ws/{colony}/{session_id}")]
+pub(crate) async fn websocket_colony_index(
+    data: web::Data<ServerData>,
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    stream: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (colony, session_id) = path.into_inner();
+    start_session(&data, &colony, &session_id, &req, stream)
+}
+
+fn start_session(
+    data: &web::Data<ServerData>,
+    colony: &str,
+    session_id: &str,
+    req: &HttpRequest,
+    stream: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(colony) = data.colony(colony) else {
+        return Err(actix_web::error::ErrorNotFound(format!(
+            "Colony {colony} does not exist"
+        )));
+    };
+    // Validate the session before upgrading, so that unknown clients cannot connect
+    let session_id = SessionId::try_from(session_id).map_err(actix_web::error::ErrorBadRequest)?;
+    let Some(session) = data.session(session_id) else {
+        return Err(actix_web::error::ErrorUnauthorized("Unknown session"));
+    };
+
+    let player = session.player_name().to_string();
 
     let session_ws = SessionWs {
         data: data.clone(),
+        addr: colony.srv.clone(),
+        colony: colony.clone(),
         session_id,
-        addr: data.srv.clone(),
+        session,
         chunks_digest: HashMap::new(),
+        frame: 0,
+        acked_entities: None,
+        sent_entities: VecDeque::new(),
         last_updated: Instant::now(),
     };
 
-    // let srv = data.srv.clone();
-    // srv.do_send(Connect{addr: Addr(session_ws).recipient()});
-
-    let resp = ws::start(session_ws, &req, stream);
+    let resp = ws::start(session_ws, req, stream);
     println!(
-        "websocket connection established for session {}: {:?}",
-        session_id, resp
+        "websocket connection established for session {} ({}) in colony {}: {:?}",
+        session_id, player, colony.name, resp
     );
     resp
 }
@@ -49,9 +94,18 @@ pub(crate) async fn websocket_index(
 /// Define HTTP actor
 struct SessionWs {
     pub data: web::Data<ServerData>,
+    pub colony: Arc<Colony>,
     pub session_id: SessionId,
+    pub session: Session,
     pub addr: Addr<ChatServer>,
     pub chunks_digest: HashMap<Position, u64>,
+    /// The number of the last update sent to the client
+    pub frame: u64,
+    /// The entity digests of the last update that the client acknowledged, which the next
+    /// update is a diff against.
+    pub acked_entities: Option<(u64, Arc<EntitiesDigest>)>,
+    /// The entity digests of the updates sent but not acknowledged yet
+    pub sent_entities: VecDeque<(u64, Arc<EntitiesDigest>)>,
     pub last_updated: Instant,
 }
 
@@ -98,13 +152,7 @@ impl Handler<Message> for SessionWs {
         match msg {
             Message::Text(txt) => ctx.text(txt),
             Message::Bin(bin) => ctx.binary(bin),
-            Message::StateWithDiff => {
-                let game = self.data.game.lock().unwrap();
-                match game.serialize_with_diffs(&self.chunks_digest) {
-                    Ok(bytes) => ctx.binary(bytes),
-                    Err(e) => ctx.text(format!("Error: {e}")),
-                }
-            }
+            Message::StateWithDiff => self.send_state(ctx),
         };
         self.last_updated = Instant::now();
     }
@@ -125,54 +173,24 @@ pub(crate) struct ChatHistoryRequest(pub SessionId);
 
 type WsResult = Result<ws::Message, ws::ProtocolError>;
 
+/// A message from the client is either a command to the game or a control of the session.
 #[derive(Deserialize)]
-#[serde(tag = "type", content = "payload")]
+#[serde(untagged)]
 enum WsMessage {
-    Excavate {
-        x: i32,
-        y: i32,
-    },
-    Move {
-        from: Pos,
-        to: Pos,
-    },
-    MoveItem {
-        from: Pos,
-        to: Pos,
-        item: ItemType,
-    },
-    Build {
-        pos: Pos,
-        #[serde(rename = "type")]
-        ty: ConstructionType,
-    },
-    BuildPlan {
-        build_plan: Vec<Construction>,
-    },
-    CancelBuild {
-        pos: Pos,
-    },
-    Deconstruct {
-        pos: Pos,
-    },
-    DeconstructConveyor {
-        pos: Pos,
-    },
-    DeconstructPowerGrid {
-        pos: Pos,
-    },
-    SetRecipe {
-        pos: Pos,
-        name: Option<String>,
-    },
+    Command(Command),
+    Control(ControlMessage),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "payload")]
+enum ControlMessage {
     ChunksDigest {
         // The payload represents HashMap<Position, u64>, but we do not deserialize into JSON for
         // performance reasons.
         chunks_digest: String,
     },
-    Cleanup {
-        pos: Pos,
-    },
+    /// Request the whole state, e.g. when the client detected a desync in lockstep mode.
+    Resync,
 }
 
 impl StreamHandler<WsResult> for SessionWs {
@@ -180,14 +198,17 @@ impl StreamHandler<WsResult> for SessionWs {
         match msg {
             Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
             Ok(ws::Message::Text(text)) => {
-                println!("client received ws text: {text}");
+                println!(
+                    "client {} received ws text: {text}",
+                    self.session.player_name()
+                );
                 let payload: WsMessage = if let Ok(payload) = serde_json::from_str(&text) {
                     payload
                 } else {
                     return ctx.text("{\"type\": \"response\", \"payload\": \"fail\"}");
                 };
 
-                if let Err(e) = self.handle_message(payload) {
+                if let Err(e) = self.handle_message(payload, ctx) {
                     return ctx.text(&*format!(
                         "{{\"type\": \"response\", \"payload\": \"fail: {}\"}}",
                         e.to_string()
@@ -195,8 +216,9 @@ impl StreamHandler<WsResult> for SessionWs {
                 }
             }
             Ok(ws::Message::Binary(bin)) => {
-                if let Ok(chunks_digest) = bincode::deserialize(&bin) {
-                    self.chunks_digest = chunks_digest;
+                if let Ok(ack) = bincode::deserialize::<SyncAck>(&bin) {
+                    self.chunks_digest = ack.chunks_digest;
+                    self.ack_entities(ack.frame);
                 }
             }
             Ok(ws::Message::Close(_op)) => {
@@ -211,67 +233,85 @@ impl StreamHandler<WsResult> for SessionWs {
     }
 }
 
-fn map_err(s: &str) -> anyhow::Error {
-    anyhow::anyhow!("{s}")
-}
-
 impl SessionWs {
-    fn handle_message(&mut self, payload: WsMessage) -> anyhow::Result<()> {
-        let mut game = self.data.game.lock().unwrap();
-
-        match payload {
-            WsMessage::Excavate { x, y } => {
-                game.excavate(x, y).map_err(|e| anyhow::anyhow!("{e}"))?;
-            }
-            WsMessage::Move { from, to } => {
-                game.move_building(from, to)
-                    .map_err(|e| anyhow::anyhow!("{e}"))?;
-            }
-            WsMessage::MoveItem { from, to, item } => {
-                game.move_item(from, to, item)
-                    .map_err(|e| anyhow::anyhow!("{e}"))?;
-            }
-            WsMessage::Build { pos, ty } => match ty {
-                ConstructionType::Building(ty) => {
-                    game.build(pos[0], pos[1], ty)
-                        .map_err(|e| anyhow::anyhow!("{e}"))?;
-                }
-                ConstructionType::PowerGrid => {
-                    game.build_power_grid(pos[0], pos[1])
-                        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    /// Make the acknowledged frame the base of the next diffs. If the frame is unknown, e.g.
+    /// when the client failed to apply an update, we fall back to sending all the entities.
+    fn ack_entities(&mut self, frame: Option<u64>) {
+        self.acked_entities = frame.and_then(|frame| {
+            if let Some((acked, _)) = &self.acked_entities {
+                if *acked == frame {
+                    return self.acked_entities.take();
                 }
-                _ => return Err(anyhow::anyhow!("Invalid build type")),
-            },
-            WsMessage::BuildPlan { build_plan } => {
-                game.build_plan(&build_plan);
             }
-            WsMessage::CancelBuild { pos } => {
-                game.cancel_build(pos[0], pos[1]);
-            }
-            WsMessage::Deconstruct { pos } => {
-                game.deconstruct(pos[0], pos[1])
-                    .map_err(|e| anyhow::anyhow!("{e}"))?;
-            }
-            WsMessage::DeconstructConveyor { pos } => {
-                game.deconstruct_conveyor(pos[0], pos[1]).map_err(map_err)?;
-            }
-            WsMessage::DeconstructPowerGrid { pos } => {
-                game.deconstruct_power_grid(pos[0], pos[1])
-                    .map_err(map_err)?;
-            }
-            WsMessage::SetRecipe { pos, name } => {
-                game.set_recipe(pos[0], pos[1], name.as_ref().map(|s| s as &_))
-                    .map_err(|e| anyhow::anyhow!("{e}"))?;
+            let idx = self.sent_entities.iter().position(|(f, _)| *f == frame)?;
+            self.sent_entities.drain(..idx);
+            self.sent_entities.pop_front()
+        });
+    }
+
+    /// Send the state with the tile chunks and the entities that changed since the last
+    /// acknowledged update.
+    fn send_state(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        let colony = self.colony.clone();
+        let game = colony.game.lock().unwrap();
+        let digest = colony.entities_digest(&game);
+        let frame = self.frame + 1;
+        let base = self
+            .acked_entities
+            .as_ref()
+            .map(|(frame, digest)| (*frame, &**digest));
+        match game.serialize_with_diffs(&self.chunks_digest, frame, base, &digest) {
+            Ok(bytes) => {
+                self.frame = frame;
+                self.sent_entities.push_back((frame, digest));
+                if MAX_UNACKED_FRAMES < self.sent_entities.len() {
+                    self.sent_entities.pop_front();
+                }
+                ctx.binary(bytes)
             }
-            WsMessage::ChunksDigest { chunks_digest } => {
+            Err(e) => ctx.text(format!("Error: {e}")),
+        }
+    }
+
+    fn handle_message(
+        &mut self,
+        payload: WsMessage,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> anyhow::Result<()> {
+        let command = match payload {
+            WsMessage::Command(command) => command,
+            WsMessage::Control(ControlMessage::ChunksDigest { chunks_digest }) => {
                 self.chunks_digest = serde_json::from_str(&chunks_digest)?;
                 return Ok(());
             }
-            WsMessage::Cleanup { pos } => {
-                game.cleanup_item(pos).map_err(|e| anyhow::anyhow!("{e}"))?;
+            WsMessage::Control(ControlMessage::Resync) => {
+                self.acked_entities = None;
+                self.sent_entities.clear();
+                self.send_state(ctx);
+                return Ok(());
             }
+        };
+
+        if self.colony.is_archived() {
+            return Err(anyhow::anyhow!("The colony is archived"));
+        }
+        let colony = self.colony.clone();
+        let mut game = colony.game.lock().unwrap();
+        check_permission(
+            &self.data.accounts.read().unwrap(),
+            &self.session,
+            &game,
+            &command,
+        )?;
+        let owner = self.session.player_id();
+        if self.data.config.lockstep {
+            colony.queue_command(PlayerCommand { owner, command });
+            return Ok(());
         }
-        self.data.set_signal_push(true);
+        game.apply_command(&command, owner)
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        colony.mark_modified();
+        self.colony.set_signal_push(true);
 
         // self.addr.do_send(NotifyBodyState {
         //     session_id: Some(self.session_id),
@@ -281,3 +321,65 @@ impl SessionWs {
         Ok(())
     }
 }
+
+/// Check if the player of the session is allowed to perform the action.
+/// Buildings and their construction plans are protected by their owners, while the tiles,
+/// power grids, conveyors and research are shared by everyone in the colony.
+fn check_permission(
+    accounts: &Accounts,
+    session: &Session,
+    game: &Game,
+    command: &Command,
+) -> anyhow::Result<()> {
+    let player = session.player_id();
+    let check_building = |pos: Pos| {
+        if accounts.can_modify(player, game.building_owner(pos)) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "The building at {pos:?} belongs to another player"
+            ))
+        }
+    };
+    match command {
+        Command::Move { from, .. } => check_building(*from),
+        Command::MoveItem { from, to, .. } => {
+            check_building(*from)?;
+            check_building(*to)
+        }
+        Command::CancelBuild { pos }
+        | Command::SetConstructionPriority { pos, .. }
+        | Command::PauseConstruction { pos, .. } => {
+            if accounts.can_modify(player, game.construction_owner(*pos)) {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!(
+                    "The construction at {pos:?} belongs to another player"
+                ))
+            }
+        }
+        Command::Deconstruct { pos }
+        | Command::SetRecipe { pos, .. }
+        | Command::SetJobPriority { pos, .. }
+        | Command::SetCrewJobPriority { pos, .. }
+        | Command::SetOutputFilter { pos, .. }
+        | Command::SetLogistics { pos, .. } => check_building(*pos),
+        Command::Excavate { .. }
+        | Command::Build { .. }
+        | Command::BuildPlan { .. }
+        | Command::DeconstructConveyor { .. }
+        | Command::DeconstructPowerGrid { .. }
+        | Command::SetSplitterFilter { .. }
+        | Command::Cleanup { .. }
+        | Command::SetResearch { .. } => Ok(()),
+        // Players can only revert their own commands, which were checked when issued. Guests
+        // would share one history, so they cannot revert each other's commands.
+        Command::Undo | Command::Redo => {
+            if player.is_some() {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("Log in to undo or redo commands"))
+            }
+        }
+    }
+}
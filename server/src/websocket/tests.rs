@@ -0,0 +1,45 @@
+use std::path::Path;
+
+use super::*;
+use crate::session::Player;
+use asteroid_colonies_logic::{building::BuildingType, construction::ConstructionType, PlayerId};
+
+#[test]
+fn test_guest_undo() {
+    let accounts = Accounts::load(Path::new("nonexistent-accounts.json")).unwrap();
+    let mut game = Game::new(None).unwrap();
+    let guest = Session::default();
+    let other_guest = Session::default();
+
+    // Both guests plan storages, which have no owner and go to the same history
+    for (session, pos) in [(&guest, [27, 52]), (&other_guest, [28, 53])] {
+        let command = Command::Build {
+            pos,
+            ty: ConstructionType::Building(BuildingType::Storage),
+        };
+        check_permission(&accounts, session, &game, &command).unwrap();
+        game.apply_command(&command, session.player_id()).unwrap();
+    }
+    assert!(game.history().can_undo(None));
+
+    // Neither guest can revert the command of the other
+    for session in [&guest, &other_guest] {
+        for command in [Command::Undo, Command::Redo] {
+            assert_eq!(
+                check_permission(&accounts, session, &game, &command)
+                    .unwrap_err()
+                    .to_string(),
+                "Log in to undo or redo commands"
+            );
+        }
+    }
+
+    let player = Session {
+        player: Some(Player {
+            name: "alice".to_string(),
+            id: PlayerId(1),
+            admin: false,
+        }),
+    };
+    assert!(check_permission(&accounts, &player, &game, &Command::Undo).is_ok());
+}
@@ -0,0 +1,149 @@
+//! Headless simulation of a colony without the server or the browser, to compare the balance
+//! of rulesets and to catch regressions in CI.
+mod script;
+mod stats;
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+use ::anyhow::{anyhow, Context, Result};
+use ::asteroid_colonies_logic::{load_ruleset, AsteroidColoniesGame, Ruleset, StatsWindow};
+use ::clap::{Parser, ValueEnum};
+
+use crate::{
+    script::load_script,
+    stats::{write_csv, Sample},
+};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Format {
+    Json,
+    Csv,
+}
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+struct Args {
+    #[clap(
+        long,
+        help = "A save file to start from. A new game is created if omitted"
+    )]
+    load: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "A JSON file of the ruleset. The default ruleset is used if omitted"
+    )]
+    ruleset: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "A file of commands to apply at given ticks, with a JSON object per line"
+    )]
+    script: Option<PathBuf>,
+    #[clap(
+        short = 'n',
+        long,
+        default_value = "1000",
+        help = "The number of ticks to simulate"
+    )]
+    ticks: usize,
+    #[clap(
+        long,
+        default_value = "100",
+        help = "Interval in ticks to record statistics"
+    )]
+    sample_period: usize,
+    #[clap(short, long, value_enum, default_value = "json")]
+    format: Format,
+    #[clap(
+        short,
+        long,
+        help = "The file to write the statistics to. The standard output is used if omitted"
+    )]
+    output: Option<PathBuf>,
+    #[clap(long, help = "Save the game after the simulation")]
+    save: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    if let Some(ruleset_file) = &args.ruleset {
+        let src = fs::read_to_string(ruleset_file)
+            .with_context(|| format!("Failed to read {ruleset_file:?}"))?;
+        let ruleset = Ruleset::from_json(&src)
+            .map_err(|e| anyhow!("Error in ruleset {ruleset_file:?}: {e}"))?;
+        load_ruleset(ruleset).map_err(|e| anyhow!(e))?;
+    }
+
+    let mut game = AsteroidColoniesGame::new(None).map_err(|e| anyhow!(e))?;
+    if let Some(load) = &args.load {
+        let file = fs::File::open(load).with_context(|| format!("Failed to open {load:?}"))?;
+        game.deserialize(io::BufReader::new(file))
+            .map_err(|e| anyhow!("Failed to load {load:?}: {e}"))?;
+    }
+
+    let script = match &args.script {
+        Some(script) => load_script(script)?,
+        None => vec![],
+    };
+    let mut script = script.into_iter().peekable();
+
+    let sample_period = args.sample_period.max(1);
+    let mut samples = vec![Sample::new(&game, None, 0)];
+    let mut deliveries = 0;
+    let start = Instant::now();
+    for i in 0..args.ticks {
+        while let Some(entry) = script.next_if(|entry| entry.tick <= i) {
+            if let Err(e) = game.apply_command(&entry.command, entry.owner) {
+                eprintln!("Command {:?} at tick {i} failed: {e}", entry.command);
+            }
+        }
+        game.tick().map_err(|e| anyhow!("Tick error at {i}: {e}"))?;
+        deliveries += game
+            .production_stats()
+            .window(StatsWindow::Minute)
+            .back()
+            .map_or(0, |bucket| bucket.deliveries);
+        if (i + 1) % sample_period == 0 || i + 1 == args.ticks {
+            samples.push(Sample::new(&game, samples.last(), deliveries));
+            deliveries = 0;
+        }
+    }
+    eprintln!(
+        "Simulated {} ticks in {:.3}s",
+        args.ticks,
+        start.elapsed().as_secs_f64()
+    );
+    if let Some(entry) = script.peek() {
+        eprintln!(
+            "Commands from tick {} were not applied, since the simulation ended",
+            entry.tick
+        );
+    }
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(output) => Box::new(io::BufWriter::new(
+            fs::File::create(output).with_context(|| format!("Failed to create {output:?}"))?,
+        )),
+        None => Box::new(io::stdout().lock()),
+    };
+    match args.format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, &samples)?;
+            writeln!(out)?;
+        }
+        Format::Csv => write_csv(&samples, &mut out)?,
+    }
+    out.flush()?;
+
+    if let Some(save) = &args.save {
+        game.uniformify_tiles();
+        let serialized = game.serialize(false)?;
+        fs::write(save, serialized).with_context(|| format!("Failed to write {save:?}"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use std::{fs, path::Path};
+
+use ::anyhow::{anyhow, Context, Result};
+use ::asteroid_colonies_logic::{Command, PlayerId};
+use ::serde::Deserialize;
+
+/// A command in a script, applied before the given tick.
+#[derive(Deserialize, Debug)]
+pub(crate) struct ScriptEntry {
+    /// Ticks from the start of the simulation, not the global time of the game.
+    pub tick: usize,
+    #[serde(default)]
+    pub owner: Option<PlayerId>,
+    pub command: Command,
+}
+
+/// Load a script with a JSON object per line. Empty lines and lines starting with `#` are
+/// skipped. The entries are sorted by the tick, keeping the order in the file within a tick.
+pub(crate) fn load_script(path: &Path) -> Result<Vec<ScriptEntry>> {
+    let src = fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+    let mut entries = src
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+        .map(|(i, line)| {
+            serde_json::from_str::<ScriptEntry>(line)
+                .map_err(|e| anyhow!("{path:?}:{}: {e}", i + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.tick);
+    Ok(entries)
+}
@@ -0,0 +1,129 @@
+use std::{collections::BTreeMap, io::Write};
+
+use ::asteroid_colonies_logic::{ruleset, AsteroidColoniesGame, ItemType, TransportPayload};
+use ::serde::Serialize;
+
+/// Statistics of the colony at a tick.
+#[derive(Serialize, Debug)]
+pub(crate) struct Sample {
+    /// The global time of the game
+    pub tick: usize,
+    pub buildings: usize,
+    pub constructions: usize,
+    pub crews: usize,
+    pub global_tasks: usize,
+    pub transports: usize,
+    /// Transports delivered since the previous sample, which tells the throughput of conveyors
+    pub deliveries: usize,
+    /// Generated power summed over all power networks, in kW
+    pub power_gen: isize,
+    /// Power demand summed over all power networks, in kW
+    pub power_demand: isize,
+    /// Power used in the last tick, in kW
+    pub used_power: f64,
+    /// Energy stored in batteries
+    pub energy: usize,
+    /// Items in the inventories of buildings
+    pub inventory: BTreeMap<ItemType, usize>,
+    /// Items carried by transports
+    pub in_transit: BTreeMap<ItemType, usize>,
+    /// Net change of the items in inventories and transports since the previous sample.
+    /// Items consumed by recipes and constructions count as negative.
+    pub production: BTreeMap<ItemType, isize>,
+}
+
+impl Sample {
+    pub fn new(game: &AsteroidColoniesGame, prev: Option<&Sample>, deliveries: usize) -> Self {
+        let networks = game.power_networks();
+        let mut inventory = BTreeMap::new();
+        for building in game.iter_building() {
+            for (item, count) in building.inventory.iter() {
+                *inventory.entry(*item).or_default() += *count;
+            }
+        }
+        let mut in_transit = BTreeMap::new();
+        for transport in game.iter_transport() {
+            if let TransportPayload::Item(item, count) = transport.payload {
+                *in_transit.entry(item).or_default() += count;
+            }
+        }
+        let production = prev.map_or_else(BTreeMap::new, |prev| {
+            ruleset()
+                .items
+                .iter()
+                .map(|item| (*item, prev.total(item)))
+                .map(|(item, prev_total)| {
+                    let total = inventory.get(&item).copied().unwrap_or(0)
+                        + in_transit.get(&item).copied().unwrap_or(0);
+                    (item, total as isize - prev_total as isize)
+                })
+                .filter(|(_, delta)| *delta != 0)
+                .collect()
+        });
+        Self {
+            tick: game.get_global_time(),
+            buildings: game.iter_building().count(),
+            constructions: game.iter_construction().count(),
+            crews: game.iter_crew().count(),
+            global_tasks: game.iter_global_task().count(),
+            transports: game.num_transports(),
+            deliveries,
+            power_gen: networks.iter().map(|(_, n)| n.power_gen).sum(),
+            power_demand: networks.iter().map(|(_, n)| n.power_demand).sum(),
+            used_power: game.get_used_power(),
+            energy: networks.iter().map(|(_, n)| n.energy).sum(),
+            inventory,
+            in_transit,
+            production,
+        }
+    }
+
+    fn total(&self, item: &ItemType) -> usize {
+        self.inventory.get(item).copied().unwrap_or(0)
+            + self.in_transit.get(item).copied().unwrap_or(0)
+    }
+}
+
+/// Write the samples as CSV, with a column per item in the ruleset for each item statistic,
+/// so that the columns do not change between runs.
+pub(crate) fn write_csv(samples: &[Sample], mut out: impl Write) -> std::io::Result<()> {
+    let items = &ruleset().items;
+    write!(
+        out,
+        "tick,buildings,constructions,crews,global_tasks,transports,deliveries,power_gen,power_demand,used_power,energy"
+    )?;
+    for prefix in ["inventory", "in_transit", "production"] {
+        for item in items {
+            write!(out, ",{prefix}.{item:?}")?;
+        }
+    }
+    writeln!(out)?;
+    for sample in samples {
+        write!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            sample.tick,
+            sample.buildings,
+            sample.constructions,
+            sample.crews,
+            sample.global_tasks,
+            sample.transports,
+            sample.deliveries,
+            sample.power_gen,
+            sample.power_demand,
+            sample.used_power,
+            sample.energy
+        )?;
+        for item in items {
+            write!(out, ",{}", sample.inventory.get(item).unwrap_or(&0))?;
+        }
+        for item in items {
+            write!(out, ",{}", sample.in_transit.get(item).unwrap_or(&0))?;
+        }
+        for item in items {
+            write!(out, ",{}", sample.production.get(item).unwrap_or(&0))?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
@@ -10,14 +10,18 @@ pub(crate) struct Assets {
     pub img_crew: HtmlImageElement,
     pub img_power_grid: HtmlImageElement,
     pub img_conveyor: HtmlImageElement,
+    pub img_underground: HtmlImageElement,
     pub img_atomic_battery: HtmlImageElement,
     pub img_battery: HtmlImageElement,
     pub img_excavator: HtmlImageElement,
     pub img_storage: HtmlImageElement,
     pub img_medium_storage: HtmlImageElement,
     pub img_crew_cabin: HtmlImageElement,
+    pub img_large_crew_cabin: HtmlImageElement,
     pub img_assembler: HtmlImageElement,
     pub img_furnace: HtmlImageElement,
+    pub img_oxygen_generator: HtmlImageElement,
+    pub img_greenhouse: HtmlImageElement,
     pub img_raw_ore: HtmlImageElement,
     pub img_iron_ingot: HtmlImageElement,
     pub img_copper_ingot: HtmlImageElement,
@@ -29,6 +33,8 @@ pub(crate) struct Assets {
     pub img_battery_item: HtmlImageElement,
     pub img_conveyor_item: HtmlImageElement,
     pub img_assembler_component: HtmlImageElement,
+    pub img_oxygen: HtmlImageElement,
+    pub img_food: HtmlImageElement,
     pub img_construction: HtmlImageElement,
     pub img_deconstruction: HtmlImageElement,
     pub img_cleanup: HtmlImageElement,
@@ -64,14 +70,18 @@ impl Assets {
             img_crew: load_texture("crew")?,
             img_power_grid: load_texture("power_grid")?,
             img_conveyor: load_texture("conveyor")?,
+            img_underground: load_texture("underground")?,
             img_atomic_battery: load_texture("atomic_battery")?,
             img_battery: load_texture("battery")?,
             img_excavator: load_texture("excavator")?,
             img_storage: load_texture("storage")?,
             img_medium_storage: load_texture("medium_storage")?,
             img_crew_cabin: load_texture("crew_cabin")?,
+            img_large_crew_cabin: load_texture("large_crew_cabin")?,
             img_assembler: load_texture("assembler")?,
             img_furnace: load_texture("furnace")?,
+            img_oxygen_generator: load_texture("oxygen_generator")?,
+            img_greenhouse: load_texture("greenhouse")?,
             img_raw_ore: load_texture("raw_ore")?,
             img_iron_ingot: load_texture("iron_ingot")?,
             img_copper_ingot: load_texture("copper_ingot")?,
@@ -83,6 +93,8 @@ impl Assets {
             img_battery_item: load_texture("battery_item")?,
             img_conveyor_item: load_texture("conveyor_item")?,
             img_assembler_component: load_texture("assembler_component")?,
+            img_oxygen: load_texture("oxygen")?,
+            img_food: load_texture("food")?,
             img_construction: load_texture("construction")?,
             img_deconstruction: load_texture("deconstruction")?,
             img_cleanup: load_texture("cleanup")?,
@@ -97,8 +109,12 @@ impl Assets {
             BuildingType::Storage => &self.img_storage,
             BuildingType::MediumStorage => &self.img_medium_storage,
             BuildingType::CrewCabin => &self.img_crew_cabin,
-            BuildingType::Assembler => &self.img_assembler,
+            BuildingType::LargeCrewCabin => &self.img_large_crew_cabin,
+            // TODO: Laboratory deserves its own graphics
+            BuildingType::Assembler | BuildingType::Laboratory => &self.img_assembler,
             BuildingType::Furnace => &self.img_furnace,
+            BuildingType::OxygenGenerator => &self.img_oxygen_generator,
+            BuildingType::Greenhouse => &self.img_greenhouse,
             _ => panic!("Uncovered building type!"),
         }
     }
@@ -0,0 +1,45 @@
+use asteroid_colonies_logic::{Blueprint, Command};
+use wasm_bindgen::prelude::*;
+
+use crate::AsteroidColonies;
+
+#[wasm_bindgen]
+impl AsteroidColonies {
+    /// Copy the area between two corner tiles into a blueprint string.
+    pub fn copy_blueprint(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> Result<String, JsValue> {
+        let pos = [x0.min(x1), y0.min(y1)];
+        let size = [x0.abs_diff(x1) as usize + 1, y0.abs_diff(y1) as usize + 1];
+        Ok(self.game.copy_blueprint(pos, size)?.to_share_string()?)
+    }
+
+    pub fn rotate_blueprint(&self, blueprint: &str) -> Result<String, JsValue> {
+        Ok(Blueprint::from_share_string(blueprint)?
+            .rotate()?
+            .to_share_string()?)
+    }
+
+    pub fn mirror_blueprint(&self, blueprint: &str) -> Result<String, JsValue> {
+        Ok(Blueprint::from_share_string(blueprint)?
+            .mirror()
+            .to_share_string()?)
+    }
+
+    /// Place a blueprint with its top-left corner at a tile and return the build plan to send
+    /// to the server.
+    pub fn place_blueprint(
+        &mut self,
+        blueprint: &str,
+        x: i32,
+        y: i32,
+    ) -> Result<Vec<JsValue>, JsValue> {
+        let blueprint = Blueprint::from_share_string(blueprint)?;
+        let plan = self.game.blueprint_plan(&blueprint, [x, y])?;
+        self.apply_command_local(Command::BuildPlan {
+            build_plan: plan.clone(),
+        })?;
+        plan.iter()
+            .map(serde_wasm_bindgen::to_value)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(JsValue::from)
+    }
+}
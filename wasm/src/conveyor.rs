@@ -1,3 +1,4 @@
+use asteroid_colonies_logic::{Command, SplitterFilter};
 use wasm_bindgen::prelude::*;
 
 use crate::AsteroidColonies;
@@ -12,9 +13,10 @@ impl AsteroidColonies {
         x1: i32,
         y1: i32,
         preview: bool,
+        underground: bool,
     ) -> Result<(), JsValue> {
         self.game
-            .preview_build_conveyor(x0, y0, x1, y1, preview)
+            .preview_build_conveyor(x0, y0, x1, y1, preview, underground)
             .map_err(JsValue::from)
     }
 
@@ -26,14 +28,30 @@ impl AsteroidColonies {
         self.game.build_merger(x, y);
     }
 
+    /// Set the item filter of a splitter, which is `{dir, items}` or `null` to clear it.
+    pub fn set_splitter_filter(&mut self, x: i32, y: i32, filter: JsValue) -> Result<(), JsValue> {
+        let filter: Option<SplitterFilter> = serde_wasm_bindgen::from_value(filter)?;
+        self.apply_command_local(Command::SetSplitterFilter {
+            pos: [x, y],
+            filter,
+        })
+    }
+
+    /// Set the tier of the conveyors to build, 0 being the basic one.
+    pub fn set_conveyor_tier(&mut self, tier: u8) -> Result<(), JsValue> {
+        self.game.set_conveyor_tier(tier).map_err(JsValue::from)
+    }
+
     pub fn cancel_build_conveyor(&mut self, preview: bool) {
         self.game.cancel_build_conveyor(preview);
     }
 
     pub fn commit_build_conveyor(&mut self) -> Result<Vec<JsValue>, JsValue> {
-        self.game
-            .commit_build_conveyor()
-            .iter()
+        let plan = self.game.take_build_conveyor();
+        self.apply_command_local(Command::BuildPlan {
+            build_plan: plan.clone(),
+        })?;
+        plan.iter()
             .map(serde_wasm_bindgen::to_value)
             .collect::<Result<Vec<_>, _>>()
             .map_err(JsValue::from)
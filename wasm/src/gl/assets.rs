@@ -68,14 +68,18 @@ pub(crate) struct Assets {
     pub tex_crew: WebGlTexture,
     pub tex_power_grid: WebGlTexture,
     pub tex_conveyor: WebGlTexture,
+    pub tex_underground: WebGlTexture,
     pub tex_atomic_battery: WebGlTexture,
     pub tex_battery: WebGlTexture,
     pub tex_storage: WebGlTexture,
     pub tex_medium_storage: WebGlTexture,
     pub tex_crew_cabin: WebGlTexture,
+    pub tex_large_crew_cabin: WebGlTexture,
     pub tex_excavator: WebGlTexture,
     pub tex_assembler: WebGlTexture,
     pub tex_furnace: WebGlTexture,
+    pub tex_oxygen_generator: WebGlTexture,
+    pub tex_greenhouse: WebGlTexture,
     pub tex_raw_ore: WebGlTexture,
     pub tex_iron_ingot: WebGlTexture,
     pub tex_copper_ingot: WebGlTexture,
@@ -87,6 +91,8 @@ pub(crate) struct Assets {
     pub tex_battery_item: WebGlTexture,
     pub tex_conveyor_item: WebGlTexture,
     pub tex_assembler_component: WebGlTexture,
+    pub tex_oxygen: WebGlTexture,
+    pub tex_food: WebGlTexture,
     pub tex_construction: WebGlTexture,
     pub tex_deconstruction: WebGlTexture,
     pub tex_cleanup: WebGlTexture,
@@ -201,14 +207,18 @@ impl Assets {
             tex_crew: load_texture_local("crew")?,
             tex_power_grid: load_texture_local("power_grid")?,
             tex_conveyor: load_texture_local("conveyor")?,
+            tex_underground: load_texture_local("underground")?,
             tex_atomic_battery: load_texture_local("atomic_battery")?,
             tex_battery: load_texture_local("battery")?,
             tex_storage: load_texture_local("storage")?,
             tex_medium_storage: load_texture_local("medium_storage")?,
             tex_crew_cabin: load_texture_local("crew_cabin")?,
+            tex_large_crew_cabin: load_texture_local("large_crew_cabin")?,
             tex_excavator: load_texture_local("excavator")?,
             tex_assembler: load_texture_local("assembler")?,
             tex_furnace: load_texture_local("furnace")?,
+            tex_oxygen_generator: load_texture_local("oxygen_generator")?,
+            tex_greenhouse: load_texture_local("greenhouse")?,
             tex_raw_ore: load_texture_local("raw_ore")?,
             tex_iron_ingot: load_texture_local("iron_ingot")?,
             tex_copper_ingot: load_texture_local("copper_ingot")?,
@@ -220,6 +230,8 @@ impl Assets {
             tex_battery_item: load_texture_local("battery_item")?,
             tex_conveyor_item: load_texture_local("conveyor_item")?,
             tex_assembler_component: load_texture_local("assembler_component")?,
+            tex_oxygen: load_texture_local("oxygen")?,
+            tex_food: load_texture_local("food")?,
             tex_construction: load_texture_local("construction")?,
             tex_deconstruction: load_texture_local("deconstruction")?,
             tex_cleanup: load_texture_local("cleanup")?,
@@ -252,8 +264,12 @@ impl Assets {
             BuildingType::Storage => &self.tex_storage,
             BuildingType::MediumStorage => &self.tex_medium_storage,
             BuildingType::CrewCabin => &self.tex_crew_cabin,
-            BuildingType::Assembler => &self.tex_assembler,
+            BuildingType::LargeCrewCabin => &self.tex_large_crew_cabin,
+            // TODO: Laboratory deserves its own graphics
+            BuildingType::Assembler | BuildingType::Laboratory => &self.tex_assembler,
             BuildingType::Furnace => &self.tex_furnace,
+            BuildingType::OxygenGenerator => &self.tex_oxygen_generator,
+            BuildingType::Greenhouse => &self.tex_greenhouse,
             _ => panic!("Uncovered building type!"),
         })
     }
@@ -272,6 +288,8 @@ impl Assets {
             ItemType::PowerGridComponent => &self.tex_power_grid,
             ItemType::ConveyorComponent => &self.tex_conveyor_item,
             ItemType::AssemblerComponent => &self.tex_assembler_component,
+            ItemType::Oxygen => &self.tex_oxygen,
+            ItemType::Food => &self.tex_food,
         }
     }
 }
@@ -5,6 +5,7 @@ mod conveyor;
 mod crews;
 mod cursor;
 mod global_tasks;
+mod item_piles;
 mod path;
 mod power_grid;
 mod transports;
@@ -43,6 +44,7 @@ impl AsteroidColonies {
         self.render_gl_global_tasks(gl, &ctx);
         self.render_gl_constructions(gl, &ctx);
         self.render_gl_conveyor_plan(gl, &ctx);
+        self.render_gl_item_piles(gl, &ctx);
         self.render_gl_transports(gl, &ctx);
 
         if let Some(cursor) = self.move_cursor {
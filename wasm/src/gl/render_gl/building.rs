@@ -131,7 +131,8 @@ impl AsteroidColonies {
 
             let task_target = match building.task {
                 BuildingTask::Move(t, _) => Some((t, MOVE_TIME)),
-                BuildingTask::Assemble { t, max_t, .. } => Some((t, max_t)),
+                BuildingTask::Assemble { t, max_t, .. }
+                | BuildingTask::Research { t, max_t, .. } => Some((t, max_t)),
                 _ => None,
             };
 
@@ -239,7 +240,7 @@ pub(super) fn render_gl_building_texture(
             gl.bind_texture(GL::TEXTURE_2D, Some(&assets.tex_excavator));
             set_texture_transform(sx, 0., 1. / 3., 1.);
         }
-        BuildingType::Assembler => {
+        BuildingType::Assembler | BuildingType::Laboratory => {
             let sx = if !matches!(building.get_task(), BuildingTask::None) {
                 ((time % 2.).floor() + 1.) as f32
             } else {
@@ -93,6 +93,29 @@ impl AsteroidColonies {
                 set_texture_transform(sx, sy);
                 render_tile(x, y);
             }
+            Conveyor::UndergroundIn(dir) | Conveyor::UndergroundOut(dir) => {
+                let sx = match dir {
+                    Direction::Left => 0.,
+                    Direction::Up => 1.,
+                    Direction::Right => 2.,
+                    Direction::Down => 3.,
+                };
+                let sy = match conv {
+                    Conveyor::UndergroundIn(_) => 0.,
+                    _ => 1.,
+                };
+                let tex_transform = Matrix3::<f32>::from_nonuniform_scale(1. / 4., 1. / 2.)
+                    * Matrix3::from_translation(Vector2::new(sx, sy));
+                gl.uniform_matrix3fv_with_f32_array(
+                    assets.textured_shader.tex_transform_loc.as_ref(),
+                    false,
+                    tex_transform.flatten(),
+                );
+                gl.bind_texture(GL::TEXTURE_2D, Some(&assets.tex_underground));
+                render_tile(x, y);
+                // Callers draw the other conveyors with the same texture
+                gl.bind_texture(GL::TEXTURE_2D, Some(&assets.tex_conveyor));
+            }
             _ => {}
         };
     }
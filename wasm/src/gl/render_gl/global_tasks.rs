@@ -15,11 +15,11 @@ impl AsteroidColonies {
 
         for task in self.game.iter_global_task() {
             match &*task {
-                GlobalTask::Excavate(t, pos) => {
+                GlobalTask::Excavate(t, pos, _) => {
                     render_icon(gl, ctx, *pos, &assets.tex_excavate);
                     render_global_task_bar(gl, ctx, *pos, 1., *t, LABOR_EXCAVATE_TIME);
                 }
-                GlobalTask::Cleanup(pos) => {
+                GlobalTask::Cleanup(pos, _) => {
                     render_icon(gl, ctx, *pos, &assets.tex_cleanup);
                 }
             }
@@ -0,0 +1,55 @@
+use super::{super::utils::Flatten, RenderContext};
+use crate::{gl::utils::enable_buffer, AsteroidColonies};
+
+use ::asteroid_colonies_logic::{ItemType, TILE_SIZE};
+use cgmath::{Matrix3, Matrix4, SquareMatrix, Vector3};
+
+use web_sys::WebGlRenderingContext as GL;
+
+impl AsteroidColonies {
+    pub(super) fn render_gl_item_piles(&self, gl: &GL, ctx: &RenderContext) {
+        let RenderContext {
+            assets,
+            to_screen,
+            offset,
+            scale,
+            ..
+        } = ctx;
+
+        let shader = &assets.textured_shader;
+
+        gl.use_program(Some(&shader.program));
+        gl.uniform1f(shader.alpha_loc.as_ref(), 1.0);
+        gl.uniform_matrix3fv_with_f32_array(
+            shader.tex_transform_loc.as_ref(),
+            false,
+            Matrix3::identity().flatten(),
+        );
+        enable_buffer(gl, &assets.screen_buffer, 2, shader.vertex_position);
+
+        for pile in self.game.iter_item_pile() {
+            // Ores are shown as raw ores if there are no other items
+            let item = pile
+                .inventory
+                .keys()
+                .next()
+                .copied()
+                .unwrap_or(ItemType::RawOre);
+            gl.bind_texture(GL::TEXTURE_2D, Some(assets.item_to_tex(item)));
+            let x = (pile.pos[0] as f64 + offset[0] as f64 / TILE_SIZE) as f32;
+            let y = (pile.pos[1] as f64 + offset[1] as f64 / TILE_SIZE) as f32;
+            let transform = to_screen
+                * scale
+                * Matrix4::from_translation(Vector3::new(x, y, 0.))
+                * Matrix4::from_translation(Vector3::new(0.5, 0.5, 0.))
+                * Matrix4::from_scale(0.75)
+                * Matrix4::from_translation(Vector3::new(-0.5, -0.5, 0.));
+            gl.uniform_matrix4fv_with_f32_array(
+                shader.transform_loc.as_ref(),
+                false,
+                transform.flatten(),
+            );
+            gl.draw_arrays(GL::TRIANGLE_FAN, 0, 4);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use super::{super::utils::Flatten, lerp, path::render_path, RenderContext};
+use super::{super::utils::Flatten, path::render_path, RenderContext};
 use crate::{gl::utils::enable_buffer, AsteroidColonies};
 
 use ::asteroid_colonies_logic::{Transport, TILE_SIZE};
@@ -29,7 +29,7 @@ impl AsteroidColonies {
         );
 
         let render_transport = |t: &Transport| {
-            let Some(&pos) = t.path.last() else {
+            let Some([x, y]) = t.position(*frac_frame) else {
                 return;
             };
             let tex = match t.payload {
@@ -37,11 +37,6 @@ impl AsteroidColonies {
                 TransportPayload::Ores(_) => assets.item_to_tex(ItemType::RawOre),
             };
             gl.bind_texture(GL::TEXTURE_2D, Some(tex));
-            let [x, y] = if 2 <= t.path.len() {
-                lerp(pos, t.path[t.path.len() - 2], *frac_frame)
-            } else {
-                [pos[0] as f64, pos[1] as f64]
-            };
             let x = (x + offset[0] as f64 / TILE_SIZE) as f32;
             let y = (y + offset[1] as f64 / TILE_SIZE) as f32;
             let transform = to_screen
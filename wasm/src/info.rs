@@ -2,39 +2,102 @@ use crate::AsteroidColonies;
 use asteroid_colonies_logic::{
     building::{BuildingType, OreAccum, Recipe},
     construction::{BuildMenuItem, ConstructionType},
-    CountableInventory, Inventory, Pos, TileState,
+    ruleset, CabinSkills, Conveyor, CountableInventory, CrewNeeds, Direction, Inventory, ItemSet,
+    ItemType, Logistics, Pos, PowerNetworkId, SplitterFilter, TileState, SLOTS_PER_TILE,
+    TILE_LENGTH,
 };
 use serde::Serialize;
+use std::collections::BTreeMap;
 use wasm_bindgen::prelude::*;
 
 #[derive(Serialize)]
 struct GetBuildingInfoResult {
     type_: BuildingType,
+    pos: Pos,
     recipe: Option<Recipe>,
     task: String,
     inventory: Inventory,
     crews: usize,
+    population: usize,
     max_crews: usize,
     ores: Option<OreAccum>,
+    needs: Option<CrewNeeds>,
+    skills: Option<CabinSkills>,
+    output_filter: Option<ItemSet>,
+    /// Only storages can have logistics
+    logistics: Option<GetLogisticsInfoResult>,
+}
+
+#[derive(Serialize)]
+struct GetLogisticsInfoResult {
+    setting: Option<Logistics>,
+    /// All the items that can have target counts
+    items: Vec<ItemType>,
 }
 
 #[derive(Serialize)]
 struct GetConstructionInfoResult {
+    pos: Pos,
     type_: ConstructionType,
     recipe: BuildMenuItem,
     ingredients: CountableInventory,
+    priority: u8,
+    paused: bool,
+}
+
+#[derive(Serialize)]
+struct GetSplitterInfoResult {
+    pos: Pos,
+    /// The directions that the filter can send items to
+    outputs: Vec<Direction>,
+    filter: Option<SplitterFilter>,
+    /// All the items that can be filtered
+    items: Vec<ItemType>,
+}
+
+#[derive(Serialize)]
+struct GetConveyorInfoResult {
+    tier: u8,
+    name: String,
+    /// Items per tick that the conveyor can carry when they are packed
+    throughput: f64,
+}
+
+impl GetConveyorInfoResult {
+    fn new(tier: u8) -> Self {
+        let speed = ruleset().conveyor_speed(tier);
+        Self {
+            tier,
+            name: ruleset().conveyor_tier_name(tier).to_string(),
+            throughput: speed as f64 * SLOTS_PER_TILE as f64 / TILE_LENGTH as f64,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct GetConveyorTierResult {
+    #[serde(flatten)]
+    info: GetConveyorInfoResult,
+    ingredients: BTreeMap<ItemType, usize>,
+    time: f64,
 }
 
 #[derive(Serialize)]
 struct GetInfoResult {
     building: Option<GetBuildingInfoResult>,
     construction: Option<GetConstructionInfoResult>,
+    splitter: Option<GetSplitterInfoResult>,
+    conveyor: Option<GetConveyorInfoResult>,
     energy: usize,
     power_demand: isize,
     power_supply: isize,
     power_capacity: isize,
     transports: usize,
     ores: Option<OreAccum>,
+    /// The items dropped on the tile under the cursor
+    item_pile: Option<Inventory>,
+    /// The power network under the cursor. Power figures above are of this network if it exists.
+    power_network: Option<PowerNetworkId>,
 }
 
 #[wasm_bindgen]
@@ -43,7 +106,10 @@ impl AsteroidColonies {
         // let [ix, iy] = self.transform_pos(x, y);
         let mut building = None;
         let mut construction = None;
+        let mut splitter = None;
+        let mut conveyor = None;
         let mut ores = None;
+        let mut item_pile = None;
 
         if let Some([ix, iy]) = self.cursor {
             let intersects = |pos: Pos, size: [usize; 2]| {
@@ -60,15 +126,24 @@ impl AsteroidColonies {
                     let recipe = building.recipe.clone();
                     GetBuildingInfoResult {
                         type_: building.type_,
+                        pos: building.pos,
                         recipe,
                         task: format!("{}", building.task),
                         inventory: building.inventory.clone(),
                         crews: building.crews,
+                        population: building.population,
                         max_crews: building.type_.max_crews(),
                         ores: match building.type_ {
                             BuildingType::Furnace => Some(building.ore_accum),
                             _ => None,
                         },
+                        needs: building.needs,
+                        skills: building.skills.clone(),
+                        output_filter: building.output_filter,
+                        logistics: building.type_.is_storage().then(|| GetLogisticsInfoResult {
+                            setting: building.logistics.clone(),
+                            items: ruleset().items.clone(),
+                        }),
                     }
                 });
             construction = self.game.iter_construction().find_map(|c| {
@@ -76,42 +151,110 @@ impl AsteroidColonies {
                     return None;
                 }
                 Some(GetConstructionInfoResult {
+                    pos: c.pos,
                     type_: c.get_type(),
                     recipe: c.recipe.clone(),
                     ingredients: c.ingredients.countable().clone(),
+                    priority: c.priority,
+                    paused: c.paused,
                 })
             });
+            item_pile = self
+                .game
+                .item_pile_at([ix, iy])
+                .map(|pile| pile.inventory.clone());
             let tile = self.game.tiles()[[ix, iy]];
             if matches!(tile.state, TileState::Solid) {
                 ores = Some(tile.ores);
             }
+            if tile.conveyor.is_some() {
+                conveyor = Some(GetConveyorInfoResult::new(tile.conveyor_tier));
+            }
+            if let Conveyor::Splitter(from) = tile.conveyor {
+                splitter = Some(GetSplitterInfoResult {
+                    pos: [ix, iy],
+                    outputs: [
+                        Direction::Left,
+                        Direction::Up,
+                        Direction::Right,
+                        Direction::Down,
+                    ]
+                    .into_iter()
+                    .filter(|dir| *dir != from)
+                    .collect(),
+                    filter: tile.filter,
+                    items: ruleset().items.clone(),
+                });
+            }
         }
 
-        // We want to count power generation and consumption separately
-        let (energy, dischargeable, power_supply, power_demand) = self
-            .game
-            .iter_building()
-            .map(|b| (b.energy.unwrap_or(0), b.power_discharge(), b.power_gen()))
-            .fold((0, 0, 0, 0), |acc, (energy, discharge, gen)| {
+        let network = self
+            .cursor
+            .and_then(|cursor| self.game.power_network_at(cursor));
+
+        let (energy, dischargeable, power_supply, power_demand) =
+            if let Some((_, network)) = network {
                 (
-                    acc.0 + energy,
-                    acc.1 + discharge,
-                    acc.2 + gen.max(0).abs(),
-                    acc.3 + gen.min(0).abs(),
+                    network.energy,
+                    network.dischargeable,
+                    network.power_gen,
+                    network.power_demand,
                 )
-            });
+            } else {
+                // We want to count power generation and consumption separately
+                self.game
+                    .iter_building()
+                    .map(|b| (b.energy.unwrap_or(0), b.power_discharge(), b.power_gen()))
+                    .fold((0, 0, 0, 0), |acc, (energy, discharge, gen)| {
+                        (
+                            acc.0 + energy,
+                            acc.1 + discharge,
+                            acc.2 + gen.max(0).abs(),
+                            acc.3 + gen.min(0).abs(),
+                        )
+                    })
+            };
 
         let result = GetInfoResult {
             building,
             construction,
+            splitter,
+            conveyor,
             energy,
             power_demand,
             power_supply,
             power_capacity: dischargeable + power_supply,
             transports: self.game.num_transports(),
             ores,
+            item_pile,
+            power_network: network.map(|(id, _)| id),
         };
 
         serde_wasm_bindgen::to_value(&result).map_err(JsValue::from)
     }
+
+    /// Returns the conveyor tiers with their throughput and cost, from the basic one.
+    pub fn get_conveyor_tiers(&self) -> Result<JsValue, JsValue> {
+        let ruleset = ruleset();
+        let tiers: Vec<_> = std::iter::once(&ruleset.conveyor)
+            .chain(ruleset.conveyor_tiers.iter().map(|tier| &tier.cost))
+            .enumerate()
+            .map(|(tier, cost)| GetConveyorTierResult {
+                info: GetConveyorInfoResult::new(tier as u8),
+                ingredients: cost.ingredients.clone(),
+                time: cost.time,
+            })
+            .collect();
+        serde_wasm_bindgen::to_value(&tiers).map_err(JsValue::from)
+    }
+
+    /// Returns the stats of the power network that the building or the power grid at the given
+    /// tile belongs to, or null if there is none.
+    pub fn get_power_network(&self, x: i32, y: i32) -> Result<JsValue, JsValue> {
+        let network = self
+            .game
+            .power_network_at([x, y])
+            .map(|(_, network)| network);
+        serde_wasm_bindgen::to_value(&network).map_err(JsValue::from)
+    }
 }
@@ -1,4 +1,5 @@
 mod assets;
+mod blueprint;
 mod conveyor;
 mod info;
 mod render;
@@ -14,12 +15,20 @@ use wasm_bindgen::prelude::*;
 use web_sys::{js_sys, WebGlRenderingContext};
 
 use asteroid_colonies_logic::{
-    building::BuildingType, get_build_menu, AsteroidColoniesGame, Conveyor, ItemType, Pos,
-    TileState, HEIGHT, TILE_SIZE, WIDTH,
+    building::BuildingType, construction::ConstructionType, AsteroidColoniesGame,
+    CalculateBackImage, Command, Conveyor, ItemSet, ItemType, Job, Logistics, Pos, Ruleset,
+    StateHash, StatsWindow, StepMessage, TileState, HEIGHT, TILE_SIZE, WIDTH,
 };
 
 use crate::{assets::Assets, render::calculate_back_image};
 
+fn build_power_grid_command(ix: i32, iy: i32) -> Command {
+    Command::Build {
+        pos: [ix, iy],
+        ty: ConstructionType::PowerGrid,
+    }
+}
+
 #[macro_export]
 macro_rules! hash_map {
     { $($key:expr => $value:expr),+ } => {
@@ -103,18 +112,33 @@ pub struct AsteroidColonies {
     viewport: Viewport,
     debug_draw_chunks: bool,
     draw_ore_overlay: bool,
+    /// In lockstep mode, commands are applied when the server broadcasts them in a step,
+    /// not locally.
+    lockstep: bool,
 }
 
 #[wasm_bindgen]
 impl AsteroidColonies {
+    /// Create an instance of the game. `ruleset` is a JSON string of the ruleset, which should be
+    /// the same as the server's. If omitted, the default ruleset is used.
     #[wasm_bindgen(constructor)]
     pub fn new(
         image_assets: js_sys::Array,
         vp_width: f64,
         vp_height: f64,
+        ruleset: Option<String>,
     ) -> Result<AsteroidColonies, JsValue> {
+        let calculate_back_image = Some(Box::new(calculate_back_image) as CalculateBackImage);
+        let game = if let Some(ruleset) = ruleset {
+            AsteroidColoniesGame::new_with_ruleset(
+                calculate_back_image,
+                Ruleset::from_json(&ruleset)?,
+            )?
+        } else {
+            AsteroidColoniesGame::new(calculate_back_image)?
+        };
         Ok(Self {
-            game: AsteroidColoniesGame::new(Some(Box::new(calculate_back_image)))?,
+            game,
             cursor: None,
             move_cursor: None,
             move_item_cursor: None,
@@ -130,6 +154,7 @@ impl AsteroidColonies {
             },
             debug_draw_chunks: false,
             draw_ore_overlay: false,
+            lockstep: false,
         })
     }
 
@@ -166,20 +191,23 @@ impl AsteroidColonies {
         if ix < 0 || WIDTH as i32 <= ix || iy < 0 || HEIGHT as i32 <= iy {
             return Err(JsValue::from("Point outside tile"));
         }
-        let res = match com {
-            "excavate" => self.game.excavate(ix, iy),
-            "power" => self.game.build_power_grid(ix, iy),
+        let res = self.apply_local(|game| match com {
+            "excavate" => game.excavate(ix, iy, None),
+            "power" => game
+                .apply_command(&build_power_grid_command(ix, iy), None)
+                .map(|_| true),
             _ => Err(format!("Unknown command: {}", com)),
-        };
-        res.map(|r| JsValue::from(r)).map_err(|e| JsValue::from(e))
+        });
+        res.map(|r| JsValue::from(r))
     }
 
     pub fn excavate(&mut self, ix: i32, iy: i32) -> Result<bool, JsValue> {
-        self.game.excavate(ix, iy).map_err(JsValue::from)
+        self.apply_local(|game| game.excavate(ix, iy, None))
     }
 
     pub fn build_power_grid(&mut self, ix: i32, iy: i32) -> Result<bool, JsValue> {
-        self.game.build_power_grid(ix, iy).map_err(JsValue::from)
+        self.apply_command_local(build_power_grid_command(ix, iy))?;
+        Ok(true)
     }
 
     pub fn start_move_item(&mut self, x: i32, y: i32) -> bool {
@@ -199,9 +227,7 @@ impl AsteroidColonies {
             .move_item_cursor
             .ok_or_else(|| JsValue::from("Select a building to move items from first"))?;
         self.move_item_cursor = None;
-        self.game
-            .move_item(src, dpos, item)
-            .map_err(JsValue::from)?;
+        self.apply_local(|game| game.move_item(src, dpos, item))?;
         Ok(serde_wasm_bindgen::to_value(&src)?)
     }
 
@@ -223,7 +249,7 @@ impl AsteroidColonies {
         let dpos = self.transform_pos(dst_x, dst_y);
         if let Some(src) = self.move_cursor {
             self.move_cursor = None;
-            self.game.move_building(src, dpos).map_err(JsValue::from)?;
+            self.apply_local(|game| game.move_building(src, dpos))?;
             Ok(serde_wasm_bindgen::to_value(&src)?)
         } else {
             Err(JsValue::from("Select a building to move first"))
@@ -232,13 +258,15 @@ impl AsteroidColonies {
 
     pub fn build(&mut self, ix: i32, iy: i32, type_: JsValue) -> Result<(), JsValue> {
         let type_: BuildingType = serde_wasm_bindgen::from_value(type_)?;
-        self.game.build(ix, iy, type_).map_err(|e| JsValue::from(e))
+        self.apply_command_local(Command::Build {
+            pos: [ix, iy],
+            ty: ConstructionType::Building(type_),
+        })
     }
 
     pub fn cancel_build(&mut self) -> Result<(), JsValue> {
-        let [ix, iy] = self.cursor.ok_or("Cursor was not selected")?;
-        self.game.cancel_build(ix, iy);
-        Ok(())
+        let pos = self.cursor.ok_or("Cursor was not selected")?;
+        self.apply_command_local(Command::CancelBuild { pos })
     }
 
     pub fn find_building(&self, x: i32, y: i32) -> Result<bool, JsValue> {
@@ -264,26 +292,27 @@ impl AsteroidColonies {
             .into_iter()
             .map(serde_wasm_bindgen::from_value)
             .collect::<Result<Vec<_>, _>>()?;
-        self.game.build_plan(&constructions);
-        Ok(())
+        self.apply_command_local(Command::BuildPlan {
+            build_plan: constructions,
+        })
     }
 
     /// Puts a task to deconstruct a building. It is different from `cancel_build` in that it destroys already built ones.
     pub fn deconstruct(&mut self) -> Result<(), JsValue> {
-        let [ix, iy] = self.cursor.ok_or("Cursor was not selected")?;
-        Ok(self.game.deconstruct(ix, iy)?)
+        let pos = self.cursor.ok_or("Cursor was not selected")?;
+        self.apply_command_local(Command::Deconstruct { pos })
     }
 
     /// Puts a task to deconstruct a conveyor.
     pub fn deconstruct_conveyor(&mut self) -> Result<(), JsValue> {
-        let [ix, iy] = self.cursor.ok_or("Cursor was not selected")?;
-        Ok(self.game.deconstruct_conveyor(ix, iy)?)
+        let pos = self.cursor.ok_or("Cursor was not selected")?;
+        self.apply_command_local(Command::DeconstructConveyor { pos })
     }
 
     /// Puts a task to deconstruct a power grid.
     pub fn deconstruct_power_grid(&mut self) -> Result<(), JsValue> {
-        let [ix, iy] = self.cursor.ok_or("Cursor was not selected")?;
-        Ok(self.game.deconstruct_power_grid(ix, iy)?)
+        let pos = self.cursor.ok_or("Cursor was not selected")?;
+        self.apply_command_local(Command::DeconstructPowerGrid { pos })
     }
 
     pub fn get_recipes(&self, ix: i32, iy: i32) -> Result<Vec<JsValue>, JsValue> {
@@ -297,19 +326,98 @@ impl AsteroidColonies {
     }
 
     pub fn set_recipe(&mut self, ix: i32, iy: i32, name: &str) -> Result<(), JsValue> {
-        self.game
-            .set_recipe(ix, iy, Some(name))
-            .map_err(JsValue::from)
+        self.apply_command_local(Command::SetRecipe {
+            pos: [ix, iy],
+            name: Some(name.to_string()),
+        })
     }
 
     pub fn clear_recipe(&mut self, ix: i32, iy: i32) -> Result<(), JsValue> {
-        self.game.set_recipe(ix, iy, None).map_err(JsValue::from)
+        self.apply_command_local(Command::SetRecipe {
+            pos: [ix, iy],
+            name: None,
+        })
+    }
+
+    /// Set the items that the building pushes out, which is an array of items or `null` to push
+    /// out all of its products.
+    pub fn set_output_filter(&mut self, ix: i32, iy: i32, items: JsValue) -> Result<(), JsValue> {
+        let items: Option<ItemSet> = serde_wasm_bindgen::from_value(items)?;
+        self.apply_command_local(Command::SetOutputFilter {
+            pos: [ix, iy],
+            items,
+        })
+    }
+
+    /// Set the logistics of the storage, which is an object with `mode` ("Provider" or
+    /// "Requester") and `targets` of item counts, or `null` to clear it.
+    pub fn set_logistics(&mut self, ix: i32, iy: i32, logistics: JsValue) -> Result<(), JsValue> {
+        let logistics: Option<Logistics> = serde_wasm_bindgen::from_value(logistics)?;
+        self.apply_command_local(Command::SetLogistics {
+            pos: [ix, iy],
+            logistics,
+        })
+    }
+
+    pub fn is_laboratory_at(&self, x: i32, y: i32) -> bool {
+        self.game
+            .iter_building()
+            .any(|b| b.intersects([x, y]) && matches!(b.type_, BuildingType::Laboratory))
+    }
+
+    /// Get the research projects with their state in the colony.
+    pub fn get_research(&self) -> Result<Vec<JsValue>, JsValue> {
+        self.game
+            .research_status()
+            .iter()
+            .map(|s| serde_wasm_bindgen::to_value(s).map_err(JsValue::from))
+            .collect()
+    }
+
+    pub fn set_research(&mut self, name: Option<String>) -> Result<(), JsValue> {
+        self.apply_local(|game| game.set_research(name.as_deref()))
+    }
+
+    pub fn set_job_priority(
+        &mut self,
+        ix: i32,
+        iy: i32,
+        job: JsValue,
+        priority: u8,
+    ) -> Result<(), JsValue> {
+        let job: Job = serde_wasm_bindgen::from_value(job)?;
+        self.apply_local(|game| game.set_job_priority([ix, iy], job, priority))
+    }
+
+    pub fn set_crew_job_priority(
+        &mut self,
+        ix: i32,
+        iy: i32,
+        crew: usize,
+        job: JsValue,
+        priority: Option<u8>,
+    ) -> Result<(), JsValue> {
+        let job: Job = serde_wasm_bindgen::from_value(job)?;
+        self.apply_local(|game| game.set_crew_job_priority([ix, iy], crew, job, priority))
+    }
+
+    pub fn set_construction_priority(
+        &mut self,
+        ix: i32,
+        iy: i32,
+        priority: u8,
+    ) -> Result<(), JsValue> {
+        self.apply_local(|game| game.set_construction_priority([ix, iy], priority))
+    }
+
+    pub fn pause_construction(&mut self, ix: i32, iy: i32, paused: bool) -> Result<(), JsValue> {
+        self.apply_local(|game| game.pause_construction([ix, iy], paused))
     }
 
     pub fn cleanup_item(&mut self, x: f64, y: f64) -> Result<(), JsValue> {
         let ix = (x - self.viewport.offset[0]).div_euclid(TILE_SIZE) as i32;
         let iy = (y - self.viewport.offset[1]).div_euclid(TILE_SIZE) as i32;
-        self.game.cleanup_item([ix, iy]).map_err(JsValue::from)
+        self.apply_local(|game| game.cleanup_item([ix, iy], None))
     }
 
     pub fn get_inventory(&self) -> Result<JsValue, JsValue> {
@@ -322,6 +430,19 @@ impl AsteroidColonies {
         serde_wasm_bindgen::to_value(&inventory).map_err(JsValue::from)
     }
 
+    /// Get the buckets of a statistics window ("minute", "hour" or "day") for graphs.
+    pub fn get_production_stats(&self, window: JsValue) -> Result<JsValue, JsValue> {
+        let window: StatsWindow = serde_wasm_bindgen::from_value(window)?;
+        serde_wasm_bindgen::to_value(self.game.production_stats().window(window))
+            .map_err(JsValue::from)
+    }
+
+    pub fn get_production_total(&self, window: JsValue) -> Result<JsValue, JsValue> {
+        let window: StatsWindow = serde_wasm_bindgen::from_value(window)?;
+        serde_wasm_bindgen::to_value(&self.game.production_stats().total(window))
+            .map_err(JsValue::from)
+    }
+
     pub fn pan(&mut self, x: f64, y: f64) {
         self.viewport.offset[0] += x / self.viewport.scale;
         self.viewport.offset[1] += y / self.viewport.scale;
@@ -376,8 +497,9 @@ impl AsteroidColonies {
     }
 
     pub fn get_build_menu(&self) -> Result<Vec<JsValue>, JsValue> {
-        get_build_menu()
-            .iter()
+        self.game
+            .build_menu()
+            .into_iter()
             .map(|s| serde_wasm_bindgen::to_value(&s).map_err(JsValue::from))
             .collect()
     }
@@ -385,7 +507,7 @@ impl AsteroidColonies {
     pub fn deserialize(&mut self, data: &str) -> Result<(), JsValue> {
         self.game
             .deserialize(data.as_bytes())
-            .map_err(|e| JsValue::from(format!("{e}")))
+            .map_err(JsValue::from)
     }
 
     pub fn deserialize_bin(&mut self, data: &[u8]) -> Result<(), JsValue> {
@@ -398,14 +520,76 @@ impl AsteroidColonies {
         self.game.uniformify_tiles();
     }
 
-    pub fn serialize_chunks_digest(&self) -> Result<Vec<u8>, JsValue> {
+    /// Apply an incremental update from the server.
+    pub fn deserialize_diff(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        self.game.deserialize_diff(data).map_err(JsValue::from)
+    }
+
+    pub fn set_lockstep(&mut self, v: bool) {
+        self.lockstep = v;
+    }
+
+    /// Apply a step message broadcast by the server in lockstep mode. If it fails, the client
+    /// should ask the server to resync.
+    pub fn apply_step(&mut self, msg: &str) -> Result<(), JsValue> {
+        let msg = StepMessage::from_json(msg)?;
+        self.game.apply_step(&msg.payload).map_err(JsValue::from)
+    }
+
+    /// Hashes of the simulated state in JSON, which keeps the 64 bit values intact unlike
+    /// JavaScript numbers.
+    pub fn state_hash(&self) -> Result<String, JsValue> {
+        Ok(self.game.state_hash().to_json()?)
+    }
+
+    /// Compare the state with hashes from [`Self::state_hash`] of another instance, e.g. the
+    /// server, and return the names of the subsystems that diverged.
+    pub fn diverged_subsystems(&self, state_hash: &str) -> Result<Vec<JsValue>, JsValue> {
+        let state_hash = StateHash::from_json(state_hash)?;
+        Ok(self
+            .game
+            .state_hash()
+            .diverged(&state_hash)
+            .into_iter()
+            .map(JsValue::from)
+            .collect())
+    }
+
+    /// Acknowledge the last update to the server, so that it can send diffs against it.
+    pub fn serialize_sync_ack(&self) -> Result<Vec<u8>, JsValue> {
         self.game
-            .serialize_chunks_digest()
+            .serialize_sync_ack()
             .map_err(|e| JsValue::from(e.to_string()))
     }
+
+    /// Revert the last command made on this client.
+    pub fn undo(&mut self) -> Result<(), JsValue> {
+        self.apply_command_local(Command::Undo)
+    }
+
+    pub fn redo(&mut self) -> Result<(), JsValue> {
+        self.apply_command_local(Command::Redo)
+    }
 }
 
 impl AsteroidColonies {
+    /// Apply a command to the local game, unless in lockstep mode, in which case the server
+    /// applies it on the next step.
+    fn apply_local<T: Default, E: Into<JsValue>>(
+        &mut self,
+        f: impl FnOnce(&mut AsteroidColoniesGame) -> Result<T, E>,
+    ) -> Result<T, JsValue> {
+        if self.lockstep {
+            return Ok(T::default());
+        }
+        f(&mut self.game).map_err(Into::into)
+    }
+
+    /// Apply a command in the same way as the server, so that it is recorded in the history.
+    pub(crate) fn apply_command_local(&mut self, command: Command) -> Result<(), JsValue> {
+        self.apply_local(|game| game.apply_command(&command, None))
+    }
+
     fn transform_pos(&self, x: f64, y: f64) -> Pos {
         let vp = &self.viewport;
         let ix = (x / vp.scale - vp.offset[0]).div_euclid(TILE_SIZE) as i32;
@@ -6,7 +6,7 @@ use std::{
 use super::AsteroidColonies;
 
 use wasm_bindgen::prelude::*;
-use web_sys::CanvasRenderingContext2d;
+use web_sys::{CanvasRenderingContext2d, HtmlImageElement};
 
 use asteroid_colonies_logic::{
     building::BuildingType,
@@ -119,6 +119,30 @@ impl AsteroidColonies {
                             TILE_SIZE,
                         )?;
                 }
+                Conveyor::UndergroundIn(dir) | Conveyor::UndergroundOut(dir) => {
+                    let sx = match dir {
+                        Direction::Left => 0.,
+                        Direction::Up => TILE_SIZE,
+                        Direction::Right => 2. * TILE_SIZE,
+                        Direction::Down => 3. * TILE_SIZE,
+                    };
+                    let sy = match conv {
+                        Conveyor::UndergroundIn(_) => 0.,
+                        _ => TILE_SIZE,
+                    };
+                    context
+                        .draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                            &self.assets.img_underground,
+                            sx,
+                            sy,
+                            TILE_SIZE,
+                            TILE_SIZE,
+                            x,
+                            y,
+                            TILE_SIZE,
+                            TILE_SIZE,
+                        )?;
+                }
                 _ => {}
             };
             Ok(())
@@ -235,7 +259,7 @@ impl AsteroidColonies {
                         (0., 0.)
                     }
                 }
-                BuildingType::Assembler => {
+                BuildingType::Assembler | BuildingType::Laboratory => {
                     if !matches!(building.task, BuildingTask::None) {
                         ((time % 2 + 1) as f64 * TILE_SIZE * 2., 0.)
                     } else {
@@ -282,7 +306,9 @@ impl AsteroidColonies {
 
             let task_target = match building.task {
                 BuildingTask::Move(t, _) => Some((t, MOVE_TIME)),
-                BuildingTask::Assemble { t, max_t, .. } => Some((t, max_t)),
+                BuildingTask::Assemble { t, max_t, .. }
+                | BuildingTask::Research { t, max_t, .. }
+                | BuildingTask::Immigrate { t, max_t } => Some((t, max_t)),
                 _ => None,
             };
 
@@ -366,12 +392,12 @@ impl AsteroidColonies {
 
         for task in self.game.iter_global_task() {
             match &*task {
-                GlobalTask::Excavate(t, pos) => {
+                GlobalTask::Excavate(t, pos, _) => {
                     let x = pos[0] as f64 * TILE_SIZE + offset[0];
                     let y = pos[1] as f64 * TILE_SIZE + offset[1];
                     render_global_task_bar(context, [x, y], *t, LABOR_EXCAVATE_TIME);
                 }
-                GlobalTask::Cleanup(pos) => {
+                GlobalTask::Cleanup(pos, _) => {
                     let x = pos[0] as f64 * TILE_SIZE + offset[0];
                     let y = pos[1] as f64 * TILE_SIZE + offset[1];
                     context.draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
@@ -427,6 +453,22 @@ impl AsteroidColonies {
             render_conveyor(context, x, y, *conv)?;
         }
 
+        for pile in self.game.iter_item_pile() {
+            // Ores are shown as raw ores if there are no other items
+            let item = pile
+                .inventory
+                .keys()
+                .next()
+                .copied()
+                .unwrap_or(ItemType::RawOre);
+            let (img, sw, sh) = self.item_image(item);
+            let x = pile.pos[0] as f64 * TILE_SIZE + offset[0];
+            let y = pile.pos[1] as f64 * TILE_SIZE + offset[1];
+            context.draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                img, 0., 0., sw, sh, x, y, TILE_SIZE, TILE_SIZE,
+            )?;
+        }
+
         for t in self.game.iter_transport() {
             context.set_stroke_style(&JsValue::from("#ffff00"));
             context.set_line_width(2.);
@@ -438,29 +480,14 @@ impl AsteroidColonies {
                 );
             }
             context.stroke();
-            if let Some(pos) = t.path.last() {
+            if let Some(pos) = t.position(0.) {
                 let (img, sw, sh) = match t.payload {
-                    TransportPayload::Item(item, _) => match item {
-                        ItemType::RawOre => (&self.assets.img_raw_ore, 16., 16.),
-                        ItemType::IronIngot => (&self.assets.img_iron_ingot, 16., 16.),
-                        ItemType::CopperIngot => (&self.assets.img_copper_ingot, 16., 16.),
-                        ItemType::LithiumIngot => (&self.assets.img_lithium_ingot, 16., 16.),
-                        ItemType::Cilicate => (&self.assets.img_cilicate, 16., 16.),
-                        ItemType::Gear => (&self.assets.img_gear, 32., 32.),
-                        ItemType::Wire => (&self.assets.img_wire, 32., 32.),
-                        ItemType::Circuit => (&self.assets.img_circuit, 32., 32.),
-                        ItemType::Battery => (&self.assets.img_battery_item, 32., 32.),
-                        ItemType::PowerGridComponent => (&self.assets.img_power_grid, 32., 32.),
-                        ItemType::ConveyorComponent => (&self.assets.img_conveyor_item, 32., 32.),
-                        ItemType::AssemblerComponent => {
-                            (&self.assets.img_assembler_component, 32., 32.)
-                        }
-                    },
-                    TransportPayload::Ores(_) => (&self.assets.img_raw_ore, 16., 16.),
+                    TransportPayload::Item(item, _) => self.item_image(item),
+                    TransportPayload::Ores(_) => self.item_image(ItemType::RawOre),
                 };
                 let tile_offset = (TILE_SIZE as f64 - ITEM_SIZE as f64) / 2.;
-                let x = pos[0] as f64 * TILE_SIZE + tile_offset + offset[0];
-                let y = pos[1] as f64 * TILE_SIZE + tile_offset + offset[1];
+                let x = pos[0] * TILE_SIZE + tile_offset + offset[0];
+                let y = pos[1] * TILE_SIZE + tile_offset + offset[1];
                 context
                     .draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
                         img, 0., 0., sw, sh, x, y, ITEM_SIZE, ITEM_SIZE,
@@ -503,6 +530,28 @@ impl AsteroidColonies {
     }
 }
 
+impl AsteroidColonies {
+    /// The image of an item and the size of the source rectangle in it.
+    fn item_image(&self, item: ItemType) -> (&HtmlImageElement, f64, f64) {
+        match item {
+            ItemType::RawOre => (&self.assets.img_raw_ore, 16., 16.),
+            ItemType::IronIngot => (&self.assets.img_iron_ingot, 16., 16.),
+            ItemType::CopperIngot => (&self.assets.img_copper_ingot, 16., 16.),
+            ItemType::LithiumIngot => (&self.assets.img_lithium_ingot, 16., 16.),
+            ItemType::Cilicate => (&self.assets.img_cilicate, 16., 16.),
+            ItemType::Gear => (&self.assets.img_gear, 32., 32.),
+            ItemType::Wire => (&self.assets.img_wire, 32., 32.),
+            ItemType::Circuit => (&self.assets.img_circuit, 32., 32.),
+            ItemType::Battery => (&self.assets.img_battery_item, 32., 32.),
+            ItemType::PowerGridComponent => (&self.assets.img_power_grid, 32., 32.),
+            ItemType::ConveyorComponent => (&self.assets.img_conveyor_item, 32., 32.),
+            ItemType::AssemblerComponent => (&self.assets.img_assembler_component, 32., 32.),
+            ItemType::Oxygen => (&self.assets.img_oxygen, 32., 32.),
+            ItemType::Food => (&self.assets.img_food, 32., 32.),
+        }
+    }
+}
+
 fn render_global_task_bar(
     context: &CanvasRenderingContext2d,
     [x, y]: [f64; 2],